@@ -0,0 +1,43 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use smb1_tools::{NesPalette, Rom, GRAYSCALE_TILE_PALETTE};
+
+/// Parse 4 comma separated NES color indices in hex, such as `22,29,1a,0f`.
+fn parse_colors(colors: &str) -> Result<[u8; 4]> {
+    let indices = colors
+        .split(',')
+        .map(|color| u8::from_str_radix(color.trim(), 16))
+        .collect::<Result<Vec<u8>, _>>()?;
+    indices
+        .try_into()
+        .map_err(|_| anyhow!("expected 4 colors, not {:?}", colors))
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let rom_file = &args[1];
+    let out_dir = Path::new(&args[2]);
+
+    let palette = match args.get(3) {
+        Some(colors) => {
+            NesPalette::default().tile_palette(&parse_colors(colors)?)
+        }
+        None => GRAYSCALE_TILE_PALETTE,
+    };
+
+    let rom_data = fs::read(rom_file)?;
+    let rom = Rom::new(rom_data)?;
+
+    fs::create_dir_all(out_dir)?;
+    for (idx, table) in rom.pattern_tables()?.iter().enumerate() {
+        let path = out_dir.join(format!("pattern-table-{}.png", idx));
+        table.save_png(&path, &palette)?;
+        println!("wrote {}", path.display());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use std::env;
+use std::fs;
+
+use anyhow::{bail, Result};
+
+use smb1_tools::{Demo, Rom};
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let command = &args[1];
+
+    match command.as_str() {
+        "export-fm2" => export_fm2(&args[2], &args[3]),
+        "import-fm2" => import_fm2(&args[2], &args[3], &args[4]),
+        _ => bail!("unknown command: {}", command),
+    }
+}
+
+/// Write the attract mode demo of a rom as an FCEUX movie.
+fn export_fm2(rom_file: &str, out_file: &str) -> Result<()> {
+    let rom = Rom::new(fs::read(rom_file)?)?;
+
+    let demo = rom.demo()?;
+    fs::write(out_file, demo.to_fm2(&rom))?;
+    println!("wrote {}", out_file);
+
+    Ok(())
+}
+
+/// Replace the attract mode demo with an FCEUX movie's input.
+fn import_fm2(rom_file: &str, fm2_file: &str, out_file: &str) -> Result<()> {
+    let mut rom = Rom::new_unverified(fs::read(rom_file)?)?;
+
+    let demo = Demo::try_from_fm2(&fs::read_to_string(fm2_file)?)?;
+    rom.set_demo(&demo)?;
+    fs::write(out_file, &rom.rom_data)?;
+    println!("wrote {}", out_file);
+
+    Ok(())
+}
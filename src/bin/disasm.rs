@@ -0,0 +1,37 @@
+use std::env;
+use std::fs;
+
+use anyhow::{anyhow, Result};
+
+use smb1_tools::Rom;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let rom_file = &args[1];
+
+    let rom_data = fs::read(rom_file)?;
+    let rom = Rom::new_unverified(rom_data)?;
+    let disasm = rom.disassemble()?;
+
+    // with a label, only show where it's used
+    let Some(name) = args.get(2) else {
+        print!("{}", disasm);
+        return Ok(());
+    };
+    let addr = disasm
+        .label_addr(name)
+        .ok_or_else(|| anyhow!("unknown label: {}", name))?;
+    println!("{} = ${:04x}", name, addr);
+    for reference in disasm.references(addr) {
+        match disasm.instruction(reference) {
+            Some(ins) => println!(
+                "  ${:04x}  {}",
+                reference,
+                ins.format(|addr| disasm.label(addr).map(str::to_string))
+            ),
+            None => println!("  ${:04x}  .dw {}", reference, name),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,31 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use smb1_tools::Level;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let level_file = Path::new(&args[1]);
+
+    let text = fs::read_to_string(level_file)?;
+    let level = match level_file.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => Level::from_json(&text)?,
+        Some("ron") => Level::from_ron(&text)?,
+        Some("yaml" | "yml") => Level::from_yaml(&text)?,
+        _ => bail!("unknown level format: {}", level_file.display()),
+    };
+
+    println!("area data  = {}", hex(&level.area_data_to_bytes()));
+    println!("enemy data = {}", hex(&level.enemy_data_to_bytes()));
+
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let bytes: Vec<_> =
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    bytes.join(" ")
+}
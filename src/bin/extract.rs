@@ -1,9 +1,10 @@
 use std::env;
 use std::fs;
+use std::path::Path;
 
 use anyhow::Result;
 
-use smb1_tools::{Rom, RomLevel};
+use smb1_tools::{Area, LevelRenderer, Rom, RomLevel};
 
 fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
@@ -13,10 +14,65 @@ fn main() -> Result<()> {
     let rom_data = fs::read(rom_file)?;
     let rom = Rom::new(rom_data)?;
 
-    let level = rom.get_level(&RomLevel::from_name(world));
+    // draw every area of the game into a directory
+    if world == "--png" {
+        let out_dir = Path::new(args.get(3).map_or(".", String::as_str));
+        return extract_png(&rom, out_dir);
+    }
+
+    // accept either a level ("1-1") or any area of the game ("ground-5")
+    let area = match Area::from_name(world) {
+        Some(area) => area,
+        None => rom.level_area(&RomLevel::from_name(world)?)?,
+    };
+    let level = rom.get_area(&area)?;
+
+    // print the level as text instead of its data
+    match args.get(3).map(String::as_str) {
+        Some("--text") => {
+            print!("{}", level.display(area.area_type));
+            return Ok(());
+        }
+        Some("--ansi") => {
+            print!("{}", level.display(area.area_type).ansi(true));
+            return Ok(());
+        }
+        #[cfg(feature = "serde")]
+        Some("--json") => {
+            println!("{}", level.to_json()?);
+            return Ok(());
+        }
+        #[cfg(feature = "serde")]
+        Some("--ron") => {
+            println!("{}", level.to_ron()?);
+            return Ok(());
+        }
+        #[cfg(feature = "serde")]
+        Some("--yaml") => {
+            print!("{}", level.to_yaml()?);
+            return Ok(());
+        }
+        _ => {}
+    }
+
     println!("{:#?}", level);
     println!("objects.len = {}", level.object_data.objects.len());
     println!("enemies.len = {}", level.enemy_data.enemies.len());
 
     Ok(())
 }
+
+fn extract_png(rom: &Rom, out_dir: &Path) -> Result<()> {
+    fs::create_dir_all(out_dir)?;
+
+    for area in rom.areas()? {
+        let level = rom.get_area(&area)?;
+        let renderer = LevelRenderer::new(rom, area.area_type)?;
+        let image = renderer.render_level(&level);
+        let path = out_dir.join(format!("{}.png", area.name()));
+        image.save_with_format(&path, image::ImageFormat::Png)?;
+        println!("wrote {}", path.display());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+
+use smb1_tools::{Rom, SONGS};
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let command = &args[1];
+
+    match command.as_str() {
+        "export-midi" => export_midi(&args[2], &args[3]),
+        "import-midi" => import_midi(&args[2], &args[3], &args[4], &args[5]),
+        _ => bail!("unknown command: {}", command),
+    }
+}
+
+/// Write every song of a rom as a MIDI file.
+fn export_midi(rom_file: &str, out_dir: &str) -> Result<()> {
+    let out_dir = Path::new(out_dir);
+    let rom = Rom::new(fs::read(rom_file)?)?;
+
+    fs::create_dir_all(out_dir)?;
+    for song in rom.songs()? {
+        let path = out_dir.join(format!("{}.mid", song.name));
+        fs::write(&path, song.to_midi())?;
+        println!("wrote {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Replace a song of a rom with a MIDI file and write the modified rom.
+fn import_midi(
+    rom_file: &str,
+    name: &str,
+    midi_file: &str,
+    out_file: &str,
+) -> Result<()> {
+    let info = SONGS
+        .iter()
+        .find(|info| info.name == name)
+        .ok_or_else(|| anyhow!("unknown song: {}", name))?;
+    let mut rom = Rom::new_unverified(fs::read(rom_file)?)?;
+
+    let song = rom.song_from_midi(info, &fs::read(midi_file)?)?;
+    rom.set_song(info, &song)?;
+    fs::write(out_file, &rom.rom_data)?;
+    println!("wrote {}", out_file);
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use smb1_tools::{BpsPatch, IpsPatch, Rom};
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let command = &args[1];
+
+    match command.as_str() {
+        "create-patch" => create_patch(&args[2], &args[3], &args[4]),
+        "apply-patch" => apply_patch(&args[2], &args[3], &args[4]),
+        _ => bail!("unknown command: {}", command),
+    }
+}
+
+/// Whether a patch file is a BPS patch rather than an IPS patch.
+fn is_bps(patch_file: &str) -> bool {
+    Path::new(patch_file).extension().is_some_and(|ext| ext == "bps")
+}
+
+/// Write the patch turning a rom into a modified rom.
+fn create_patch(
+    rom_file: &str,
+    modified_file: &str,
+    patch_file: &str,
+) -> Result<()> {
+    let modified = Rom::new_unverified(fs::read(modified_file)?)?;
+
+    if is_bps(patch_file) {
+        // BPS patches check their own source, any rom can be patched
+        let rom = Rom::new_unverified(fs::read(rom_file)?)?;
+        let patch = rom.create_bps(&modified);
+        fs::write(patch_file, patch.to_bytes())?;
+        println!("wrote {} ({} actions)", patch_file, patch.actions.len());
+    } else {
+        let rom = Rom::new(fs::read(rom_file)?)?;
+        let patch = rom.create_ips(&modified)?;
+        fs::write(patch_file, patch.to_bytes())?;
+        println!("wrote {} ({} records)", patch_file, patch.records.len());
+    }
+
+    Ok(())
+}
+
+/// Apply a patch to a rom and write the modified rom.
+fn apply_patch(rom_file: &str, patch_file: &str, out_file: &str) -> Result<()> {
+    let patch_data = fs::read(patch_file)?;
+
+    let patched = if is_bps(patch_file) {
+        let rom = Rom::new_unverified(fs::read(rom_file)?)?;
+        rom.apply_bps(&BpsPatch::try_from_bytes(&patch_data)?)?
+    } else {
+        let rom = Rom::new(fs::read(rom_file)?)?;
+        rom.apply_ips(&IpsPatch::try_from_bytes(&patch_data)?)?
+    };
+    for error in patched.failed_checks() {
+        println!("note: {}", error);
+    }
+    fs::write(out_file, &patched.rom_data)?;
+    println!("wrote {}", out_file);
+
+    Ok(())
+}
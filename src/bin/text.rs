@@ -0,0 +1,33 @@
+use std::env;
+use std::fs;
+
+use anyhow::{anyhow, Result};
+
+use smb1_tools::{Rom, TEXT_CATALOG};
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let rom_file = &args[1];
+
+    let rom_data = fs::read(rom_file)?;
+    let mut rom = Rom::new_unverified(rom_data)?;
+
+    // with a name, replace that string and write the modified rom
+    let Some(name) = args.get(2) else {
+        for text in rom.text_catalog()? {
+            println!("{:<20} ${:04x}  {:?}", text.name, text.addr, text.text());
+        }
+        return Ok(());
+    };
+    let info = TEXT_CATALOG
+        .iter()
+        .find(|info| info.name == name)
+        .ok_or_else(|| anyhow!("unknown text: {}", name))?;
+    let out_file = &args[4];
+
+    rom.set_text(info, &args[3])?;
+    fs::write(out_file, &rom.rom_data)?;
+    println!("wrote {}", out_file);
+
+    Ok(())
+}
@@ -0,0 +1,21 @@
+use std::env;
+use std::fs;
+
+use anyhow::Result;
+
+use smb1_tools::Rom;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let rom_file = &args[1];
+    let out_file = &args[2];
+
+    let rom_data = fs::read(rom_file)?;
+    let rom = Rom::new_unverified(rom_data)?;
+
+    let image = rom.render_title_screen()?;
+    image.save_with_format(out_file, image::ImageFormat::Png)?;
+    println!("wrote {}", out_file);
+
+    Ok(())
+}
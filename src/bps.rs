@@ -0,0 +1,588 @@
+/*!
+ * BPS patches, which unlike IPS patches know the rom they apply to.
+ *
+ * A patch is `BPS1`, the source, target and metadata sizes, the metadata,
+ * a list of actions building the target and the CRC32 of the source, the
+ * target and the patch itself. Numbers are variable length, 7 bits a byte.
+ *
+ * Each action starts with a number holding its length and command:
+ *
+ *  L L L L L L ... C C
+ *  |_____________| |_|
+ *         |         |___ command
+ *         |_____________ length - 1
+ */
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+
+use crate::*;
+
+const BPS_MAGIC: &[u8] = b"BPS1";
+
+/// Bytes used by the three CRC32s at the end of a patch.
+const BPS_FOOTER_SIZE: usize = 12;
+
+/// Shortest match worth a copy instead of writing the bytes.
+const MIN_MATCH: usize = 4;
+
+/// Most earlier positions compared when looking for a match.
+const MAX_CANDIDATES: usize = 32;
+
+/// A single step building the target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BpsAction {
+    /// Copy `len` bytes from the same offset of the source.
+    SourceRead(usize),
+    /// Write bytes stored in the patch.
+    TargetRead(Vec<u8>),
+    /// Copy `len` bytes from anywhere in the source.
+    SourceCopy { offset: usize, len: usize },
+    /// Copy `len` bytes from earlier in the target, which may overlap.
+    TargetCopy { offset: usize, len: usize },
+}
+
+impl BpsAction {
+    /// Get the number of bytes this action writes.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::SourceRead(len) => *len,
+            Self::TargetRead(data) => data.len(),
+            Self::SourceCopy { len, .. } | Self::TargetCopy { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BpsPatch {
+    pub source_size: usize,
+    pub target_size: usize,
+    pub metadata: Vec<u8>,
+    pub actions: Vec<BpsAction>,
+    pub source_crc32: u32,
+    pub target_crc32: u32,
+}
+
+impl BpsPatch {
+    /**
+     * The known dump this patch was made for.
+     *
+     * Only found for patches made from a headerless copy of the dump, as
+     * `RomProfile` hashes cover PRG-ROM and CHR-ROM without a header.
+     */
+    pub fn source_profile(&self) -> Option<&'static RomProfile> {
+        if self.source_size != SMB1_PRG_SIZE + SMB1_CHR_SIZE {
+            return None;
+        }
+        ROM_PROFILES.iter().find(|profile| profile.crc32 == self.source_crc32)
+    }
+
+    /// Read a patch, checking its own CRC32.
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if !bytes.starts_with(BPS_MAGIC) {
+            return Err(Error::InvalidHeader {
+                what: "bps patch",
+                offset: 0,
+                reason: "missing BPS1 magic".into(),
+            });
+        }
+        Error::check_len(
+            "bps patch",
+            bytes,
+            BPS_MAGIC.len() + BPS_FOOTER_SIZE,
+        )?;
+
+        let footer = bytes.len() - BPS_FOOTER_SIZE;
+        let crc = |idx: usize| {
+            u32::from_le_bytes(bytes[idx..idx + 4].try_into().unwrap())
+        };
+        let (source_crc32, target_crc32) = (crc(footer), crc(footer + 4));
+        let patch_crc32 = crc(footer + 8);
+        let actual = crc32fast::hash(&bytes[..footer + 8]);
+        if actual != patch_crc32 {
+            return Err(Error::ChecksumMismatch {
+                what: "bps patch crc32",
+                offset: 0,
+                len: footer + 8,
+                expected: format!("{:08x}", patch_crc32),
+                actual: format!("{:08x}", actual),
+            });
+        }
+
+        let mut reader =
+            Reader { bytes: &bytes[..footer], idx: BPS_MAGIC.len() };
+        let source_size = reader.number()?;
+        let target_size = reader.number()?;
+        let metadata_size = reader.number()?;
+        let metadata = reader.bytes(metadata_size)?.to_vec();
+
+        let mut actions = vec![];
+        let (mut source_offset, mut target_offset) = (0, 0);
+        while reader.idx < footer {
+            let number = reader.number()?;
+            let len = (number >> 2) + 1;
+            let action = match number & 0b11 {
+                0 => BpsAction::SourceRead(len),
+                1 => BpsAction::TargetRead(reader.bytes(len)?.to_vec()),
+                2 => {
+                    source_offset = reader.relative(source_offset)?;
+                    let offset = source_offset;
+                    source_offset = source_offset.saturating_add(len);
+                    BpsAction::SourceCopy { offset, len }
+                }
+                _ => {
+                    target_offset = reader.relative(target_offset)?;
+                    let offset = target_offset;
+                    target_offset = target_offset.saturating_add(len);
+                    BpsAction::TargetCopy { offset, len }
+                }
+            };
+            actions.push(action);
+        }
+
+        Ok(Self {
+            source_size,
+            target_size,
+            metadata,
+            actions,
+            source_crc32,
+            target_crc32,
+        })
+    }
+
+    /// Panics on malformed patches, see `BpsPatch::try_from_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::try_from_bytes(bytes).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /**
+     * Encode the patch. Actions writing no bytes are left out, as the
+     * format stores lengths less one and has no way to write them.
+     */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = BPS_MAGIC.to_vec();
+        write_number(&mut bytes, self.source_size);
+        write_number(&mut bytes, self.target_size);
+        write_number(&mut bytes, self.metadata.len());
+        bytes.extend(&self.metadata);
+
+        let (mut source_offset, mut target_offset) = (0, 0);
+        for action in self.actions.iter().filter(|action| !action.is_empty()) {
+            let len = action.len();
+            let command = match action {
+                BpsAction::SourceRead(_) => 0,
+                BpsAction::TargetRead(_) => 1,
+                BpsAction::SourceCopy { .. } => 2,
+                BpsAction::TargetCopy { .. } => 3,
+            };
+            write_number(&mut bytes, (len - 1) << 2 | command);
+
+            match action {
+                BpsAction::SourceRead(_) => {}
+                BpsAction::TargetRead(data) => bytes.extend(data),
+                BpsAction::SourceCopy { offset, len } => {
+                    write_relative(&mut bytes, source_offset, *offset);
+                    source_offset = offset + len;
+                }
+                BpsAction::TargetCopy { offset, len } => {
+                    write_relative(&mut bytes, target_offset, *offset);
+                    target_offset = offset + len;
+                }
+            }
+        }
+
+        bytes.extend(self.source_crc32.to_le_bytes());
+        bytes.extend(self.target_crc32.to_le_bytes());
+        bytes.extend(crc32fast::hash(&bytes).to_le_bytes());
+        bytes
+    }
+
+    /**
+     * Find the actions that build `target` from `source`.
+     *
+     * Bytes left where they were are read from the source, anything else is
+     * copied from the longest match found in the source or the target built
+     * so far, or stored in the patch.
+     */
+    pub fn diff(source: &[u8], target: &[u8]) -> Self {
+        let source_index = MatchIndex::new(source);
+        let mut target_index = MatchIndex::default();
+
+        let mut actions = vec![];
+        let mut pending = vec![];
+        let mut idx = 0;
+        while idx < target.len() {
+            let rest = &target[idx..];
+            let same = match_len(source.get(idx..).unwrap_or(&[]), rest);
+            let mut best = (BpsAction::SourceRead(same), same);
+
+            for offset in source_index.candidates(rest) {
+                let len = match_len(&source[offset..], rest);
+                if len > best.1 {
+                    best = (BpsAction::SourceCopy { offset, len }, len);
+                }
+            }
+            for offset in target_index.candidates(rest) {
+                // copies may overlap the bytes they write
+                let len = match_len(&target[offset..], rest);
+                if len > best.1 {
+                    best = (BpsAction::TargetCopy { offset, len }, len);
+                }
+            }
+
+            let (action, len) = match best.1 >= MIN_MATCH {
+                true => best,
+                false => {
+                    pending.push(target[idx]);
+                    target_index.insert(target, idx);
+                    idx += 1;
+                    continue;
+                }
+            };
+            if !pending.is_empty() {
+                actions
+                    .push(BpsAction::TargetRead(std::mem::take(&mut pending)));
+            }
+            actions.push(action);
+            for written in idx..idx + len {
+                target_index.insert(target, written);
+            }
+            idx += len;
+        }
+        if !pending.is_empty() {
+            actions.push(BpsAction::TargetRead(pending));
+        }
+
+        Self {
+            source_size: source.len(),
+            target_size: target.len(),
+            metadata: vec![],
+            actions,
+            source_crc32: crc32fast::hash(source),
+            target_crc32: crc32fast::hash(target),
+        }
+    }
+
+    /// Build the target, refusing any source the patch wasn't made for.
+    pub fn apply(&self, source: &[u8]) -> Result<Vec<u8>, Error> {
+        let actual = crc32fast::hash(source);
+        if source.len() != self.source_size || actual != self.source_crc32 {
+            return Err(Error::ChecksumMismatch {
+                what: "bps source crc32",
+                offset: 0,
+                len: source.len(),
+                expected: format!("{:08x}", self.source_crc32),
+                actual: format!("{:08x}", actual),
+            });
+        }
+
+        let invalid = |action: &BpsAction| Error::InvalidValue {
+            what: "bps action",
+            value: format!("{:?}", action),
+        };
+        // grown as actions write, the sizes are only what the patch claims
+        let mut target = vec![];
+        for action in &self.actions {
+            let idx = target.len();
+            if action.len() > self.target_size - idx {
+                return Err(invalid(action));
+            }
+            let source_bytes = |offset: usize, len: usize| {
+                let bytes = offset
+                    .checked_add(len)
+                    .and_then(|end| source.get(offset..end));
+                bytes.ok_or_else(|| invalid(action))
+            };
+            match action {
+                BpsAction::SourceRead(len) => {
+                    target.extend(source_bytes(idx, *len)?)
+                }
+                BpsAction::TargetRead(data) => target.extend(data),
+                BpsAction::SourceCopy { offset, len } => {
+                    target.extend(source_bytes(*offset, *len)?)
+                }
+                BpsAction::TargetCopy { offset, len } => {
+                    if *offset >= idx {
+                        return Err(invalid(action));
+                    }
+                    // byte by byte, the copy can read what it just wrote
+                    for byte in *offset..offset + len {
+                        target.push(target[byte]);
+                    }
+                }
+            }
+        }
+
+        let actual = crc32fast::hash(&target);
+        if target.len() != self.target_size || actual != self.target_crc32 {
+            return Err(Error::ChecksumMismatch {
+                what: "bps target crc32",
+                offset: 0,
+                len: target.len(),
+                expected: format!("{:08x}", self.target_crc32),
+                actual: format!("{:08x}", actual),
+            });
+        }
+        Ok(target)
+    }
+}
+
+/// Reads the numbers and data of a patch.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    idx: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        Error::check_len("bps data", &self.bytes[self.idx..], len)
+            .map_err(|error| error.offset_by(self.idx))?;
+        self.idx += len;
+        Ok(&self.bytes[self.idx - len..self.idx])
+    }
+
+    fn number(&mut self) -> Result<usize, Error> {
+        let start = self.idx;
+        let too_large = || Error::InvalidHeader {
+            what: "bps number",
+            offset: start,
+            reason: "too large".into(),
+        };
+
+        let (mut number, mut shift) = (0usize, 1usize);
+        loop {
+            let byte = self.bytes(1)?[0] as usize;
+            number = (byte & 0x7f)
+                .checked_mul(shift)
+                .and_then(|value| number.checked_add(value))
+                .ok_or_else(too_large)?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_mul(0x80).ok_or_else(too_large)?;
+            number = number.checked_add(shift).ok_or_else(too_large)?;
+        }
+    }
+
+    /// Read an offset stored relative to `base`, the low bit is the sign.
+    fn relative(&mut self, base: usize) -> Result<usize, Error> {
+        let start = self.idx;
+        let number = self.number()?;
+        let distance = number >> 1;
+        let offset = match number & 1 {
+            0 => base.checked_add(distance),
+            _ => base.checked_sub(distance),
+        };
+        offset.ok_or(Error::InvalidHeader {
+            what: "bps offset",
+            offset: start,
+            reason: format!("{} away from {}", distance, base),
+        })
+    }
+}
+
+fn write_number(bytes: &mut Vec<u8>, mut number: usize) {
+    loop {
+        let low = (number & 0x7f) as u8;
+        number >>= 7;
+        if number == 0 {
+            bytes.push(0x80 | low);
+            return;
+        }
+        bytes.push(low);
+        number -= 1;
+    }
+}
+
+fn write_relative(bytes: &mut Vec<u8>, base: usize, offset: usize) {
+    match offset >= base {
+        true => write_number(bytes, (offset - base) << 1),
+        false => write_number(bytes, (base - offset) << 1 | 1),
+    }
+}
+
+/// Number of bytes `a` and `b` have in common at their start.
+fn match_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// Positions of every `MIN_MATCH` byte sequence, to find matches quickly.
+#[derive(Default)]
+struct MatchIndex {
+    positions: HashMap<[u8; MIN_MATCH], Vec<usize>>,
+}
+
+impl MatchIndex {
+    fn new(bytes: &[u8]) -> Self {
+        let mut index = Self::default();
+        for idx in 0..bytes.len() {
+            index.insert(bytes, idx);
+        }
+        index
+    }
+
+    fn insert(&mut self, bytes: &[u8], idx: usize) {
+        if let Some(key) = Self::key(&bytes[idx..]) {
+            self.positions.entry(key).or_default().push(idx);
+        }
+    }
+
+    /// The latest positions starting with the same bytes as `bytes`.
+    fn candidates(&self, bytes: &[u8]) -> impl Iterator<Item = usize> + '_ {
+        let positions = Self::key(bytes)
+            .and_then(|key| self.positions.get(&key))
+            .map_or(&[][..], Vec::as_slice);
+        positions.iter().rev().take(MAX_CANDIDATES).copied()
+    }
+
+    fn key(bytes: &[u8]) -> Option<[u8; MIN_MATCH]> {
+        bytes.get(..MIN_MATCH)?.try_into().ok()
+    }
+}
+
+impl Rom {
+    /// Create the patch turning this rom into `target`.
+    pub fn create_bps(&self, target: &Rom) -> BpsPatch {
+        BpsPatch::diff(&self.rom_data, &target.rom_data)
+    }
+
+    /**
+     * Apply a patch made for this rom.
+     *
+     * The patch checks this is the exact rom it was made from, then the
+     * patched rom goes through the same checks as `Rom::apply_ips`. A patch
+     * made for a headerless copy of the dump this rom was identified as
+     * applies too, whatever header this rom was loaded with.
+     */
+    pub fn apply_bps(&self, patch: &BpsPatch) -> Result<Rom> {
+        let expected = patch.source_profile();
+        let headerless;
+        let source = match self.profile() {
+            Some(profile) if expected == Some(profile) => {
+                headerless = [self.prg(), self.chr()].concat();
+                &headerless
+            }
+            _ => &self.rom_data,
+        };
+
+        let rom_data = patch.apply(source).with_context(|| {
+            let name =
+                self.profile().map_or("this rom", |profile| profile.name);
+            match expected {
+                Some(expected) => format!(
+                    "patch was made for {}, not {}",
+                    expected.name, name
+                ),
+                None => format!("patch was not made for {}", name),
+            }
+        })?;
+        Rom::new_patched(rom_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bps_numbers() {
+        for number in [0, 1, 0x7f, 0x80, 0x407f, 0x4080, 0x123456] {
+            let mut bytes = vec![];
+            write_number(&mut bytes, number);
+            let mut reader = Reader { bytes: &bytes, idx: 0 };
+            assert_eq!(reader.number().unwrap(), number);
+            assert_eq!(reader.idx, bytes.len());
+        }
+
+        let mut bytes = vec![];
+        write_number(&mut bytes, 0x80);
+        assert_eq!(bytes, [0x00, 0x80]);
+    }
+
+    #[test]
+    fn test_bps_diff() {
+        let source: Vec<u8> = (0..=255).cycle().take(0x400).collect();
+        let mut target = source.clone();
+        // changed bytes, a moved block and a repeated pattern
+        target[0x10..0x14].copy_from_slice(b"mari");
+        target.copy_within(0x200..0x240, 0x100);
+        target.extend(b"luigi".repeat(8));
+
+        let patch = BpsPatch::diff(&source, &target);
+        assert_eq!(patch.apply(&source).unwrap(), target);
+        assert!(patch
+            .actions
+            .contains(&BpsAction::TargetRead(b"mari".to_vec())));
+        assert!(patch
+            .actions
+            .iter()
+            .any(|action| matches!(action, BpsAction::TargetCopy { .. })));
+
+        let bytes = patch.to_bytes();
+        assert!(bytes.len() < 0x80);
+        assert_eq!(BpsPatch::from_bytes(&bytes), patch);
+
+        // empty actions can't be encoded, and change nothing
+        let mut padded = patch.clone();
+        padded.actions.insert(0, BpsAction::SourceRead(0));
+        padded.actions.push(BpsAction::TargetCopy { offset: 0, len: 0 });
+        assert_eq!(padded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_bps_checksums() {
+        let source = vec![0; 0x100];
+        let target = vec![1; 0x100];
+        let patch = BpsPatch::diff(&source, &target);
+
+        // the wrong source is refused
+        assert!(matches!(
+            patch.apply(&target),
+            Err(Error::ChecksumMismatch { what: "bps source crc32", .. })
+        ));
+
+        let mut bytes = patch.to_bytes();
+        *bytes.last_mut().unwrap() ^= 0xff;
+        assert!(matches!(
+            BpsPatch::try_from_bytes(&bytes),
+            Err(Error::ChecksumMismatch { what: "bps patch crc32", .. })
+        ));
+        assert!(BpsPatch::try_from_bytes(b"UPS1").is_err());
+    }
+
+    #[test]
+    fn test_bps_sizes() {
+        let source = vec![0; 0x100];
+        let mut patch = BpsPatch::diff(&source, &[1; 0x100]);
+
+        // a target size far past what the actions write is only checked
+        // once they have run, nothing is allocated for it up front
+        patch.target_size = usize::MAX;
+        assert!(matches!(
+            patch.apply(&source),
+            Err(Error::ChecksumMismatch { what: "bps target crc32", .. })
+        ));
+
+        // actions writing past the target size are refused
+        patch.target_size = 0x80;
+        assert!(matches!(
+            patch.apply(&source),
+            Err(Error::InvalidValue { what: "bps action", .. })
+        ));
+    }
+
+    #[test]
+    fn test_bps_source_profile() {
+        let mut patch = BpsPatch::diff(&[0; 0x10], &[1; 0x10]);
+        assert_eq!(patch.source_profile(), None);
+
+        patch.source_size = SMB1_PRG_SIZE + SMB1_CHR_SIZE;
+        patch.source_crc32 = ROM_PROFILES[0].crc32;
+        assert_eq!(patch.source_profile(), Some(&ROM_PROFILES[0]));
+    }
+}
@@ -0,0 +1,227 @@
+/*!
+ * Graphics stored in CHR-ROM.
+ *
+ * CHR-ROM holds two pattern tables of 256 tiles each. The game draws
+ * sprites from the first and the background from the second.
+ */
+
+use std::path::Path;
+
+use anyhow::{ensure, Result};
+use image::{Rgba, RgbaImage};
+
+use crate::*;
+
+/// Width and height of a tile in pixels.
+pub const TILE_PIXELS: usize = 8;
+
+/// Bytes used by a single 2bpp tile.
+pub const TILE_SIZE: usize = 16;
+
+/// Number of tiles in a pattern table.
+pub const PATTERN_TABLE_TILES: usize = 256;
+
+/// Bytes used by a pattern table.
+pub const PATTERN_TABLE_SIZE: usize = PATTERN_TABLE_TILES * TILE_SIZE;
+
+/// Tiles per row when a pattern table is laid out as a sheet.
+pub const SHEET_TILES_PER_ROW: usize = 16;
+
+/// Four shades of grey, darkest for color 3, to view tiles without a palette.
+pub const GRAYSCALE_TILE_PALETTE: [Rgba<u8>; 4] = [
+    Rgba([0xff, 0xff, 0xff, 0xff]),
+    Rgba([0xaa, 0xaa, 0xaa, 0xff]),
+    Rgba([0x55, 0x55, 0x55, 0xff]),
+    Rgba([0x00, 0x00, 0x00, 0xff]),
+];
+
+/**
+ * A single 8x8 tile with a color index (0-3) per pixel.
+ *
+ * Stored as two bit planes of 8 bytes, one byte per row with the leftmost
+ * pixel in the high bit. The first plane holds bit 0 of each color index
+ * and the second plane bit 1.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Tile {
+    pub pixels: [[u8; TILE_PIXELS]; TILE_PIXELS],
+}
+
+impl Tile {
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Error::check_len("tile", bytes, TILE_SIZE)?;
+
+        let mut pixels = [[0; TILE_PIXELS]; TILE_PIXELS];
+        for (y, row) in pixels.iter_mut().enumerate() {
+            let low = bytes[y];
+            let high = bytes[y + 8];
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let bit = 7 - x;
+                *pixel = (low >> bit) & 1 | ((high >> bit) & 1) << 1;
+            }
+        }
+
+        Ok(Self { pixels })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::try_from_bytes(bytes).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn to_bytes(&self) -> [u8; TILE_SIZE] {
+        let mut bytes = [0; TILE_SIZE];
+        for (y, row) in self.pixels.iter().enumerate() {
+            for (x, pixel) in row.iter().enumerate() {
+                let bit = 7 - x;
+                bytes[y] |= (pixel & 1) << bit;
+                bytes[y + 8] |= ((pixel >> 1) & 1) << bit;
+            }
+        }
+        bytes
+    }
+
+    /// Get the color index of a pixel.
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y][x]
+    }
+
+    /// Draw the tile into an image with its top left corner at `(x, y)`.
+    pub fn draw(
+        &self,
+        image: &mut RgbaImage,
+        x: u32,
+        y: u32,
+        palette: &[Rgba<u8>; 4],
+    ) {
+        for (py, row) in self.pixels.iter().enumerate() {
+            for (px, &pixel) in row.iter().enumerate() {
+                let (ix, iy) = (x + px as u32, y + py as u32);
+                if ix < image.width() && iy < image.height() {
+                    image.put_pixel(ix, iy, palette[pixel as usize & 0b11]);
+                }
+            }
+        }
+    }
+}
+
+/// 256 tiles, as the PPU sees them at $0000 or $1000.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternTable {
+    pub tiles: Vec<Tile>,
+}
+
+impl PatternTable {
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Error::check_len("pattern table", bytes, PATTERN_TABLE_SIZE)?;
+
+        let tiles = bytes[..PATTERN_TABLE_SIZE]
+            .chunks_exact(TILE_SIZE)
+            .map(Tile::from_bytes)
+            .collect();
+        Ok(Self { tiles })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::try_from_bytes(bytes).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.tiles.iter().flat_map(Tile::to_bytes).collect()
+    }
+
+    /// Lay the tiles out 16 to a row, tile 0 in the top left corner.
+    pub fn to_image(&self, palette: &[Rgba<u8>; 4]) -> RgbaImage {
+        let rows = self.tiles.len().div_ceil(SHEET_TILES_PER_ROW);
+        let mut image = RgbaImage::new(
+            (SHEET_TILES_PER_ROW * TILE_PIXELS) as u32,
+            (rows * TILE_PIXELS) as u32,
+        );
+        for (idx, tile) in self.tiles.iter().enumerate() {
+            let x = idx % SHEET_TILES_PER_ROW * TILE_PIXELS;
+            let y = idx / SHEET_TILES_PER_ROW * TILE_PIXELS;
+            tile.draw(&mut image, x as u32, y as u32, palette);
+        }
+        image
+    }
+
+    /// Save the tiles as a PNG sheet, see `PatternTable::to_image`.
+    pub fn save_png<P: AsRef<Path>>(
+        &self,
+        path: P,
+        palette: &[Rgba<u8>; 4],
+    ) -> Result<()> {
+        self.to_image(palette)
+            .save_with_format(path, image::ImageFormat::Png)?;
+        Ok(())
+    }
+}
+
+impl Rom {
+    /// Get one of the two pattern tables, 0 (sprites) or 1 (background).
+    pub fn pattern_table(&self, index: usize) -> Result<PatternTable> {
+        ensure!(index < 2, "no pattern table {}", index);
+        let start = index * PATTERN_TABLE_SIZE;
+        let bytes = self.chr().get(start..).unwrap_or_default();
+        let table = PatternTable::try_from_bytes(bytes).map_err(|error| {
+            error.offset_by(self.header.chr_offset() + start)
+        })?;
+        Ok(table)
+    }
+
+    /// Get both pattern tables, sprites first.
+    pub fn pattern_tables(&self) -> Result<[PatternTable; 2]> {
+        Ok([self.pattern_table(0)?, self.pattern_table(1)?])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_round_trip() {
+        // a diagonal in color 1, a column in color 2 and a corner in color 3
+        let bytes = [
+            0x81, 0x40, 0x20, 0x10, 0x08, 0x04, 0x02, 0x01, //
+            0x81, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+        ];
+        let tile = Tile::from_bytes(&bytes);
+        assert_eq!(tile.pixels[0], [3, 0, 0, 0, 0, 0, 0, 3]);
+        assert_eq!(tile.pixels[1], [0, 1, 0, 0, 0, 0, 0, 2]);
+        assert_eq!(tile.pixel(7, 7), 3);
+        assert_eq!(tile.pixel(6, 6), 1);
+        assert_eq!(tile.to_bytes(), bytes);
+
+        assert!(Tile::try_from_bytes(&bytes[..15]).is_err());
+    }
+
+    #[test]
+    fn test_pattern_table_image() {
+        let mut bytes = vec![0; PATTERN_TABLE_SIZE];
+        // tile 17 filled with color 3
+        bytes[17 * TILE_SIZE..18 * TILE_SIZE].fill(0xff);
+
+        let table = PatternTable::from_bytes(&bytes);
+        assert_eq!(table.tiles.len(), PATTERN_TABLE_TILES);
+        assert_eq!(table.to_bytes(), bytes);
+
+        let image = table.to_image(&GRAYSCALE_TILE_PALETTE);
+        assert_eq!(image.dimensions(), (128, 128));
+        assert_eq!(image.get_pixel(7, 7), &GRAYSCALE_TILE_PALETTE[0]);
+        assert_eq!(image.get_pixel(8, 8), &GRAYSCALE_TILE_PALETTE[3]);
+        assert_eq!(image.get_pixel(15, 15), &GRAYSCALE_TILE_PALETTE[3]);
+        assert_eq!(image.get_pixel(16, 8), &GRAYSCALE_TILE_PALETTE[0]);
+    }
+
+    #[test]
+    fn test_missing_pattern_table() {
+        // a rom without CHR-ROM, as if it used CHR-RAM
+        let mut rom_data = SMB1_INES_HEADER.to_vec();
+        rom_data[5] = 0;
+        rom_data.resize(INES_HEADER_SIZE + SMB1_PRG_SIZE, 0);
+        let rom = Rom::new_unverified(rom_data).unwrap();
+        assert!(rom.pattern_table(0).is_err());
+        assert!(rom.pattern_table(1).is_err());
+        assert!(rom.pattern_table(2).is_err());
+    }
+}
@@ -0,0 +1,542 @@
+/*!
+ * A headless 6502 core for running the game's own routines.
+ *
+ * There is no PPU or APU, only the 2KB of RAM and the cartridge, which is
+ * enough for the routines that only work on RAM, like the area parser.
+ */
+
+mod area_loader;
+
+pub use area_loader::*;
+
+use anyhow::{bail, Result};
+
+use crate::*;
+
+/// Size of the NES's internal RAM, mirrored up to $1FFF.
+pub const RAM_SIZE: usize = 0x800;
+
+/// Return address pushed by `Cpu::call`, reached by the routine's `rts`.
+const CALL_RETURN: u16 = 0x0000;
+
+/// Where `brk` and IRQs jump to.
+const IRQ_VECTOR: u16 = 0xfffe;
+
+/// Where execution starts after a reset.
+const RESET_VECTOR: u16 = 0xfffc;
+
+const FLAG_CARRY: u8 = 0x01;
+const FLAG_ZERO: u8 = 0x02;
+const FLAG_INTERRUPT: u8 = 0x04;
+const FLAG_DECIMAL: u8 = 0x08;
+const FLAG_BREAK: u8 = 0x10;
+const FLAG_UNUSED: u8 = 0x20;
+const FLAG_OVERFLOW: u8 = 0x40;
+const FLAG_NEGATIVE: u8 = 0x80;
+
+/// The memory seen by the CPU.
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, value: u8);
+}
+
+/**
+ * The memory map of an NROM (mapper 0) cartridge such as the game's.
+ *
+ * PPU, APU and controller registers read as 0 and ignore writes, as does
+ * everything else outside RAM and PRG-ROM.
+ */
+pub struct NromBus {
+    ram: [u8; RAM_SIZE],
+    prg: Vec<u8>,
+}
+
+impl NromBus {
+    /// Map a 16KB (mirrored) or 32KB PRG-ROM at $8000.
+    pub fn new(prg: &[u8]) -> Self {
+        Self { ram: [0; RAM_SIZE], prg: prg.to_vec() }
+    }
+
+    pub fn ram(&self) -> &[u8; RAM_SIZE] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u8; RAM_SIZE] {
+        &mut self.ram
+    }
+}
+
+impl Bus for NromBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1fff => self.ram[addr as usize % RAM_SIZE],
+            0x8000..=0xffff if !self.prg.is_empty() => {
+                self.prg[(addr as usize - 0x8000) % self.prg.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if addr < 0x2000 {
+            self.ram[addr as usize % RAM_SIZE] = value;
+        }
+    }
+}
+
+/**
+ * A 6502 as found in the NES, without decimal mode.
+ *
+ * Only official opcodes are supported and cycles aren't counted, the core
+ * is meant to run routines to completion rather than keep time.
+ */
+pub struct Cpu<B> {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub pc: u16,
+    pub status: u8,
+    pub bus: B,
+}
+
+impl<B: Bus> Cpu<B> {
+    /// Power on with the registers cleared, see `Cpu::reset`.
+    pub fn new(bus: B) -> Self {
+        Self {
+            a: 0,
+            x: 0,
+            y: 0,
+            sp: 0xfd,
+            pc: 0,
+            status: FLAG_INTERRUPT | FLAG_UNUSED,
+            bus,
+        }
+    }
+
+    /// Jump to the reset vector.
+    pub fn reset(&mut self) {
+        self.sp = 0xfd;
+        self.status |= FLAG_INTERRUPT;
+        self.pc = self.read_word(RESET_VECTOR);
+    }
+
+    /**
+     * Run the subroutine at `addr` until it returns, as if by `jsr`.
+     *
+     * Fails on an unofficial opcode, or if the routine is still running
+     * after `max_steps` instructions.
+     */
+    pub fn call(&mut self, addr: u16, max_steps: usize) -> Result<()> {
+        let sp = self.sp;
+        self.push_word(CALL_RETURN.wrapping_sub(1));
+        self.pc = addr;
+        for _ in 0..max_steps {
+            self.step()?;
+            if self.pc == CALL_RETURN && self.sp == sp {
+                return Ok(());
+            }
+        }
+        bail!(
+            "routine at ${:04x} still running after {} instructions",
+            addr,
+            max_steps
+        );
+    }
+
+    /// Run a single instruction and return it.
+    pub fn step(&mut self) -> Result<Instruction> {
+        let opcode = self.bus.read(self.pc);
+        let len = Opcode::new(opcode).map_or(1, |op| 1 + op.mode.operand_len());
+        let bytes: Vec<_> = (0..len as u16)
+            .map(|idx| self.bus.read(self.pc.wrapping_add(idx)))
+            .collect();
+        let ins = Instruction::try_from_bytes(self.pc, &bytes)?;
+        self.execute(&ins);
+        Ok(ins)
+    }
+
+    fn read_word(&mut self, addr: u16) -> u16 {
+        let low = self.bus.read(addr);
+        let high = self.bus.read(addr.wrapping_add(1));
+        u16::from_le_bytes([low, high])
+    }
+
+    /// Read a pointer from the zero page, wrapping around within it.
+    fn read_zero_page_word(&mut self, addr: u8) -> u16 {
+        let low = self.bus.read(addr as u16);
+        let high = self.bus.read(addr.wrapping_add(1) as u16);
+        u16::from_le_bytes([low, high])
+    }
+
+    fn push(&mut self, value: u8) {
+        self.bus.write(0x100 | self.sp as u16, value);
+        self.sp = self.sp.wrapping_sub(1);
+    }
+
+    fn pull(&mut self) -> u8 {
+        self.sp = self.sp.wrapping_add(1);
+        self.bus.read(0x100 | self.sp as u16)
+    }
+
+    fn push_word(&mut self, value: u16) {
+        let [low, high] = value.to_le_bytes();
+        self.push(high);
+        self.push(low);
+    }
+
+    fn pull_word(&mut self) -> u16 {
+        let low = self.pull();
+        let high = self.pull();
+        u16::from_le_bytes([low, high])
+    }
+
+    fn flag(&self, flag: u8) -> bool {
+        self.status & flag != 0
+    }
+
+    fn set_flag(&mut self, flag: u8, set: bool) {
+        if set {
+            self.status |= flag;
+        } else {
+            self.status &= !flag;
+        }
+    }
+
+    fn set_zero_negative(&mut self, value: u8) {
+        self.set_flag(FLAG_ZERO, value == 0);
+        self.set_flag(FLAG_NEGATIVE, value & 0x80 != 0);
+    }
+
+    /// Get the address an instruction reads or writes.
+    fn operand_addr(&mut self, ins: &Instruction) -> Option<u16> {
+        use AddressingMode::*;
+
+        let zero_page = ins.operand as u8;
+        let addr = match ins.mode {
+            Implied | Accumulator | Immediate => return None,
+            ZeroPage => zero_page as u16,
+            ZeroPageX => zero_page.wrapping_add(self.x) as u16,
+            ZeroPageY => zero_page.wrapping_add(self.y) as u16,
+            Absolute | Relative => ins.target().unwrap_or(ins.operand),
+            AbsoluteX => ins.operand.wrapping_add(self.x as u16),
+            AbsoluteY => ins.operand.wrapping_add(self.y as u16),
+            // the high byte never carries into the next page
+            Indirect => {
+                let high =
+                    (ins.operand & 0xff00) | ins.operand.wrapping_add(1) & 0xff;
+                let low = self.bus.read(ins.operand);
+                u16::from_le_bytes([low, self.bus.read(high)])
+            }
+            IndirectX => {
+                self.read_zero_page_word(zero_page.wrapping_add(self.x))
+            }
+            IndirectY => {
+                self.read_zero_page_word(zero_page).wrapping_add(self.y as u16)
+            }
+        };
+        Some(addr)
+    }
+
+    /// Read the value an instruction works on.
+    fn load(&mut self, ins: &Instruction) -> u8 {
+        match ins.mode {
+            AddressingMode::Immediate => ins.operand as u8,
+            AddressingMode::Accumulator => self.a,
+            _ => {
+                let addr = self.operand_addr(ins).unwrap_or_default();
+                self.bus.read(addr)
+            }
+        }
+    }
+
+    /// Read, change and write back the value an instruction works on.
+    fn modify(
+        &mut self,
+        ins: &Instruction,
+        change: impl Fn(&mut Self, u8) -> u8,
+    ) {
+        let value = self.load(ins);
+        let value = change(self, value);
+        match self.operand_addr(ins) {
+            Some(addr) => self.bus.write(addr, value),
+            None => self.a = value,
+        }
+        self.set_zero_negative(value);
+    }
+
+    fn store(&mut self, ins: &Instruction, value: u8) {
+        let addr = self.operand_addr(ins).unwrap_or_default();
+        self.bus.write(addr, value);
+    }
+
+    fn add(&mut self, value: u8) {
+        let carry = self.flag(FLAG_CARRY) as u16;
+        let sum = self.a as u16 + value as u16 + carry;
+        let result = sum as u8;
+        let overflow = (self.a ^ result) & (value ^ result) & 0x80 != 0;
+        self.set_flag(FLAG_CARRY, sum > 0xff);
+        self.set_flag(FLAG_OVERFLOW, overflow);
+        self.a = result;
+        self.set_zero_negative(result);
+    }
+
+    fn compare(&mut self, register: u8, value: u8) {
+        self.set_flag(FLAG_CARRY, register >= value);
+        self.set_zero_negative(register.wrapping_sub(value));
+    }
+
+    fn branch(&mut self, ins: &Instruction, taken: bool) {
+        if taken {
+            self.pc = ins.target().unwrap_or(self.pc);
+        }
+    }
+
+    fn execute(&mut self, ins: &Instruction) {
+        self.pc = ins.next_addr();
+        match ins.mnemonic {
+            "lda" => {
+                self.a = self.load(ins);
+                self.set_zero_negative(self.a);
+            }
+            "ldx" => {
+                self.x = self.load(ins);
+                self.set_zero_negative(self.x);
+            }
+            "ldy" => {
+                self.y = self.load(ins);
+                self.set_zero_negative(self.y);
+            }
+            "sta" => self.store(ins, self.a),
+            "stx" => self.store(ins, self.x),
+            "sty" => self.store(ins, self.y),
+            "tax" => {
+                self.x = self.a;
+                self.set_zero_negative(self.x);
+            }
+            "tay" => {
+                self.y = self.a;
+                self.set_zero_negative(self.y);
+            }
+            "txa" => {
+                self.a = self.x;
+                self.set_zero_negative(self.a);
+            }
+            "tya" => {
+                self.a = self.y;
+                self.set_zero_negative(self.a);
+            }
+            "tsx" => {
+                self.x = self.sp;
+                self.set_zero_negative(self.x);
+            }
+            "txs" => self.sp = self.x,
+            "pha" => self.push(self.a),
+            "php" => self.push(self.status | FLAG_BREAK | FLAG_UNUSED),
+            "pla" => {
+                self.a = self.pull();
+                self.set_zero_negative(self.a);
+            }
+            "plp" => {
+                self.status = self.pull() & !FLAG_BREAK | FLAG_UNUSED;
+            }
+            "and" => {
+                self.a &= self.load(ins);
+                self.set_zero_negative(self.a);
+            }
+            "ora" => {
+                self.a |= self.load(ins);
+                self.set_zero_negative(self.a);
+            }
+            "eor" => {
+                self.a ^= self.load(ins);
+                self.set_zero_negative(self.a);
+            }
+            "bit" => {
+                let value = self.load(ins);
+                self.set_flag(FLAG_ZERO, self.a & value == 0);
+                self.set_flag(FLAG_OVERFLOW, value & 0x40 != 0);
+                self.set_flag(FLAG_NEGATIVE, value & 0x80 != 0);
+            }
+            "adc" => {
+                let value = self.load(ins);
+                self.add(value);
+            }
+            // the 2A03 has no decimal mode, so subtracting is adding !value
+            "sbc" => {
+                let value = self.load(ins);
+                self.add(!value);
+            }
+            "cmp" => {
+                let value = self.load(ins);
+                self.compare(self.a, value);
+            }
+            "cpx" => {
+                let value = self.load(ins);
+                self.compare(self.x, value);
+            }
+            "cpy" => {
+                let value = self.load(ins);
+                self.compare(self.y, value);
+            }
+            "inc" => self.modify(ins, |_, value| value.wrapping_add(1)),
+            "dec" => self.modify(ins, |_, value| value.wrapping_sub(1)),
+            "inx" => {
+                self.x = self.x.wrapping_add(1);
+                self.set_zero_negative(self.x);
+            }
+            "iny" => {
+                self.y = self.y.wrapping_add(1);
+                self.set_zero_negative(self.y);
+            }
+            "dex" => {
+                self.x = self.x.wrapping_sub(1);
+                self.set_zero_negative(self.x);
+            }
+            "dey" => {
+                self.y = self.y.wrapping_sub(1);
+                self.set_zero_negative(self.y);
+            }
+            "asl" => self.modify(ins, |cpu, value| {
+                cpu.set_flag(FLAG_CARRY, value & 0x80 != 0);
+                value << 1
+            }),
+            "lsr" => self.modify(ins, |cpu, value| {
+                cpu.set_flag(FLAG_CARRY, value & 0x01 != 0);
+                value >> 1
+            }),
+            "rol" => self.modify(ins, |cpu, value| {
+                let carry = cpu.flag(FLAG_CARRY) as u8;
+                cpu.set_flag(FLAG_CARRY, value & 0x80 != 0);
+                value << 1 | carry
+            }),
+            "ror" => self.modify(ins, |cpu, value| {
+                let carry = cpu.flag(FLAG_CARRY) as u8;
+                cpu.set_flag(FLAG_CARRY, value & 0x01 != 0);
+                value >> 1 | carry << 7
+            }),
+            "jmp" => self.pc = self.operand_addr(ins).unwrap_or(self.pc),
+            "jsr" => {
+                self.push_word(self.pc.wrapping_sub(1));
+                self.pc = ins.operand;
+            }
+            "rts" => self.pc = self.pull_word().wrapping_add(1),
+            "rti" => {
+                self.status = self.pull() & !FLAG_BREAK | FLAG_UNUSED;
+                self.pc = self.pull_word();
+            }
+            "brk" => {
+                // brk skips a padding byte after it
+                self.push_word(self.pc.wrapping_add(1));
+                self.push(self.status | FLAG_BREAK | FLAG_UNUSED);
+                self.status |= FLAG_INTERRUPT;
+                self.pc = self.read_word(IRQ_VECTOR);
+            }
+            "bcc" => self.branch(ins, !self.flag(FLAG_CARRY)),
+            "bcs" => self.branch(ins, self.flag(FLAG_CARRY)),
+            "bne" => self.branch(ins, !self.flag(FLAG_ZERO)),
+            "beq" => self.branch(ins, self.flag(FLAG_ZERO)),
+            "bpl" => self.branch(ins, !self.flag(FLAG_NEGATIVE)),
+            "bmi" => self.branch(ins, self.flag(FLAG_NEGATIVE)),
+            "bvc" => self.branch(ins, !self.flag(FLAG_OVERFLOW)),
+            "bvs" => self.branch(ins, self.flag(FLAG_OVERFLOW)),
+            "clc" => self.set_flag(FLAG_CARRY, false),
+            "sec" => self.set_flag(FLAG_CARRY, true),
+            "cli" => self.set_flag(FLAG_INTERRUPT, false),
+            "sei" => self.set_flag(FLAG_INTERRUPT, true),
+            "cld" => self.set_flag(FLAG_DECIMAL, false),
+            "sed" => self.set_flag(FLAG_DECIMAL, true),
+            "clv" => self.set_flag(FLAG_OVERFLOW, false),
+            "nop" => {}
+            mnemonic => unreachable!("no 6502 instruction {}", mnemonic),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::zero_rom;
+
+    /// A cpu with `code` at $8000, mirrored at $c000 as on NROM-128.
+    fn new_cpu(code: &[u8]) -> Cpu<NromBus> {
+        let mut rom = zero_rom();
+        rom.write_cpu(0x8000, code).unwrap();
+        Cpu::new(NromBus::new(&rom.prg()[..PRG_BANK_SIZE]))
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        // lda #$50, adc #$50, sta $10, sec, sbc #$f0, rts
+        let mut cpu = new_cpu(&[
+            0xa9, 0x50, 0x69, 0x50, 0x85, 0x10, 0x38, 0xe9, 0xf0, 0x60,
+        ]);
+        cpu.call(0x8000, 10).unwrap();
+        assert_eq!(cpu.bus.ram()[0x10], 0xa0);
+        assert!(!cpu.flag(FLAG_OVERFLOW));
+        assert!(!cpu.flag(FLAG_CARRY));
+        assert_eq!(cpu.a, 0xb0);
+        assert!(cpu.flag(FLAG_NEGATIVE));
+
+        // overflow from two positives, then a carry out of $ff
+        let mut cpu = new_cpu(&[0xa9, 0x7f, 0x69, 0x01, 0x60]);
+        cpu.call(0x8000, 3).unwrap();
+        assert!(cpu.flag(FLAG_OVERFLOW));
+        let mut cpu = new_cpu(&[0xa9, 0xff, 0x69, 0x01, 0x60]);
+        cpu.call(0x8000, 3).unwrap();
+        assert!(cpu.flag(FLAG_CARRY) && cpu.flag(FLAG_ZERO));
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let mut cpu = new_cpu(&[
+            0xa2, 0x03, // ldx #$03
+            0x20, 0x0a, 0x80, // loop: jsr $800a
+            0xca, // dex
+            0xd0, 0xfa, // bne loop
+            0x60, // rts
+            0x00, // padding
+            0xe6, 0x20, // inc $20
+            0x60, // rts
+        ]);
+        cpu.call(0x8000, 100).unwrap();
+        assert_eq!(cpu.bus.ram()[0x20], 3);
+        assert_eq!(cpu.sp, 0xfd);
+
+        // an endless loop never returns
+        let mut cpu = new_cpu(&[0x4c, 0x00, 0x80]);
+        assert!(cpu.call(0x8000, 100).is_err());
+        // neither does an unofficial opcode
+        let mut cpu = new_cpu(&[0x02]);
+        assert!(cpu.call(0x8000, 100).is_err());
+    }
+
+    #[test]
+    fn test_addressing() {
+        let mut cpu = new_cpu(&[
+            0xa0, 0x02, // ldy #$02
+            0xa9, 0x07, // lda #$07
+            0x91, 0xff, // sta ($ff),y, pointer wraps to $00
+            0xa2, 0x01, // ldx #$01
+            0xb5, 0xff, // lda $ff,x, wraps to $00
+            0x6c, 0xff, 0x02, // jmp ($02ff), high byte from $0200
+        ]);
+        cpu.bus.ram_mut()[0xff] = 0x00;
+        cpu.bus.ram_mut()[0x00] = 0x03;
+        cpu.bus.ram_mut()[0x2ff] = 0x34;
+        cpu.bus.ram_mut()[0x200] = 0x12;
+        cpu.pc = 0x8000;
+        for _ in 0..6 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.bus.ram()[0x302], 0x07);
+        assert_eq!(cpu.a, 0x03);
+        assert_eq!(cpu.pc, 0x1234);
+
+        // ram is mirrored, prg-rom can't be written
+        cpu.bus.write(0x0800, 0x55);
+        assert_eq!(cpu.bus.read(0x1000), 0x55);
+        cpu.bus.write(0x8000, 0x55);
+        assert_eq!(cpu.bus.read(0xc000), 0xa0);
+    }
+}
@@ -0,0 +1,160 @@
+use anyhow::{anyhow, Result};
+
+use crate::*;
+
+/*
+ * RAM used by the area parser, named after the community disassembly.
+ */
+const AREA_POINTER: u16 = 0x0750;
+const AREA_OBJECT_LENGTH: u16 = 0x0730;
+const METATILE_BUFFER: u16 = 0x06a1;
+
+/// Most instructions a single call into the area parser may take.
+const MAX_ROUTINE_STEPS: usize = 1_000_000;
+
+/**
+ * Routines of the game that load an area, found in its disassembly.
+ *
+ * `GetAreaDataAddrs` has no table of its own, it's the routine reading
+ * `EnemyAddrHOffsets`. The other two come from `AREA_PARSER_TASKS`.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AreaLoaderRoutines {
+    pub get_area_data_addrs: u16,
+    pub area_parser_core: u16,
+    pub increment_column_pos: u16,
+}
+
+impl AreaLoaderRoutines {
+    pub fn find(disasm: &Disassembly) -> Result<Self> {
+        let label = |name| {
+            disasm
+                .label_addr(name)
+                .ok_or_else(|| anyhow!("no {} in the disassembly", name))
+        };
+        let reader = disasm
+            .references(ENEMY_ADDR_H_OFFSETS)
+            .into_iter()
+            .find(|&addr| disasm.instruction(addr).is_some());
+        let get_area_data_addrs = reader
+            .and_then(|addr| disasm.subroutine_start(addr))
+            .ok_or_else(|| anyhow!("no GetAreaDataAddrs in the disassembly"))?;
+
+        Ok(Self {
+            get_area_data_addrs,
+            area_parser_core: label("AreaParserCore")?,
+            increment_column_pos: label("IncrementColumnPos")?,
+        })
+    }
+}
+
+impl Rom {
+    /**
+     * Render an area by running the game's own area parser.
+     *
+     * The area is set up the way `InitializeArea` does before calling
+     * `GetAreaDataAddrs`, then `AreaParserCore` fills the metatile buffer
+     * one column at a time. This is slow, but shows exactly what the game
+     * draws, so it can check `LevelGrid` and edited levels.
+     */
+    pub fn run_area_loader(
+        &self,
+        area: &Area,
+        width: usize,
+    ) -> Result<LevelGrid> {
+        let routines = AreaLoaderRoutines::find(&self.disassemble()?)?;
+
+        let mut cpu = Cpu::new(NromBus::new(self.prg()));
+        cpu.bus.write(AREA_POINTER, area.pointer());
+        for offset in 0..3 {
+            cpu.bus.write(AREA_OBJECT_LENGTH + offset, 0xff);
+        }
+        cpu.call(routines.get_area_data_addrs, MAX_ROUTINE_STEPS)?;
+
+        let mut columns = vec![];
+        for _ in 0..width {
+            cpu.call(routines.area_parser_core, MAX_ROUTINE_STEPS)?;
+            let mut column = [BLANK_METATILE; GRID_ROWS];
+            for (row, metatile) in column.iter_mut().enumerate() {
+                *metatile = cpu.bus.read(METATILE_BUFFER + row as u16);
+            }
+            columns.push(column);
+            cpu.call(routines.increment_column_pos, MAX_ROUTINE_STEPS)?;
+        }
+        Ok(LevelGrid::from_columns(columns))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::zero_rom;
+
+    #[test]
+    fn test_area_loader() {
+        let mut rom = zero_rom();
+        let mut write =
+            |addr: u16, bytes: &[u8]| rom.write_cpu(addr, bytes).unwrap();
+        write(
+            0xc000,
+            &[
+                0x20, 0x10, 0xc0, // jsr GetAreaDataAddrs
+                0x20, 0x20, 0xc0, // jsr AreaParserTasks
+                0x4c, 0x00, 0xc0, // jmp Start
+            ],
+        );
+        write(
+            0xc010,
+            &[
+                0xad, 0x50, 0x07, // lda AreaPointer
+                0x8d, 0x00, 0x07, // sta $0700
+                0xa0, 0x00, // ldy #$00
+                0xb9, 0xe0, 0x9c, // lda EnemyAddrHOffsets,y
+                0x60, // rts
+            ],
+        );
+        write(0xc020, &[0x20, 0x90, 0xc0]);
+        let tasks: [u16; 8] =
+            [0xc100, 0xc110, 0xc110, 0xc120, 0xc100, 0xc110, 0xc110, 0xc120];
+        for (idx, task) in tasks.iter().enumerate() {
+            write(0xc023 + 2 * idx as u16, &task.to_le_bytes());
+        }
+        write(0xc090, &JUMP_ENGINE_CODE);
+        // increment the column, render nothing, then put the column and
+        // area pointer into the metatile buffer
+        write(0xc100, &[0xee, 0x26, 0x07, 0x60]);
+        write(0xc110, &[0x60]);
+        write(
+            0xc120,
+            &[
+                0xad, 0x26, 0x07, // lda CurrentColumnPos
+                0x8d, 0xa1, 0x06, // sta MetatileBuffer
+                0xad, 0x00, 0x07, // lda $0700
+                0x8d, 0xad, 0x06, // sta MetatileBuffer+12
+                0x60, // rts
+            ],
+        );
+        write(VECTORS_ADDR, &[0x10, 0xc1, 0x00, 0xc0, 0x00, 0xc0]);
+
+        let routines = AreaLoaderRoutines::find(&rom.disassemble().unwrap());
+        let disasm = rom.disassemble().unwrap();
+        assert_eq!(disasm.label(0xc020), Some("AreaParserTasks"));
+        assert_eq!(disasm.label(0xc023), Some("AreaParserTaskTable"));
+        assert_eq!(
+            routines.unwrap(),
+            AreaLoaderRoutines {
+                get_area_data_addrs: 0xc010,
+                area_parser_core: 0xc120,
+                increment_column_pos: 0xc100,
+            }
+        );
+
+        let area = Area::new(AreaType::Ground, 5);
+        let grid = rom.run_area_loader(&area, 3).unwrap();
+        assert_eq!(grid.width(), 3);
+        for x in 0..3 {
+            assert_eq!(grid.get(x, 0), Some(x as u8));
+            assert_eq!(grid.get(x, GRID_ROWS - 1), Some(area.pointer()));
+        }
+    }
+}
@@ -0,0 +1,281 @@
+/*!
+ * The attract mode demo, played on the title screen.
+ *
+ * `DemoEngine` plays back two tables: the buttons pressed for each action
+ * and how many frames each is held for. A timing of 0 ends the demo, so
+ * there's one more timing than there are actions.
+ */
+
+mod fm2;
+
+use anyhow::{anyhow, ensure, Result};
+
+use crate::util::find_pattern;
+use crate::*;
+
+pub const BUTTON_A: u8 = 0b10000000;
+pub const BUTTON_B: u8 = 0b01000000;
+pub const BUTTON_SELECT: u8 = 0b00100000;
+pub const BUTTON_START: u8 = 0b00010000;
+pub const BUTTON_UP: u8 = 0b00001000;
+pub const BUTTON_DOWN: u8 = 0b00000100;
+pub const BUTTON_LEFT: u8 = 0b00000010;
+pub const BUTTON_RIGHT: u8 = 0b00000001;
+
+/**
+ * `DemoEngine`, reading the tables from one byte before each (as the
+ * action number is incremented first):
+ *
+ * ```text
+ * ldx DemoAction
+ * lda DemoActionTimer
+ * bne DoAction
+ * inx
+ * inc DemoAction
+ * sec
+ * lda DemoTimingData-1,x
+ * sta DemoActionTimer
+ * beq DemoOver
+ * DoAction:
+ * lda DemoActionData-1,x
+ * sta SavedJoypad1Bits
+ * ```
+ */
+const DEMO_ENGINE_CODE: [Option<u8>; 27] = [
+    Some(0xae),
+    None,
+    None,
+    Some(0xad),
+    None,
+    None,
+    Some(0xd0),
+    None,
+    Some(0xe8),
+    Some(0xee),
+    None,
+    None,
+    Some(0x38),
+    Some(0xbd),
+    None,
+    None,
+    Some(0x8d),
+    None,
+    None,
+    Some(0xf0),
+    None,
+    Some(0xbd),
+    None,
+    None,
+    Some(0x8d),
+    None,
+    None,
+];
+
+/// Buttons held for a number of frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DemoAction {
+    /// Buttons in the order the game reads them, see `BUTTON_A` on.
+    pub buttons: u8,
+    /// Frames held, from 1 to 255.
+    pub frames: u8,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Demo {
+    pub actions: Vec<DemoAction>,
+}
+
+impl Demo {
+    /// Decode the action and timing tables, up to the 0 timing.
+    pub fn try_from_bytes(
+        actions: &[u8],
+        timings: &[u8],
+    ) -> Result<Self, Error> {
+        let len = timings.iter().position(|&frames| frames == 0).ok_or(
+            Error::MissingTerminator {
+                what: "demo timings",
+                offset: 0,
+                terminator: 0x00,
+            },
+        )?;
+        Error::check_len("demo actions", actions, len)?;
+
+        let actions = actions
+            .iter()
+            .zip(&timings[..len])
+            .map(|(&buttons, &frames)| DemoAction { buttons, frames })
+            .collect();
+        Ok(Self { actions })
+    }
+
+    pub fn from_bytes(actions: &[u8], timings: &[u8]) -> Self {
+        Self::try_from_bytes(actions, timings)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Encode the action and timing tables, with the 0 timing.
+    pub fn to_bytes(&self) -> (Vec<u8>, Vec<u8>) {
+        let actions = self.actions.iter().map(|action| action.buttons);
+        let timings = self.actions.iter().map(|action| action.frames);
+        (actions.collect(), timings.chain([0x00]).collect())
+    }
+
+    /// Get the buttons held on each frame of the demo.
+    pub fn inputs(&self) -> Vec<u8> {
+        self.actions
+            .iter()
+            .flat_map(|action| vec![action.buttons; action.frames as usize])
+            .collect()
+    }
+
+    /**
+     * Create a demo holding the buttons of each frame, the reverse of
+     * `inputs`. Inputs held for more than 255 frames take several actions.
+     */
+    pub fn from_inputs(inputs: &[u8]) -> Self {
+        let mut actions: Vec<DemoAction> = vec![];
+        for &buttons in inputs {
+            match actions.last_mut() {
+                Some(action)
+                    if action.buttons == buttons && action.frames < 0xff =>
+                {
+                    action.frames += 1
+                }
+                _ => actions.push(DemoAction { buttons, frames: 1 }),
+            }
+        }
+        Self { actions }
+    }
+}
+
+/// Where the demo's tables are, see `Rom::demo_tables`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DemoTables {
+    /// `DemoActionData`, the buttons of each action.
+    pub actions: u16,
+    /// `DemoTimingData`, directly after the actions.
+    pub timings: u16,
+}
+
+impl DemoTables {
+    /// Most actions the tables have space for.
+    pub fn capacity(&self) -> usize {
+        self.timings.wrapping_sub(self.actions) as usize
+    }
+}
+
+impl Rom {
+    /// Find the demo's tables by the code reading them.
+    pub fn demo_tables(&self) -> Result<DemoTables> {
+        let prg = self.prg();
+        let operand = |pos: usize| {
+            u16::from_le_bytes([prg[pos + 1], prg[pos + 2]]).wrapping_add(1)
+        };
+        find_pattern(prg, &DEMO_ENGINE_CODE)
+            .map(|pos| DemoTables {
+                actions: operand(pos + 21),
+                timings: operand(pos + 13),
+            })
+            .find(|tables| (1..0x100).contains(&tables.capacity()))
+            .ok_or_else(|| anyhow!("no DemoEngine found in prg-rom"))
+    }
+
+    /// Decode the attract mode demo.
+    pub fn demo(&self) -> Result<Demo> {
+        let tables = self.demo_tables()?;
+        let timings = self.cpu_slice(tables.timings)?;
+        let timings = &timings[..timings.len().min(tables.capacity() + 1)];
+        let offset = self.cpu_to_offset(tables.timings)?;
+        Ok(Demo::try_from_bytes(self.cpu_slice(tables.actions)?, timings)
+            .map_err(|error| error.offset_by(offset))?)
+    }
+
+    /**
+     * Replace the attract mode demo.
+     *
+     * It can't have more actions than fit before the timing table, or any
+     * action held for 0 frames (which would end it there).
+     */
+    pub fn set_demo(&mut self, demo: &Demo) -> Result<()> {
+        let tables = self.demo_tables()?;
+        ensure!(
+            demo.actions.len() <= tables.capacity(),
+            "demo has {} actions, only {} fit",
+            demo.actions.len(),
+            tables.capacity()
+        );
+        ensure!(
+            demo.actions.iter().all(|action| action.frames > 0),
+            "demo actions must be held for at least 1 frame"
+        );
+
+        let (mut actions, mut timings) = demo.to_bytes();
+        actions.resize(tables.capacity(), 0x00);
+        timings.resize(tables.capacity() + 1, 0x00);
+        self.write_cpu(tables.actions, &actions)?;
+        self.write_cpu(tables.timings, &timings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::zero_rom;
+
+    const ACTIONS: [u8; 3] = [BUTTON_RIGHT, BUTTON_A | BUTTON_RIGHT, 0x00];
+    const TIMINGS: [u8; 4] = [0x03, 0x02, 0xff, 0x00];
+
+    /// A rom with `DemoEngine` and its tables at $8000.
+    fn demo_rom() -> Rom {
+        let mut rom = zero_rom();
+        rom.write_cpu(0x8000, &ACTIONS).unwrap();
+        rom.write_cpu(0x8003, &TIMINGS).unwrap();
+        let code = [
+            0xae, 0x17, 0x07, 0xad, 0x18, 0x07, 0xd0, 0x09, 0xe8, 0xee, 0x17,
+            0x07, 0x38, 0xbd, 0x02, 0x80, 0x8d, 0x18, 0x07, 0xf0, 0x0a, 0xbd,
+            0xff, 0x7f, 0x8d, 0xfc, 0x06,
+        ];
+        rom.write_cpu(0x8010, &code).unwrap();
+        rom
+    }
+
+    #[test]
+    fn test_demo() {
+        let demo = Demo::from_bytes(&ACTIONS, &TIMINGS);
+        assert_eq!(demo.actions.len(), 3);
+        assert_eq!(demo.to_bytes(), (ACTIONS.to_vec(), TIMINGS.to_vec()));
+
+        let inputs = demo.inputs();
+        assert_eq!(inputs.len(), 3 + 2 + 255);
+        assert_eq!(inputs[3], BUTTON_A | BUTTON_RIGHT);
+
+        // runs longer than a timing can hold are split
+        let mut inputs = inputs;
+        inputs.push(0x00);
+        let longer = Demo::from_inputs(&inputs);
+        assert_eq!(longer.actions.len(), 4);
+        assert_eq!(longer.actions[3], DemoAction { buttons: 0, frames: 1 });
+        assert_eq!(longer.inputs(), inputs);
+
+        assert!(Demo::try_from_bytes(&ACTIONS, &TIMINGS[..3]).is_err());
+        assert!(Demo::try_from_bytes(&ACTIONS[..2], &TIMINGS).is_err());
+    }
+
+    #[test]
+    fn test_set_demo() {
+        let mut rom = demo_rom();
+        let tables = rom.demo_tables().unwrap();
+        assert_eq!(tables, DemoTables { actions: 0x8000, timings: 0x8003 });
+        assert_eq!(rom.demo().unwrap(), Demo::from_bytes(&ACTIONS, &TIMINGS));
+
+        let demo = Demo::from_inputs(&[BUTTON_B; 10]);
+        rom.set_demo(&demo).unwrap();
+        assert_eq!(rom.demo().unwrap(), demo);
+        assert_eq!(&rom.prg()[..7], [BUTTON_B, 0, 0, 10, 0, 0, 0]);
+
+        let demo = Demo::from_inputs(&[0x00, 0x01, 0x02, 0x03]);
+        assert!(rom.set_demo(&demo).is_err());
+        let demo = Demo { actions: vec![DemoAction { buttons: 0, frames: 0 }] };
+        assert!(rom.set_demo(&demo).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+/*!
+ * FCEUX movies (.fm2), a text header followed by a line of input for each
+ * frame. Only the first controller is used.
+ */
+
+use crate::*;
+
+/// Buttons of an input line, from `BUTTON_RIGHT` up to `BUTTON_A`.
+const FM2_BUTTONS: [char; 8] = ['R', 'L', 'D', 'U', 'T', 'S', 'B', 'A'];
+
+const BASE64_CHARS: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let mut group = [0; 3];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                let value = (bits >> (18 - idx * 6)) & 0b111111;
+                text.push(BASE64_CHARS[value as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+impl Demo {
+    /**
+     * Write the demo as an FCEUX movie of the rom, one frame per line.
+     *
+     * The movie starts on the demo's first frame, so it only plays back
+     * the same from a savestate of the title screen, not from power on.
+     */
+    pub fn to_fm2(&self, rom: &Rom) -> String {
+        let name = rom.profile().map_or("smb1", |profile| profile.name);
+        let checksum = md5::compute([rom.prg(), rom.chr()].concat());
+        let id = format!("{:X}", md5::compute(self.inputs()));
+
+        let mut text = String::new();
+        text.push_str("version 3\n");
+        text.push_str("emuVersion 20604\n");
+        text.push_str("rerecordCount 0\n");
+        text.push_str("palFlag 0\n");
+        text.push_str(&format!("romFilename {}\n", name));
+        text.push_str(&format!("romChecksum base64:{}\n", base64(&checksum.0)));
+        text.push_str(&format!(
+            "guid {}-{}-{}-{}-{}\n",
+            &id[..8],
+            &id[8..12],
+            &id[12..16],
+            &id[16..20],
+            &id[20..]
+        ));
+        text.push_str("fourscore 0\n");
+        text.push_str("microphone 0\n");
+        text.push_str("port0 1\n");
+        text.push_str("port1 0\n");
+        text.push_str("port2 0\n");
+        text.push_str("FDS 0\n");
+        text.push_str("NewPPU 0\n");
+
+        for buttons in self.inputs() {
+            let pressed: String = FM2_BUTTONS
+                .iter()
+                .enumerate()
+                .map(|(bit, &button)| match buttons >> bit & 1 {
+                    1 => button,
+                    _ => '.',
+                })
+                .collect();
+            text.push_str(&format!("|0|{}|||\n", pressed));
+        }
+        text
+    }
+
+    /**
+     * Read the first controller's input from an FCEUX movie, see
+     * `from_inputs`. Header lines are skipped.
+     */
+    pub fn try_from_fm2(text: &str) -> Result<Self, Error> {
+        let mut inputs = vec![];
+        for line in text.lines().filter(|line| line.starts_with('|')) {
+            let invalid = || Error::InvalidValue {
+                what: "fm2 input",
+                value: line.to_string(),
+            };
+            let port0 = line.split('|').nth(2).ok_or_else(invalid)?;
+            if port0.chars().count() != FM2_BUTTONS.len() {
+                return Err(invalid());
+            }
+            let buttons = port0
+                .chars()
+                .enumerate()
+                .filter(|&(_, button)| button != '.' && button != ' ')
+                .fold(0, |buttons, (bit, _)| buttons | 1 << bit);
+            inputs.push(buttons);
+        }
+        Ok(Self::from_inputs(&inputs))
+    }
+
+    pub fn from_fm2(text: &str) -> Self {
+        Self::try_from_fm2(text).unwrap_or_else(|error| panic!("{}", error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b"smb"), "c21i");
+        assert_eq!(base64(b"smb1"), "c21iMQ==");
+        assert_eq!(base64(b"smb1!"), "c21iMSE=");
+    }
+
+    #[test]
+    fn test_fm2() {
+        let demo = Demo {
+            actions: vec![
+                DemoAction { buttons: BUTTON_RIGHT, frames: 2 },
+                DemoAction { buttons: BUTTON_A | BUTTON_LEFT, frames: 1 },
+            ],
+        };
+        let rom_data = vec![0; SMB1_PRG_SIZE + SMB1_CHR_SIZE];
+        let fm2 = demo.to_fm2(&Rom::new_unverified(rom_data).unwrap());
+        assert!(fm2.starts_with("version 3\n"));
+        assert!(fm2.ends_with("|0|R.......|||\n|0|.L.....A|||\n"));
+        assert_eq!(Demo::from_fm2(&fm2), demo);
+
+        assert!(Demo::try_from_fm2("|0|R|||\n").is_err());
+    }
+}
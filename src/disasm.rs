@@ -0,0 +1,580 @@
+/*!
+ * Disassemble the game's program code into annotated 6502 assembly.
+ *
+ * Code is found by following it from the interrupt vectors, including the
+ * jump tables the game dispatches through with `JumpEngine`. Everything
+ * else is left as data.
+ */
+
+mod labels;
+mod opcode;
+
+pub use labels::*;
+pub use opcode::*;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::*;
+
+/// CPU address of the NMI, reset and IRQ vectors.
+pub const VECTORS_ADDR: u16 = 0xfffa;
+
+/// Most entries read from a single jump table.
+const MAX_JUMP_TABLE_LEN: usize = 128;
+
+/// Bytes shown on each `.db` line.
+const BYTES_PER_LINE: usize = 8;
+
+/// A decoded piece of PRG-ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisasmItem {
+    Code(Instruction),
+    /// Addresses, like a jump table or the vectors.
+    Words {
+        addr: u16,
+        words: Vec<u16>,
+    },
+    Bytes {
+        addr: u16,
+        bytes: Vec<u8>,
+    },
+}
+
+impl DisasmItem {
+    /// Get the CPU address the item starts at.
+    pub fn addr(&self) -> u16 {
+        match self {
+            Self::Code(ins) => ins.addr,
+            Self::Words { addr, .. } | Self::Bytes { addr, .. } => *addr,
+        }
+    }
+}
+
+/**
+ * A disassembled PRG bank, printed as a listing with `Display`.
+ *
+ * Known routines and tables are named after the community disassembly,
+ * anything else found gets a generated `sub_`, `L_` or `JumpTable_` label.
+ */
+#[derive(Debug)]
+pub struct Disassembly {
+    items: Vec<DisasmItem>,
+    labels: BTreeMap<u16, String>,
+    jump_tables: BTreeMap<u16, Vec<u16>>,
+}
+
+/// Follows code from its entry points, see `Disassembly::try_from_bytes`.
+struct Tracer<'a> {
+    prg: &'a [u8],
+    base: u16,
+    data: BTreeMap<u16, usize>,
+    labels: BTreeMap<u16, String>,
+    code: BTreeMap<u16, Instruction>,
+    jump_tables: BTreeMap<u16, Vec<u16>>,
+    jump_engine: Option<u16>,
+    pending: Vec<u16>,
+}
+
+impl Tracer<'_> {
+    fn byte_at(&self, addr: u16) -> Option<u8> {
+        let offset = addr.checked_sub(self.base)?;
+        self.prg.get(offset as usize).copied()
+    }
+
+    fn slice_at(&self, addr: u16) -> Option<&[u8]> {
+        let offset = addr.checked_sub(self.base)? as usize;
+        self.prg.get(offset..)
+    }
+
+    fn word_at(&self, addr: u16) -> Option<u16> {
+        let low = self.byte_at(addr)?;
+        let high = self.byte_at(addr.checked_add(1)?)?;
+        Some(u16::from_le_bytes([low, high]))
+    }
+
+    /// Whether an address is inside one of the known data tables.
+    fn is_data(&self, addr: u16) -> bool {
+        let table = self.data.range(..=addr).next_back();
+        table.is_some_and(|(&start, &len)| ((addr - start) as usize) < len)
+    }
+
+    fn name(&mut self, addr: u16, prefix: &str) {
+        let name = format!("{}_{:04x}", prefix, addr);
+        self.labels.entry(addr).or_insert(name);
+    }
+
+    fn trace(&mut self) {
+        while let Some(mut addr) = self.pending.pop() {
+            loop {
+                if self.code.contains_key(&addr) || self.is_data(addr) {
+                    break;
+                }
+                let Some(bytes) = self.slice_at(addr) else {
+                    break;
+                };
+                // an unofficial opcode means this was never code
+                let Ok(ins) = Instruction::try_from_bytes(addr, bytes) else {
+                    break;
+                };
+                self.code.insert(addr, ins);
+
+                if let Some(target) = ins.target() {
+                    let prefix =
+                        if ins.mnemonic == "jsr" { "sub" } else { "L" };
+                    self.name(target, prefix);
+                    self.pending.push(target);
+                }
+                // `JumpEngine` returns through the table after the call
+                if ins.mnemonic == "jsr" && ins.target() == self.jump_engine {
+                    self.jump_table(ins.next_addr());
+                    break;
+                }
+                if ins.ends_flow() || ins.next_addr() < addr {
+                    break;
+                }
+                addr = ins.next_addr();
+            }
+        }
+    }
+
+    /**
+     * Read a jump table, which has no length of its own.
+     *
+     * It ends where the code or data after it starts, assuming it's either
+     * the first entry or something already known.
+     */
+    fn jump_table(&mut self, addr: u16) {
+        let mut entries = vec![];
+        while entries.len() < MAX_JUMP_TABLE_LEN {
+            let Some(pos) = addr.checked_add(2 * entries.len() as u16) else {
+                break;
+            };
+            let taken = self.labels.contains_key(&pos)
+                || self.code.contains_key(&pos)
+                || self.is_data(pos);
+            if !entries.is_empty() && (taken || entries.contains(&pos)) {
+                break;
+            }
+            match self.word_at(pos) {
+                Some(word) if self.byte_at(word).is_some() => {
+                    entries.push(word)
+                }
+                _ => break,
+            }
+        }
+        if entries.is_empty() {
+            return;
+        }
+
+        let known =
+            KNOWN_JUMP_TABLES.into_iter().find(|table| table.matches(&entries));
+        match known {
+            Some(table) => {
+                entries.truncate(table.handlers.len());
+                self.labels.insert(addr, table.name.to_string());
+                for (&entry, name) in entries.iter().zip(table.handlers) {
+                    self.labels.insert(entry, name.to_string());
+                }
+            }
+            None => self.name(addr, "JumpTable"),
+        }
+        for &entry in &entries {
+            self.name(entry, "L");
+            self.pending.push(entry);
+        }
+        self.jump_tables.insert(addr, entries);
+    }
+
+    /// Split the bank into code, words and data in address order.
+    fn items(&self) -> Vec<DisasmItem> {
+        let end = self.base as usize + self.prg.len();
+        let mut items = vec![];
+        let mut bytes = vec![];
+        let mut addr = self.base as usize;
+        while addr < end {
+            let at = addr as u16;
+            let word_table = match self.jump_tables.get(&at) {
+                Some(entries) => Some(entries.clone()),
+                None if at == VECTORS_ADDR => (0..3)
+                    .map(|idx| self.word_at(at + 2 * idx))
+                    .collect::<Option<Vec<_>>>(),
+                None => None,
+            };
+            // instructions hiding another behind an operand are left as data
+            let code = self.code.get(&at).filter(|ins| {
+                let operand = addr + 1..addr + ins.len();
+                !operand.into_iter().any(|addr| {
+                    self.code.contains_key(&(addr as u16))
+                        || self.labels.contains_key(&(addr as u16))
+                })
+            });
+            let table_end = self
+                .data
+                .iter()
+                .any(|(&start, &len)| start as usize + len == addr);
+            let starts_item = word_table.is_some()
+                || code.is_some()
+                || table_end
+                || self.labels.contains_key(&at);
+
+            if starts_item && !bytes.is_empty() {
+                let start = (addr - bytes.len()) as u16;
+                let bytes = std::mem::take(&mut bytes);
+                items.push(DisasmItem::Bytes { addr: start, bytes });
+            }
+            if let Some(words) = word_table {
+                addr += 2 * words.len();
+                items.push(DisasmItem::Words { addr: at, words });
+            } else if let Some(ins) = code {
+                addr += ins.len();
+                items.push(DisasmItem::Code(*ins));
+            } else {
+                bytes.push(self.prg[addr - self.base as usize]);
+                addr += 1;
+            }
+        }
+        if !bytes.is_empty() {
+            let start = (addr - bytes.len()) as u16;
+            items.push(DisasmItem::Bytes { addr: start, bytes });
+        }
+        items
+    }
+}
+
+impl Disassembly {
+    /**
+     * Disassemble a PRG bank mapped to the end of the address space.
+     *
+     * The known tables of `SMB1_LABELS` are always treated as data, so this
+     * is only meaningful for the game and hacks of it.
+     */
+    pub fn try_from_bytes(prg: &[u8]) -> Result<Self, Error> {
+        if prg.is_empty() || prg.len() > 0x8000 {
+            return Err(Error::InvalidValue {
+                what: "prg size",
+                value: format!("{:#x}", prg.len()),
+            });
+        }
+        let base = (0x10000 - prg.len()) as u16;
+
+        let mut tracer = Tracer {
+            prg,
+            base,
+            data: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            code: BTreeMap::new(),
+            jump_tables: BTreeMap::new(),
+            jump_engine: None,
+            pending: vec![],
+        };
+        for label in SMB1_LABELS {
+            if let LabelKind::Data(len) = label.kind {
+                tracer.data.insert(label.addr, len);
+            }
+            if label.addr >= base {
+                tracer.labels.insert(label.addr, label.name.to_string());
+            }
+        }
+
+        let engine = prg
+            .windows(JUMP_ENGINE_CODE.len())
+            .position(|window| window == JUMP_ENGINE_CODE);
+        if let Some(offset) = engine {
+            let addr = base + offset as u16;
+            tracer.jump_engine = Some(addr);
+            tracer.labels.insert(addr, "JumpEngine".to_string());
+            tracer.pending.push(addr);
+        }
+
+        if tracer.word_at(VECTORS_ADDR + 4).is_some() {
+            tracer.labels.insert(VECTORS_ADDR, "Vectors".to_string());
+            for (vector, name) in VECTOR_LABELS {
+                let addr = tracer.word_at(vector).unwrap();
+                tracer.labels.insert(addr, name.to_string());
+                tracer.pending.push(addr);
+            }
+        }
+
+        tracer.trace();
+        let items = tracer.items();
+        let mut disasm = Self {
+            items,
+            labels: tracer.labels,
+            jump_tables: tracer.jump_tables,
+        };
+        disasm.name_callers();
+        Ok(disasm)
+    }
+
+    /// Name the routines calling `JumpEngine` with the known jump tables.
+    fn name_callers(&mut self) {
+        for table in KNOWN_JUMP_TABLES {
+            // the table comes straight after the `jsr JumpEngine`
+            let call = self
+                .label_addr(table.name)
+                .and_then(|addr| self.subroutine_start(addr.checked_sub(3)?));
+            let Some(start) = call else {
+                continue;
+            };
+            // only generated names are replaced
+            let generated = self.label(start).is_none_or(|name| {
+                name.starts_with("sub_") || name.starts_with("L_")
+            });
+            if generated {
+                self.labels.insert(start, table.caller.to_string());
+            }
+        }
+    }
+
+    /// Panics on an empty or oversized bank, see `try_from_bytes`.
+    pub fn from_bytes(prg: &[u8]) -> Self {
+        Self::try_from_bytes(prg).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Get the code and data of the bank in address order.
+    pub fn items(&self) -> &[DisasmItem] {
+        &self.items
+    }
+
+    /// Get the name of an address.
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&addr).map(String::as_str)
+    }
+
+    /// Get the address with a name.
+    pub fn label_addr(&self, name: &str) -> Option<u16> {
+        self.labels
+            .iter()
+            .find(|(_, label)| *label == name)
+            .map(|(&addr, _)| addr)
+    }
+
+    /// Get the jump tables found after calls to `JumpEngine`, by address.
+    pub fn jump_tables(&self) -> &BTreeMap<u16, Vec<u16>> {
+        &self.jump_tables
+    }
+
+    /// Get the instruction starting at an address.
+    pub fn instruction(&self, addr: u16) -> Option<&Instruction> {
+        self.items.iter().find_map(|item| match item {
+            DisasmItem::Code(ins) if ins.addr == addr => Some(ins),
+            _ => None,
+        })
+    }
+
+    /**
+     * Find everything pointing at an address.
+     *
+     * These are the instructions jumping to or reading from it and the
+     * addresses of jump table entries leading to it.
+     */
+    pub fn references(&self, addr: u16) -> Vec<u16> {
+        use AddressingMode::*;
+
+        let mut references = BTreeSet::new();
+        for item in &self.items {
+            match item {
+                DisasmItem::Code(ins) => {
+                    let absolute =
+                        matches!(ins.mode, Absolute | AbsoluteX | AbsoluteY);
+                    if ins.target() == Some(addr)
+                        || (absolute && ins.operand == addr)
+                    {
+                        references.insert(ins.addr);
+                    }
+                }
+                DisasmItem::Words { addr: start, words } => {
+                    let entries = words.iter().enumerate();
+                    references.extend(
+                        entries
+                            .filter(|(_, &word)| word == addr)
+                            .map(|(idx, _)| start + 2 * idx as u16),
+                    );
+                }
+                DisasmItem::Bytes { .. } => {}
+            }
+        }
+        references.into_iter().collect()
+    }
+
+    /// Find the start of the subroutine holding the instruction at `addr`.
+    pub fn subroutine_start(&self, addr: u16) -> Option<u16> {
+        let called = |start: u16| {
+            self.references(start).into_iter().any(|reference| {
+                self.instruction(reference)
+                    .is_some_and(|ins| ins.mnemonic == "jsr")
+            })
+        };
+        self.items
+            .iter()
+            .rev()
+            .filter_map(|item| match item {
+                DisasmItem::Code(ins) if ins.addr <= addr => Some(ins.addr),
+                _ => None,
+            })
+            .find(|&start| called(start))
+    }
+
+    fn known_entry(&self, table: &KnownJumpTable, idx: usize) -> Option<u16> {
+        let addr = self.label_addr(table.name)?;
+        self.jump_tables.get(&addr)?.get(idx).copied()
+    }
+
+    /// Get the routine the area parser runs to draw an object.
+    pub fn area_object_routine(&self, object: &LevelObject) -> Option<u16> {
+        let idx = area_object_handler(object)?;
+        self.known_entry(&AREA_OBJECT_HANDLERS, idx)
+    }
+
+    /// Get the routine setting up an enemy when it's spawned.
+    pub fn enemy_init_routine(&self, kind: &LevelEnemyKind) -> Option<u16> {
+        let idx = enemy_init_handler(kind)?;
+        self.known_entry(&ENEMY_INIT_HANDLERS, idx)
+    }
+
+    fn format_addr(&self, addr: u16) -> String {
+        match self.label(addr) {
+            Some(label) => label.to_string(),
+            None => format!("${:04x}", addr),
+        }
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = |bytes: &[u8]| {
+            let bytes: Vec<_> =
+                bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            bytes.join(" ")
+        };
+        let label = |f: &mut fmt::Formatter<'_>, addr| match self.label(addr) {
+            Some(name) => writeln!(f, "\n{}:", name),
+            None => Ok(()),
+        };
+
+        for item in &self.items {
+            match item {
+                DisasmItem::Code(ins) => {
+                    label(f, ins.addr)?;
+                    let text =
+                        ins.format(|addr| self.label(addr).map(str::to_string));
+                    let bytes = hex(&ins.to_bytes());
+                    writeln!(f, "  ${:04x}  {:<8}  {}", ins.addr, bytes, text)?;
+                }
+                DisasmItem::Words { addr, words } => {
+                    label(f, *addr)?;
+                    for (idx, &word) in words.iter().enumerate() {
+                        let at = addr + 2 * idx as u16;
+                        let bytes = hex(&word.to_le_bytes());
+                        let text = self.format_addr(word);
+                        writeln!(
+                            f,
+                            "  ${:04x}  {:<8}  .dw {}",
+                            at, bytes, text
+                        )?;
+                    }
+                }
+                DisasmItem::Bytes { addr, bytes } => {
+                    label(f, *addr)?;
+                    for (idx, row) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+                        let at = addr + (idx * BYTES_PER_LINE) as u16;
+                        let row: Vec<_> = row
+                            .iter()
+                            .map(|byte| format!("${:02x}", byte))
+                            .collect();
+                        writeln!(
+                            f,
+                            "  ${:04x}  {:<8}  .db {}",
+                            at,
+                            "",
+                            row.join(", ")
+                        )?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Rom {
+    /// Disassemble PRG-ROM, see `Disassembly`.
+    pub fn disassemble(&self) -> Result<Disassembly> {
+        Ok(Disassembly::try_from_bytes(self.prg())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::zero_rom;
+
+    #[test]
+    fn test_disassembly() {
+        let mut rom = zero_rom();
+        let mut write =
+            |addr: u16, bytes: &[u8]| rom.write_cpu(addr, bytes).unwrap();
+        write(
+            0xc000,
+            &[
+                0x78, // sei
+                0xb9, 0x5a, 0x80, // lda VRAM_AddrTable_Low,y
+                0xa2, 0x00, // ldx #$00
+                0xf0, 0x01, // beq $c009
+                0x2c, 0xa9, 0x02, // bit $02a9, hiding lda #$02
+                0x20, 0x20, 0xc0, // jsr $c020
+                0x4c, 0x00, 0xc0, // jmp $c000
+            ],
+        );
+        write(0xc012, &[0x40]);
+        // dispatch through a table shaped like the area object handlers
+        write(0xc020, &[0x20, 0x90, 0xc0]);
+        let mut handlers: Vec<u16> = (0..47).map(|idx| 0xc100 + idx).collect();
+        for group in AREA_OBJECT_HANDLERS.shared {
+            for &idx in group.iter() {
+                handlers[idx] = handlers[group[0]];
+            }
+        }
+        for (idx, handler) in handlers.iter().enumerate() {
+            write(0xc023 + 2 * idx as u16, &handler.to_le_bytes());
+            write(*handler, &[0x60]);
+        }
+        write(0xc090, &JUMP_ENGINE_CODE);
+        write(VECTORS_ADDR, &[0x12, 0xc0, 0x00, 0xc0, 0x00, 0xc0]);
+
+        let disasm = Disassembly::from_bytes(rom.prg());
+        assert_eq!(disasm.label(0xc000), Some("Start"));
+        assert_eq!(disasm.label(0xc012), Some("NonMaskableInterrupt"));
+        assert_eq!(disasm.label(0xc020), Some("DecodeAreaData"));
+        assert_eq!(disasm.label(0xc090), Some("JumpEngine"));
+        assert_eq!(disasm.label(0xc009), Some("L_c009"));
+        assert_eq!(disasm.label(0xc023), Some("AreaObjectHandlers"));
+        assert_eq!(disasm.jump_tables()[&0xc023], handlers);
+        assert_eq!(disasm.references(0xc020), [0xc00b]);
+
+        let bricks = LevelObject::from_bytes(&[0x47, 0x24]);
+        let routine = disasm.area_object_routine(&bricks).unwrap();
+        assert_eq!(routine, 0xc102);
+        assert_eq!(disasm.label(routine), Some("RowOfBricks"));
+        assert_eq!(disasm.instruction(routine).unwrap().mnemonic, "rts");
+        assert_eq!(disasm.enemy_init_routine(&LevelEnemyKind::Goomba), None);
+
+        let text = disasm.to_string();
+        let lines: Vec<_> = text.lines().collect();
+        assert!(lines.contains(&"Start:"));
+        assert!(lines.contains(&"  $c001  b9 5a 80  lda VRAM_AddrTable_Low,y"));
+        assert!(lines.contains(&"  $c008            .db $2c"));
+        assert!(lines.contains(&"  $c009  a9 02     lda #$02"));
+        assert!(lines.contains(&"  $c00b  20 20 c0  jsr DecodeAreaData"));
+        assert!(lines.contains(&"  $c025  01 c1     .dw AreaStyleObject"));
+        assert!(lines.contains(&"  $fffc  00 c0     .dw Start"));
+        assert!(lines.contains(
+            &"  $c013            .db $00, $00, $00, $00, $00, $00, $00, $00"
+        ));
+
+        assert!(Disassembly::try_from_bytes(&[]).is_err());
+    }
+}
@@ -0,0 +1,358 @@
+/*!
+ * Names from the community disassembly of the game (smbdis).
+ */
+
+use std::collections::BTreeSet;
+
+use crate::*;
+
+/// What a known label points at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelKind {
+    /// A routine, disassembled from here on.
+    Code,
+    /// A table of this many bytes, never disassembled.
+    Data(usize),
+}
+
+/// A named address of the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Label {
+    pub addr: u16,
+    pub name: &'static str,
+    pub kind: LabelKind,
+}
+
+const fn data(addr: u16, name: &'static str, len: usize) -> Label {
+    Label { addr, name, kind: LabelKind::Data(len) }
+}
+
+/// Tables this crate reads, at the addresses it reads them from.
+pub const SMB1_LABELS: &[Label] = &[
+    data(
+        VRAM_ADDR_TABLE_LOW,
+        "VRAM_AddrTable_Low",
+        VRAM_ADDR_TABLE_LEN as usize,
+    ),
+    data(
+        VRAM_ADDR_TABLE_HIGH,
+        "VRAM_AddrTable_High",
+        VRAM_ADDR_TABLE_LEN as usize,
+    ),
+    data(METATILE_GRAPHICS_LOW, "MetatileGraphics_Low", 4),
+    data(METATILE_GRAPHICS_HIGH, "MetatileGraphics_High", 4),
+    data(WORLD_ADDR_OFFSETS, "WorldAddrOffsets", 8),
+    data(AREA_ADDR_OFFSETS, "AreaAddrOffsets", 36),
+    data(ENEMY_ADDR_H_OFFSETS, "EnemyAddrHOffsets", 4),
+    data(ENEMY_DATA_ADDR_LOW, "EnemyDataAddrLow", 34),
+    data(ENEMY_DATA_ADDR_HIGH, "EnemyDataAddrHigh", 34),
+    data(AREA_DATA_H_OFFSETS, "AreaDataHOffsets", 4),
+    data(AREA_DATA_ADDR_LOW, "AreaDataAddrLow", 34),
+    data(AREA_DATA_ADDR_HIGH, "AreaDataAddrHigh", 34),
+];
+
+/**
+ * The game's `JumpEngine`, found by its code rather than its address.
+ *
+ * Called with an index in A, it jumps to that entry of the table of
+ * addresses following the `jsr JumpEngine`.
+ */
+pub const JUMP_ENGINE_CODE: [u8; 21] = [
+    0x0a, // asl
+    0xa8, // tay
+    0x68, // pla
+    0x85, 0x04, // sta $04
+    0x68, // pla
+    0x85, 0x05, // sta $05
+    0xc8, // iny
+    0xb1, 0x04, // lda ($04),y
+    0x85, 0x06, // sta $06
+    0xc8, // iny
+    0xb1, 0x04, // lda ($04),y
+    0x85, 0x07, // sta $07
+    0x6c, 0x06, 0x00, // jmp ($0006)
+];
+
+/**
+ * Routines the game's interrupt vectors point at, by vector address.
+ *
+ * The irq vector isn't used by the game, so it has no routine.
+ */
+pub const VECTOR_LABELS: [(u16, &str); 2] =
+    [(VECTORS_ADDR, "NonMaskableInterrupt"), (VECTORS_ADDR + 2, "Start")];
+
+/// A jump table the game dispatches through, recognised by its entries.
+pub struct KnownJumpTable {
+    pub name: &'static str,
+    /// Name of the routine calling `JumpEngine` with this table.
+    pub caller: &'static str,
+    /// Name of the routine behind each entry.
+    pub handlers: &'static [&'static str],
+    /// Groups of entries that always go to the same routine.
+    pub shared: &'static [&'static [usize]],
+}
+
+impl KnownJumpTable {
+    /**
+     * Whether a jump table's entries look like this one.
+     *
+     * Entries of a shared group must all be the same, and each group must
+     * go to a different routine.
+     */
+    pub fn matches(&self, entries: &[u16]) -> bool {
+        if entries.len() < self.handlers.len() {
+            return false;
+        }
+        let shared = self.shared.iter().all(|group| {
+            group.iter().all(|&idx| entries[idx] == entries[group[0]])
+        });
+        let firsts: BTreeSet<_> =
+            self.shared.iter().map(|group| entries[group[0]]).collect();
+        shared && firsts.len() == self.shared.len()
+    }
+}
+
+/**
+ * Handlers of area objects, run by the area parser (`DecodeAreaData`).
+ *
+ * See `area_object_handler` for the entry used by each `LevelObjectKind`.
+ */
+pub const AREA_OBJECT_HANDLERS: KnownJumpTable = KnownJumpTable {
+    name: "AreaObjectHandlers",
+    caller: "DecodeAreaData",
+    handlers: &[
+        // large objects, y 0x0-0xb
+        "VerticalPipe",
+        "AreaStyleObject",
+        "RowOfBricks",
+        "RowOfSolidBlocks",
+        "RowOfCoins",
+        "ColumnOfBricks",
+        "ColumnOfSolidBlocks",
+        "VerticalPipe",
+        // y 0xc
+        "Hole_Empty",
+        "PulleyRopeObject",
+        "Bridge_High",
+        "Bridge_Middle",
+        "Bridge_Low",
+        "Hole_Water",
+        "QuestionBlockRow_High",
+        "QuestionBlockRow_Low",
+        // y 0xf
+        "EndlessRope",
+        "BalancePlatRope",
+        "CastleObject",
+        "StaircaseObject",
+        "ExitPipe",
+        "FlagBalls_Residual",
+        // small objects, y 0x0-0xb
+        "QuestionBlock",
+        "QuestionBlock",
+        "QuestionBlock",
+        "Hidden1UpBlock",
+        "BrickWithItem",
+        "BrickWithItem",
+        "BrickWithItem",
+        "BrickWithCoins",
+        "BrickWithItem",
+        "WaterPipe",
+        "EmptyBlock",
+        "Jumpspring",
+        // y 0xd
+        "IntroPipe",
+        "FlagpoleObject",
+        "AxeObj",
+        "ChainObj",
+        "CastleBridgeObj",
+        "ScrollLockObject_Warp",
+        "ScrollLockObject",
+        "ScrollLockObject",
+        "AreaFrenzy",
+        "AreaFrenzy",
+        "AreaFrenzy",
+        "LoopCmdE",
+        // y 0xe
+        "AlterAreaAttributes",
+    ],
+    shared: &[&[0, 7], &[22, 23, 24], &[26, 27, 28, 30], &[42, 43, 44]],
+};
+
+/**
+ * Routines setting up each enemy as it is spawned, indexed by enemy id.
+ *
+ * See `enemy_init_handler` for the entry used by each `LevelEnemyKind`.
+ */
+pub const ENEMY_INIT_HANDLERS: KnownJumpTable = KnownJumpTable {
+    name: "InitEnemyRoutines",
+    caller: "CheckpointEnemyID",
+    handlers: &[
+        // 0x00-0x0f
+        "InitNormalEnemy",
+        "InitNormalEnemy",
+        "InitNormalEnemy",
+        "InitRedKoopa",
+        "NoInitCode",
+        "InitHammerBro",
+        "InitGoomba",
+        "InitBloober",
+        "InitBulletBill",
+        "NoInitCode",
+        "InitCheepCheep",
+        "InitCheepCheep",
+        "InitPodoboo",
+        "InitPiranhaPlant",
+        "InitJumpGPTroopa",
+        "InitRedPTroopa",
+        // 0x10-0x1f
+        "InitHorizFlySwimEnemy",
+        "InitLakitu",
+        "InitEnemyFrenzy",
+        "NoInitCode",
+        "InitEnemyFrenzy",
+        "InitEnemyFrenzy",
+        "InitEnemyFrenzy",
+        "InitEnemyFrenzy",
+        "EndFrenzy",
+        "NoInitCode",
+        "NoInitCode",
+        "InitShortFirebar",
+        "InitShortFirebar",
+        "InitShortFirebar",
+        "InitShortFirebar",
+        "InitLongFirebar",
+        // 0x20-0x2f
+        "NoInitCode",
+        "NoInitCode",
+        "NoInitCode",
+        "NoInitCode",
+        "InitBalPlatform",
+        "InitVertPlatform",
+        "LargeLiftUp",
+        "LargeLiftDown",
+        "InitHoriPlatform",
+        "InitDropPlatform",
+        "InitHoriPlatform",
+        "PlatLiftUp",
+        "PlatLiftDown",
+        "InitBowser",
+        "PwrUpJmp",
+        "Setup_Vine",
+        // 0x30-0x36
+        "NoInitCode",
+        "NoInitCode",
+        "NoInitCode",
+        "NoInitCode",
+        "NoInitCode",
+        "InitRetainerObj",
+        "EndOfEnemyInitCode",
+    ],
+    shared: &[
+        &[0, 1, 2],
+        &[4, 9, 0x13, 0x19, 0x1a, 0x20, 0x21, 0x22, 0x23],
+        &[0x0a, 0x0b],
+        &[0x1b, 0x1c, 0x1d, 0x1e],
+        &[0x28, 0x2a],
+    ],
+};
+
+/**
+ * The area parser's tasks, run one at a time by `AreaParserTaskHandler`.
+ *
+ * Each pass renders two columns: `AreaParserCore` fills the metatile buffer
+ * for the current column and `IncrementColumnPos` moves on to the next.
+ */
+pub const AREA_PARSER_TASKS: KnownJumpTable = KnownJumpTable {
+    name: "AreaParserTaskTable",
+    caller: "AreaParserTasks",
+    handlers: &[
+        "IncrementColumnPos",
+        "RenderAreaGraphics",
+        "RenderAreaGraphics",
+        "AreaParserCore",
+        "IncrementColumnPos",
+        "RenderAreaGraphics",
+        "RenderAreaGraphics",
+        "AreaParserCore",
+    ],
+    shared: &[&[0, 4], &[1, 2, 5, 6], &[3, 7]],
+};
+
+/// Every jump table the disassembly names.
+pub const KNOWN_JUMP_TABLES: [KnownJumpTable; 3] =
+    [AREA_OBJECT_HANDLERS, ENEMY_INIT_HANDLERS, AREA_PARSER_TASKS];
+
+/// Get the `AREA_OBJECT_HANDLERS` entry the area parser runs for an object.
+pub fn area_object_handler(object: &LevelObject) -> Option<usize> {
+    use LevelObjectKind::*;
+
+    let byte = object.kind.value();
+    let index = match (object.y_coordinate, object.kind) {
+        (_, Invalid(_) | Nothing | PageSkip(_)) => return None,
+        // pipes with an entry are warp pipes
+        (0x0..=0xb, PipeEntry(_)) => 0,
+        (0x0..=0xb, _) if byte < 0x10 => 22 + byte as usize,
+        (0x0..=0xb, _) => (byte >> 4) as usize,
+        (0xc, _) => 8 + (byte >> 4) as usize,
+        (0xf, _) => 16 + (byte >> 4) as usize,
+        (0xd, _) => 34 + (byte & 0x0f) as usize,
+        (0xe, _) => 46,
+        _ => return None,
+    };
+    Some(index)
+}
+
+/// Get the `ENEMY_INIT_HANDLERS` entry run when an enemy is spawned.
+pub fn enemy_init_handler(kind: &LevelEnemyKind) -> Option<usize> {
+    match kind {
+        // groups spawn single goombas and koopa troopas instead
+        LevelEnemyKind::PageSkip(_) | LevelEnemyKind::Undefined(_) => None,
+        _ if kind.value() as usize >= ENEMY_INIT_HANDLERS.handlers.len() => {
+            None
+        }
+        _ => Some(kind.value() as usize),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handler_indices() {
+        let handler = |bytes: [u8; 2]| {
+            let index = area_object_handler(&LevelObject::from_bytes(&bytes));
+            index.map(|index| AREA_OBJECT_HANDLERS.handlers[index])
+        };
+        assert_eq!(handler([0x47, 0x24]), Some("RowOfBricks"));
+        assert_eq!(handler([0x47, 0x01]), Some("QuestionBlock"));
+        assert_eq!(handler([0x47, 0x0b]), Some("Jumpspring"));
+        assert_eq!(handler([0x47, 0x73]), Some("VerticalPipe"));
+        assert_eq!(handler([0x47, 0x7b]), Some("VerticalPipe"));
+        assert_eq!(handler([0x0c, 0x02]), Some("Hole_Empty"));
+        assert_eq!(handler([0x0c, 0x52]), Some("Hole_Water"));
+        assert_eq!(handler([0x0d, 0x41]), Some("FlagpoleObject"));
+        assert_eq!(handler([0x0d, 0x4b]), Some("LoopCmdE"));
+        assert_eq!(handler([0x0d, 0x02]), None);
+        assert_eq!(handler([0x0e, 0x41]), Some("AlterAreaAttributes"));
+        assert_eq!(handler([0x0f, 0x25]), Some("CastleObject"));
+        assert_eq!(handler([0x0f, 0x44]), Some("ExitPipe"));
+
+        let handler = |kind| {
+            let index = enemy_init_handler(&kind);
+            index.map(|index| ENEMY_INIT_HANDLERS.handlers[index])
+        };
+        assert_eq!(handler(LevelEnemyKind::Goomba), Some("InitGoomba"));
+        assert_eq!(handler(LevelEnemyKind::Bowser), Some("InitBowser"));
+        assert_eq!(handler(LevelEnemyKind::TwoGoombasY10), None);
+        assert_eq!(handler(LevelEnemyKind::PageSkip(2)), None);
+
+        // every name in a shared group is the same routine
+        for table in KNOWN_JUMP_TABLES {
+            for group in table.shared {
+                let name = table.handlers[group[0]];
+                assert!(group.iter().all(|&idx| table.handlers[idx] == name));
+            }
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use crate::*;
+
+/// How an instruction finds its operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingMode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+
+impl AddressingMode {
+    /// Get the number of operand bytes following the opcode.
+    pub fn operand_len(&self) -> usize {
+        match self {
+            Self::Implied | Self::Accumulator => 0,
+            Self::Immediate
+            | Self::ZeroPage
+            | Self::ZeroPageX
+            | Self::ZeroPageY
+            | Self::IndirectX
+            | Self::IndirectY
+            | Self::Relative => 1,
+            Self::Absolute
+            | Self::AbsoluteX
+            | Self::AbsoluteY
+            | Self::Indirect => 2,
+        }
+    }
+}
+
+/// An official 6502 opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+}
+
+impl Opcode {
+    /// Look up an opcode byte, the game never uses unofficial opcodes.
+    pub fn new(byte: u8) -> Option<Self> {
+        use AddressingMode::*;
+
+        let (mnemonic, mode) = match byte {
+            0x69 => ("adc", Immediate),
+            0x65 => ("adc", ZeroPage),
+            0x75 => ("adc", ZeroPageX),
+            0x6d => ("adc", Absolute),
+            0x7d => ("adc", AbsoluteX),
+            0x79 => ("adc", AbsoluteY),
+            0x61 => ("adc", IndirectX),
+            0x71 => ("adc", IndirectY),
+
+            0x29 => ("and", Immediate),
+            0x25 => ("and", ZeroPage),
+            0x35 => ("and", ZeroPageX),
+            0x2d => ("and", Absolute),
+            0x3d => ("and", AbsoluteX),
+            0x39 => ("and", AbsoluteY),
+            0x21 => ("and", IndirectX),
+            0x31 => ("and", IndirectY),
+
+            0x0a => ("asl", Accumulator),
+            0x06 => ("asl", ZeroPage),
+            0x16 => ("asl", ZeroPageX),
+            0x0e => ("asl", Absolute),
+            0x1e => ("asl", AbsoluteX),
+
+            0x90 => ("bcc", Relative),
+            0xb0 => ("bcs", Relative),
+            0xf0 => ("beq", Relative),
+            0x30 => ("bmi", Relative),
+            0xd0 => ("bne", Relative),
+            0x10 => ("bpl", Relative),
+            0x50 => ("bvc", Relative),
+            0x70 => ("bvs", Relative),
+
+            0x24 => ("bit", ZeroPage),
+            0x2c => ("bit", Absolute),
+
+            0x00 => ("brk", Implied),
+            0x18 => ("clc", Implied),
+            0xd8 => ("cld", Implied),
+            0x58 => ("cli", Implied),
+            0xb8 => ("clv", Implied),
+
+            0xc9 => ("cmp", Immediate),
+            0xc5 => ("cmp", ZeroPage),
+            0xd5 => ("cmp", ZeroPageX),
+            0xcd => ("cmp", Absolute),
+            0xdd => ("cmp", AbsoluteX),
+            0xd9 => ("cmp", AbsoluteY),
+            0xc1 => ("cmp", IndirectX),
+            0xd1 => ("cmp", IndirectY),
+
+            0xe0 => ("cpx", Immediate),
+            0xe4 => ("cpx", ZeroPage),
+            0xec => ("cpx", Absolute),
+            0xc0 => ("cpy", Immediate),
+            0xc4 => ("cpy", ZeroPage),
+            0xcc => ("cpy", Absolute),
+
+            0xc6 => ("dec", ZeroPage),
+            0xd6 => ("dec", ZeroPageX),
+            0xce => ("dec", Absolute),
+            0xde => ("dec", AbsoluteX),
+            0xca => ("dex", Implied),
+            0x88 => ("dey", Implied),
+
+            0x49 => ("eor", Immediate),
+            0x45 => ("eor", ZeroPage),
+            0x55 => ("eor", ZeroPageX),
+            0x4d => ("eor", Absolute),
+            0x5d => ("eor", AbsoluteX),
+            0x59 => ("eor", AbsoluteY),
+            0x41 => ("eor", IndirectX),
+            0x51 => ("eor", IndirectY),
+
+            0xe6 => ("inc", ZeroPage),
+            0xf6 => ("inc", ZeroPageX),
+            0xee => ("inc", Absolute),
+            0xfe => ("inc", AbsoluteX),
+            0xe8 => ("inx", Implied),
+            0xc8 => ("iny", Implied),
+
+            0x4c => ("jmp", Absolute),
+            0x6c => ("jmp", Indirect),
+            0x20 => ("jsr", Absolute),
+
+            0xa9 => ("lda", Immediate),
+            0xa5 => ("lda", ZeroPage),
+            0xb5 => ("lda", ZeroPageX),
+            0xad => ("lda", Absolute),
+            0xbd => ("lda", AbsoluteX),
+            0xb9 => ("lda", AbsoluteY),
+            0xa1 => ("lda", IndirectX),
+            0xb1 => ("lda", IndirectY),
+
+            0xa2 => ("ldx", Immediate),
+            0xa6 => ("ldx", ZeroPage),
+            0xb6 => ("ldx", ZeroPageY),
+            0xae => ("ldx", Absolute),
+            0xbe => ("ldx", AbsoluteY),
+
+            0xa0 => ("ldy", Immediate),
+            0xa4 => ("ldy", ZeroPage),
+            0xb4 => ("ldy", ZeroPageX),
+            0xac => ("ldy", Absolute),
+            0xbc => ("ldy", AbsoluteX),
+
+            0x4a => ("lsr", Accumulator),
+            0x46 => ("lsr", ZeroPage),
+            0x56 => ("lsr", ZeroPageX),
+            0x4e => ("lsr", Absolute),
+            0x5e => ("lsr", AbsoluteX),
+
+            0xea => ("nop", Implied),
+
+            0x09 => ("ora", Immediate),
+            0x05 => ("ora", ZeroPage),
+            0x15 => ("ora", ZeroPageX),
+            0x0d => ("ora", Absolute),
+            0x1d => ("ora", AbsoluteX),
+            0x19 => ("ora", AbsoluteY),
+            0x01 => ("ora", IndirectX),
+            0x11 => ("ora", IndirectY),
+
+            0x48 => ("pha", Implied),
+            0x08 => ("php", Implied),
+            0x68 => ("pla", Implied),
+            0x28 => ("plp", Implied),
+
+            0x2a => ("rol", Accumulator),
+            0x26 => ("rol", ZeroPage),
+            0x36 => ("rol", ZeroPageX),
+            0x2e => ("rol", Absolute),
+            0x3e => ("rol", AbsoluteX),
+
+            0x6a => ("ror", Accumulator),
+            0x66 => ("ror", ZeroPage),
+            0x76 => ("ror", ZeroPageX),
+            0x6e => ("ror", Absolute),
+            0x7e => ("ror", AbsoluteX),
+
+            0x40 => ("rti", Implied),
+            0x60 => ("rts", Implied),
+
+            0xe9 => ("sbc", Immediate),
+            0xe5 => ("sbc", ZeroPage),
+            0xf5 => ("sbc", ZeroPageX),
+            0xed => ("sbc", Absolute),
+            0xfd => ("sbc", AbsoluteX),
+            0xf9 => ("sbc", AbsoluteY),
+            0xe1 => ("sbc", IndirectX),
+            0xf1 => ("sbc", IndirectY),
+
+            0x38 => ("sec", Implied),
+            0xf8 => ("sed", Implied),
+            0x78 => ("sei", Implied),
+
+            0x85 => ("sta", ZeroPage),
+            0x95 => ("sta", ZeroPageX),
+            0x8d => ("sta", Absolute),
+            0x9d => ("sta", AbsoluteX),
+            0x99 => ("sta", AbsoluteY),
+            0x81 => ("sta", IndirectX),
+            0x91 => ("sta", IndirectY),
+
+            0x86 => ("stx", ZeroPage),
+            0x96 => ("stx", ZeroPageY),
+            0x8e => ("stx", Absolute),
+            0x84 => ("sty", ZeroPage),
+            0x94 => ("sty", ZeroPageX),
+            0x8c => ("sty", Absolute),
+
+            0xaa => ("tax", Implied),
+            0xa8 => ("tay", Implied),
+            0xba => ("tsx", Implied),
+            0x8a => ("txa", Implied),
+            0x9a => ("txs", Implied),
+            0x98 => ("tya", Implied),
+
+            _ => return None,
+        };
+        Some(Self { mnemonic, mode })
+    }
+}
+
+/// A single decoded instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    /// CPU address of the opcode.
+    pub addr: u16,
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    /// The operand byte or (little endian) word, 0 without one.
+    pub operand: u16,
+}
+
+impl Instruction {
+    /// Decode the instruction at the start of `bytes`, found at `addr`.
+    pub fn try_from_bytes(addr: u16, bytes: &[u8]) -> Result<Self, Error> {
+        Error::check_len("instruction", bytes, 1)?;
+        let opcode = bytes[0];
+        let Opcode { mnemonic, mode } =
+            Opcode::new(opcode).ok_or_else(|| Error::InvalidValue {
+                what: "opcode",
+                value: format!("{:#04x} at {:#06x}", opcode, addr),
+            })?;
+
+        Error::check_len("instruction", bytes, 1 + mode.operand_len())?;
+        let operand = match mode.operand_len() {
+            0 => 0,
+            1 => bytes[1] as u16,
+            _ => u16::from_le_bytes([bytes[1], bytes[2]]),
+        };
+
+        Ok(Self { addr, opcode, mnemonic, mode, operand })
+    }
+
+    /// Panics on unofficial opcodes, see `Instruction::try_from_bytes`.
+    pub fn from_bytes(addr: u16, bytes: &[u8]) -> Self {
+        Self::try_from_bytes(addr, bytes)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let [low, high] = self.operand.to_le_bytes();
+        let bytes = [self.opcode, low, high];
+        bytes[..self.len()].to_vec()
+    }
+
+    /// Get the number of bytes used by the instruction.
+    pub fn len(&self) -> usize {
+        1 + self.mode.operand_len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Get the address following this instruction.
+    pub fn next_addr(&self) -> u16 {
+        self.addr.wrapping_add(self.len() as u16)
+    }
+
+    /// Get the address a branch, jump or subroutine call goes to.
+    pub fn target(&self) -> Option<u16> {
+        match (self.mnemonic, self.mode) {
+            (_, AddressingMode::Relative) => {
+                let offset = self.operand as u8 as i8;
+                Some(self.next_addr().wrapping_add(offset as u16))
+            }
+            ("jmp" | "jsr", AddressingMode::Absolute) => Some(self.operand),
+            _ => None,
+        }
+    }
+
+    /// Whether execution never continues with the next instruction.
+    pub fn ends_flow(&self) -> bool {
+        matches!(self.mnemonic, "jmp" | "rts" | "rti" | "brk")
+    }
+
+    /**
+     * Format the instruction as assembly.
+     *
+     * `label` names addresses, anything it doesn't know is printed as a
+     * number.
+     */
+    pub fn format(&self, label: impl Fn(u16) -> Option<String>) -> String {
+        use AddressingMode::*;
+
+        let addr = |addr: u16| label(addr).unwrap_or(format!("${:04x}", addr));
+        let operand = match self.mode {
+            Implied | Accumulator => return self.mnemonic.to_string(),
+            Immediate => format!("#${:02x}", self.operand),
+            ZeroPage => format!("${:02x}", self.operand),
+            ZeroPageX => format!("${:02x},x", self.operand),
+            ZeroPageY => format!("${:02x},y", self.operand),
+            Absolute => addr(self.operand),
+            AbsoluteX => format!("{},x", addr(self.operand)),
+            AbsoluteY => format!("{},y", addr(self.operand)),
+            Indirect => format!("({})", addr(self.operand)),
+            IndirectX => format!("(${:02x},x)", self.operand),
+            IndirectY => format!("(${:02x}),y", self.operand),
+            Relative => addr(self.target().unwrap()),
+        };
+        format!("{} {}", self.mnemonic, operand)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instructions() {
+        let no_labels = |_| None;
+
+        let ins = Instruction::from_bytes(0x8000, &[0xbd, 0x5a, 0x80]);
+        assert_eq!(ins.len(), 3);
+        assert_eq!(ins.format(no_labels), "lda $805a,x");
+        assert_eq!(ins.to_bytes(), [0xbd, 0x5a, 0x80]);
+
+        let label = |addr| (addr == 0x805a).then(|| "Table".to_string());
+        assert_eq!(ins.format(label), "lda Table,x");
+
+        // branches are relative to the next instruction
+        let ins = Instruction::from_bytes(0x8010, &[0xd0, 0xfe]);
+        assert_eq!(ins.target(), Some(0x8010));
+        assert_eq!(ins.format(no_labels), "bne $8010");
+
+        let ins = Instruction::from_bytes(0x8000, &[0xb1, 0x04]);
+        assert_eq!(ins.format(no_labels), "lda ($04),y");
+        let ins = Instruction::from_bytes(0x8000, &[0x6c, 0x06, 0x00]);
+        assert_eq!(ins.format(no_labels), "jmp ($0006)");
+        assert!(ins.ends_flow());
+        assert_eq!(ins.target(), None);
+
+        assert!(Instruction::try_from_bytes(0x8000, &[0x02]).is_err());
+        assert!(Instruction::try_from_bytes(0x8000, &[0x20, 0x04]).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+/*!
+ * Errors returned when parsing rom and level data.
+ */
+
+/// Errors found while parsing data that doesn't look like an smb1 rom.
+///
+/// Offsets are relative to the data that was being parsed, `Rom` rebases
+/// them onto the rom file with `Error::offset_by`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Error {
+    #[error(
+        "{what} truncated at offset {offset:#x}: need {needed} bytes, \
+         only {available} available"
+    )]
+    Truncated {
+        what: &'static str,
+        offset: usize,
+        needed: usize,
+        available: usize,
+    },
+
+    #[error(
+        "{what} at offset {offset:#x} has no {terminator:#04x} terminator"
+    )]
+    MissingTerminator { what: &'static str, offset: usize, terminator: u8 },
+
+    #[error("invalid {what} at offset {offset:#x}: {reason}")]
+    InvalidHeader { what: &'static str, offset: usize, reason: String },
+
+    #[error("invalid {what} value: {value}")]
+    InvalidValue { what: &'static str, value: String },
+
+    #[error("unknown level name: {0:?}")]
+    UnknownLevelName(String),
+
+    #[error(
+        "{what} checksum mismatch for {len} bytes at offset {offset:#x}: \
+         expected {expected} got {actual}"
+    )]
+    ChecksumMismatch {
+        what: &'static str,
+        offset: usize,
+        len: usize,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl Error {
+    /// Move the offset of this error along by `base` bytes.
+    pub fn offset_by(self, base: usize) -> Self {
+        match self {
+            Self::Truncated { what, offset, needed, available } => {
+                let offset = offset + base;
+                Self::Truncated { what, offset, needed, available }
+            }
+            Self::MissingTerminator { what, offset, terminator } => {
+                let offset = offset + base;
+                Self::MissingTerminator { what, offset, terminator }
+            }
+            Self::InvalidHeader { what, offset, reason } => {
+                let offset = offset + base;
+                Self::InvalidHeader { what, offset, reason }
+            }
+            Self::ChecksumMismatch { what, offset, len, expected, actual } => {
+                let offset = offset + base;
+                Self::ChecksumMismatch { what, offset, len, expected, actual }
+            }
+            error => error,
+        }
+    }
+
+    /// Check that `bytes` holds at least `needed` bytes.
+    pub(crate) fn check_len(
+        what: &'static str,
+        bytes: &[u8],
+        needed: usize,
+    ) -> Result<(), Self> {
+        if bytes.len() < needed {
+            return Err(Self::Truncated {
+                what,
+                offset: 0,
+                needed,
+                available: bytes.len(),
+            });
+        }
+        Ok(())
+    }
+}
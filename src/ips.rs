@@ -0,0 +1,413 @@
+/*!
+ * IPS patches, to share changes to the rom without sharing the rom.
+ *
+ * A patch is `PATCH`, a list of records and `EOF`, optionally followed by
+ * the size to truncate the file to. Each record writes bytes at an offset,
+ * or repeats a single byte (RLE) when its size is 0:
+ *
+ *  data:  O O O   S S   D ...
+ *  rle:   O O O   0 0   L L   V
+ *
+ * with a 24 bit offset, 16 bit size (or length) and the data (or value).
+ */
+
+use anyhow::Result;
+
+use crate::*;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+
+/// Largest offset a record can write to, offsets are 24 bits.
+pub const IPS_MAX_OFFSET: usize = 0xffffff;
+
+/// Largest number of bytes a single record can write.
+pub const IPS_MAX_RECORD_SIZE: usize = 0xffff;
+
+/// Offset that can't start a record, as it reads as the `EOF` marker.
+const EOF_OFFSET: usize = 0x454f46;
+
+/// Bytes a record needs besides its data: 3 for the offset and 2 the size.
+const RECORD_HEADER_SIZE: usize = 5;
+
+/*
+ * Unchanged bytes between two changes are written too when that is
+ * shorter than starting a new record, and runs of a single byte are only
+ * worth an RLE record (8 bytes) when they would also split a record.
+ */
+const MAX_MERGED_GAP: usize = RECORD_HEADER_SIZE;
+const MIN_RLE_RUN: usize = 8 + RECORD_HEADER_SIZE;
+
+/// A single change made by an IPS patch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IpsRecord {
+    /// Write `data` starting at `offset`.
+    Data { offset: usize, data: Vec<u8> },
+    /// Write `value` `len` times starting at `offset`.
+    Rle { offset: usize, len: usize, value: u8 },
+}
+
+impl IpsRecord {
+    pub fn offset(&self) -> usize {
+        match self {
+            Self::Data { offset, .. } | Self::Rle { offset, .. } => *offset,
+        }
+    }
+
+    /// Get the number of bytes this record writes.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Data { data, .. } => data.len(),
+            Self::Rle { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>) {
+        let end = self.offset() + self.len();
+        if bytes.len() < end {
+            bytes.resize(end, 0);
+        }
+        match self {
+            Self::Data { offset, data } => {
+                bytes[*offset..end].copy_from_slice(data)
+            }
+            Self::Rle { offset, value, .. } => bytes[*offset..end].fill(*value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IpsPatch {
+    pub records: Vec<IpsRecord>,
+    /// Size the patched file is cut down to, after every record.
+    pub truncate: Option<usize>,
+}
+
+impl IpsPatch {
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if !bytes.starts_with(IPS_MAGIC) {
+            return Err(Error::InvalidHeader {
+                what: "ips patch",
+                offset: 0,
+                reason: "missing PATCH magic".into(),
+            });
+        }
+
+        let mut records = vec![];
+        let mut idx = IPS_MAGIC.len();
+        loop {
+            let rest = &bytes[idx..];
+            if rest.starts_with(IPS_EOF) {
+                idx += IPS_EOF.len();
+                break;
+            }
+
+            Error::check_len("ips record", rest, RECORD_HEADER_SIZE)
+                .map_err(|error| error.offset_by(idx))?;
+            let offset = u32::from_be_bytes([0, rest[0], rest[1], rest[2]]);
+            let size = u16::from_be_bytes([rest[3], rest[4]]) as usize;
+            let offset = offset as usize;
+
+            let record = if size == 0 {
+                Error::check_len(
+                    "ips rle record",
+                    rest,
+                    RECORD_HEADER_SIZE + 3,
+                )
+                .map_err(|error| error.offset_by(idx))?;
+                let len = u16::from_be_bytes([rest[5], rest[6]]) as usize;
+                idx += RECORD_HEADER_SIZE + 3;
+                IpsRecord::Rle { offset, len, value: rest[7] }
+            } else {
+                Error::check_len("ips record", rest, RECORD_HEADER_SIZE + size)
+                    .map_err(|error| error.offset_by(idx))?;
+                let data = rest[RECORD_HEADER_SIZE..][..size].to_vec();
+                idx += RECORD_HEADER_SIZE + size;
+                IpsRecord::Data { offset, data }
+            };
+            records.push(record);
+        }
+
+        // the truncation extension, a 24 bit size after EOF
+        let truncate = match &bytes[idx..] {
+            [] => None,
+            [a, b, c] => Some(u32::from_be_bytes([0, *a, *b, *c]) as usize),
+            rest => {
+                return Err(Error::InvalidHeader {
+                    what: "ips patch",
+                    offset: idx,
+                    reason: format!(
+                        "{} unexpected bytes after EOF",
+                        rest.len()
+                    ),
+                })
+            }
+        };
+
+        Ok(Self { records, truncate })
+    }
+
+    /// Panics on malformed patches, see `IpsPatch::try_from_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::try_from_bytes(bytes).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /**
+     * Encode the patch, see `IpsPatch::diff` for the limits on records.
+     *
+     * Data records with no data are left out, as they write nothing and
+     * their size of 0 would read back as an RLE record.
+     */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = IPS_MAGIC.to_vec();
+        let records = self.records.iter().filter(|record| {
+            !matches!(record, IpsRecord::Data { data, .. } if data.is_empty())
+        });
+        for record in records {
+            bytes.extend(&(record.offset() as u32).to_be_bytes()[1..]);
+            match record {
+                IpsRecord::Data { data, .. } => {
+                    bytes.extend((data.len() as u16).to_be_bytes());
+                    bytes.extend(data);
+                }
+                IpsRecord::Rle { len, value, .. } => {
+                    bytes.extend([0, 0]);
+                    bytes.extend((*len as u16).to_be_bytes());
+                    bytes.push(*value);
+                }
+            }
+        }
+        bytes.extend(IPS_EOF);
+        if let Some(truncate) = self.truncate {
+            bytes.extend(&(truncate as u32).to_be_bytes()[1..]);
+        }
+        bytes
+    }
+
+    /**
+     * Find the records that turn `source` into `target`.
+     *
+     * Runs of a single byte become RLE records. Targets larger than the
+     * 16 MiB an IPS patch can address are an error.
+     */
+    pub fn diff(source: &[u8], target: &[u8]) -> Result<Self, Error> {
+        if target.len() > IPS_MAX_OFFSET + 1 {
+            return Err(Error::InvalidValue {
+                what: "ips target size",
+                value: format!("{:#x}", target.len()),
+            });
+        }
+
+        let changed = |idx: usize| source.get(idx) != Some(&target[idx]);
+
+        let mut records = vec![];
+        let mut idx = 0;
+        while idx < target.len() {
+            if !changed(idx) {
+                idx += 1;
+                continue;
+            }
+
+            // extend the change over any short gaps of unchanged bytes
+            let start = idx;
+            let mut end = idx + 1;
+            while end < target.len() {
+                let gap = (end..target.len()).take_while(|&i| !changed(i));
+                let gap = gap.count();
+                if gap > MAX_MERGED_GAP || end + gap == target.len() {
+                    break;
+                }
+                end += gap;
+                end += (end..target.len()).take_while(|&i| changed(i)).count();
+            }
+
+            Self::push_records(&mut records, target, start, end);
+            idx = end;
+        }
+
+        let truncate = (target.len() < source.len()).then_some(target.len());
+        Ok(Self { records, truncate })
+    }
+
+    /// Add records writing `target[start..end]`, using RLE for long runs.
+    fn push_records(
+        records: &mut Vec<IpsRecord>,
+        target: &[u8],
+        start: usize,
+        end: usize,
+    ) {
+        let mut data_start = start;
+        let mut idx = start;
+        while idx < end {
+            let value = target[idx];
+            let run = target[idx..end].iter().take_while(|&&b| b == value);
+            let run = run.count().min(IPS_MAX_RECORD_SIZE);
+
+            if run < MIN_RLE_RUN || idx == EOF_OFFSET {
+                idx += 1;
+                continue;
+            }
+
+            Self::push_data(records, target, data_start, idx);
+            records.push(IpsRecord::Rle { offset: idx, len: run, value });
+            idx += run;
+            data_start = idx;
+        }
+        Self::push_data(records, target, data_start, end);
+    }
+
+    fn push_data(
+        records: &mut Vec<IpsRecord>,
+        target: &[u8],
+        mut start: usize,
+        end: usize,
+    ) {
+        while start < end {
+            // a record can't start at the offset that reads as EOF
+            if start == EOF_OFFSET {
+                start -= 1;
+            }
+            let len = (end - start).min(IPS_MAX_RECORD_SIZE);
+            let data = target[start..start + len].to_vec();
+            records.push(IpsRecord::Data { offset: start, data });
+            start += len;
+        }
+    }
+
+    /// Apply every record to `bytes`, growing it when records write past it.
+    pub fn apply(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut bytes = bytes.to_vec();
+        for record in &self.records {
+            record.write(&mut bytes);
+        }
+        if let Some(truncate) = self.truncate {
+            bytes.truncate(truncate);
+        }
+        bytes
+    }
+}
+
+impl Rom {
+    /// Create the patch turning this rom into `target`.
+    pub fn create_ips(&self, target: &Rom) -> Result<IpsPatch> {
+        Ok(IpsPatch::diff(&self.rom_data, &target.rom_data)?)
+    }
+
+    /**
+     * Apply a patch made for this rom.
+     *
+     * The patched rom goes through the same checks as any other rom. A
+     * patch is expected to change the checksums, so those are only
+     * reported by `Rom::failed_checks`, but one that breaks the cartridge
+     * layout is refused.
+     */
+    pub fn apply_ips(&self, patch: &IpsPatch) -> Result<Rom> {
+        Rom::new_patched(patch.apply(&self.rom_data))
+    }
+
+    /// Load patched rom data, refusing data that isn't laid out like the game.
+    pub(crate) fn new_patched(rom_data: Vec<u8>) -> Result<Rom> {
+        let rom = Rom::new_unverified(rom_data)?;
+        let layout_error = rom
+            .failed_checks()
+            .iter()
+            .find(|error| matches!(error, Error::InvalidHeader { .. }));
+        if let Some(error) = layout_error {
+            return Err(error.clone().into());
+        }
+        Ok(rom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ips_round_trip() {
+        let bytes = [
+            b"PATCH".as_slice(),
+            &[0x00, 0x00, 0x10, 0x00, 0x02, 0xaa, 0xbb],
+            &[0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x20, 0xff],
+            b"EOF",
+        ]
+        .concat();
+        let patch = IpsPatch::from_bytes(&bytes);
+        assert_eq!(
+            patch.records,
+            [
+                IpsRecord::Data { offset: 0x10, data: vec![0xaa, 0xbb] },
+                IpsRecord::Rle { offset: 0x100, len: 0x20, value: 0xff },
+            ]
+        );
+        assert_eq!(patch.truncate, None);
+        assert_eq!(patch.to_bytes(), bytes);
+
+        let truncated = [&bytes[..], &[0x00, 0x01, 0x00]].concat();
+        let patch = IpsPatch::from_bytes(&truncated);
+        assert_eq!(patch.truncate, Some(0x100));
+        assert_eq!(patch.to_bytes(), truncated);
+
+        assert!(IpsPatch::try_from_bytes(b"PATCH\x00\x00\x10\x00").is_err());
+        assert!(
+            IpsPatch::try_from_bytes(b"PATCH\x00\x00\x10\x00\x02\xaa").is_err()
+        );
+        assert!(IpsPatch::try_from_bytes(b"PATCH").is_err());
+
+        // an empty data record would read back as rle, so it's left out
+        let mut patch = IpsPatch::from_bytes(&bytes);
+        patch.records.push(IpsRecord::Data { offset: 0x20, data: vec![] });
+        assert_eq!(patch.to_bytes(), bytes);
+        assert!(IpsPatch::try_from_bytes(b"PATCHEOF\x00").is_err());
+        assert!(IpsPatch::try_from_bytes(b"PATCX").is_err());
+    }
+
+    #[test]
+    fn test_ips_diff() {
+        let source = vec![0; 0x100];
+        let mut target = source.clone();
+        // two changes close enough for one record, then a long run
+        target[0x10] = 1;
+        target[0x13] = 2;
+        target[0x40..0x80].fill(0x24);
+        target.push(3);
+
+        let patch = IpsPatch::diff(&source, &target).unwrap();
+        assert_eq!(
+            patch.records,
+            [
+                IpsRecord::Data { offset: 0x10, data: vec![1, 0, 0, 2] },
+                IpsRecord::Rle { offset: 0x40, len: 0x40, value: 0x24 },
+                IpsRecord::Data { offset: 0x100, data: vec![3] },
+            ]
+        );
+        assert_eq!(patch.apply(&source), target);
+
+        let patch = IpsPatch::diff(&target, &source).unwrap();
+        assert_eq!(patch.truncate, Some(0x100));
+        assert_eq!(patch.apply(&target), source);
+
+        let patch = IpsPatch::diff(&source, &source).unwrap();
+        assert!(patch.records.is_empty());
+        assert_eq!(patch.to_bytes(), b"PATCHEOF");
+    }
+
+    #[test]
+    fn test_ips_eof_offset() {
+        let source = vec![0; EOF_OFFSET + 0x10];
+        let mut target = source.clone();
+        target[EOF_OFFSET] = 1;
+
+        let patch = IpsPatch::diff(&source, &target).unwrap();
+        assert_eq!(
+            patch.records,
+            [IpsRecord::Data { offset: EOF_OFFSET - 1, data: vec![0, 1] }]
+        );
+        let bytes = patch.to_bytes();
+        assert_eq!(IpsPatch::from_bytes(&bytes).apply(&source), target);
+    }
+}
@@ -0,0 +1,50 @@
+mod display;
+mod enemy_data;
+mod grid;
+mod header;
+mod object_data;
+mod position;
+#[cfg(feature = "serde")]
+mod text;
+
+pub use display::*;
+pub use enemy_data::*;
+pub use grid::*;
+pub use header::*;
+pub use object_data::*;
+pub use position::*;
+
+use crate::Error;
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Level {
+    pub level_header: LevelHeader,
+    pub object_data: LevelObjectData,
+    pub enemy_data: LevelEnemyData,
+}
+
+impl Level {
+    /**
+     * Check every object and enemy can be encoded.
+     *
+     * Levels parsed from the rom always can, levels edited by hand (or
+     * loaded from text) should be checked before they are encoded.
+     */
+    pub fn validate(&self) -> Result<(), Error> {
+        self.object_data.validate()?;
+        self.enemy_data.validate()
+    }
+
+    /// Encode the header and object data, as stored in the rom.
+    pub fn area_data_to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.level_header.to_bytes().to_vec();
+        bytes.extend(self.object_data.to_bytes());
+        bytes
+    }
+
+    /// Encode the enemy data, as stored in the rom.
+    pub fn enemy_data_to_bytes(&self) -> Vec<u8> {
+        self.enemy_data.to_bytes()
+    }
+}
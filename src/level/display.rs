@@ -0,0 +1,212 @@
+use std::fmt;
+
+use crate::*;
+
+/// Resets the colors set by an ANSI escape code.
+const ANSI_RESET: &str = "\x1b[0m";
+
+/**
+ * A character and 256 color ANSI background for a metatile.
+ *
+ * Scenery is shown as `.` without a color, anything unknown as `*`.
+ */
+fn metatile_style(metatile: u8) -> (char, Option<u8>) {
+    match metatile {
+        0x00 => (' ', None),
+        // scenery: bushes, hills, trees, fences and clouds
+        0x02..=0x0a | 0x0d..=0x0f | 0x4d | 0x4e | 0x80..=0x85 => ('.', None),
+        0x54 | 0x62 | 0x69 => ('#', Some(130)),
+        0x22 | 0x51 | 0x52 | 0x55..=0x5e => ('B', Some(166)),
+        0x61 => ('X', Some(137)),
+        0xc0 | 0xc1 => ('?', Some(214)),
+        0x5f | 0x60 => (',', None),
+        0xc2 | 0xc3 => ('o', Some(220)),
+        0xc4 => ('U', Some(94)),
+        0x10..=0x13 => ('T', Some(34)),
+        0x14 | 0x15 | 0x1c..=0x21 | 0x6b | 0x6c => ('P', Some(28)),
+        0x16..=0x1b => ('-', Some(70)),
+        0x4c | 0x4f | 0x50 => (':', Some(58)),
+        0x64..=0x66 => ('C', Some(238)),
+        0x24 | 0x25 => ('|', Some(252)),
+        0x0b | 0x63 | 0x89 => ('=', Some(130)),
+        0x0c => ('~', Some(245)),
+        0x45..=0x4b => ('%', Some(245)),
+        0x86 | 0x87 => ('~', Some(27)),
+        0x88 => ('@', Some(255)),
+        0x40..=0x44 => ('|', Some(250)),
+        0x67 | 0x68 => ('S', Some(160)),
+        0xc5 => ('A', Some(220)),
+        _ => ('*', Some(201)),
+    }
+}
+
+/// A letter for an enemy and whether it stands on top of its row.
+fn enemy_style(kind: &LevelEnemyKind) -> Option<(char, bool)> {
+    use LevelEnemyKind::*;
+
+    let style = match kind {
+        Goomba | TwoGoombasY10 | ThreeGoombasY10 | TwoGoombasY6
+        | ThreeGoombasY6 => ('G', true),
+        GreenKoopaTroopa
+        | RedKoopaTroopaFallsOffEdges
+        | RedKoopaTroopa
+        | GreenKoopaTroopaStationary
+        | TwoKoopaTroopasY10
+        | ThreeKoopaTroopasY10
+        | TwoKoopaTroopasY6
+        | ThreeKoopaTroopasY6 => ('K', true),
+        YellowKoopaParatroopa
+        | GreenKoopaParatroopaJumping
+        | RedKoopaParatroopaVertical
+        | GreenKoopaParatroopaHorizontal => ('P', true),
+        BuzzyBeetle => ('B', true),
+        HammerBro => ('H', true),
+        Blooper => ('Q', true),
+        BulletBill => ('W', true),
+        GreyCheepCheep | RedCheepCheep => ('C', true),
+        Podoboo => ('O', true),
+        PiranhaPlant => ('V', true),
+        Lakitu => ('L', true),
+        Spiny => ('S', true),
+        Bowser => ('X', true),
+        ToadOrPrincess => ('T', true),
+        FireBarClockwise
+        | FastFireBarClockwise
+        | FireBarCounterClockwise
+        | FastFireBarCounterClockwise
+        | LongFireBarClockwise => ('F', false),
+        BalanceLift | LiftUpAndDown | LiftUp | LiftDown | LiftLeftAndRight
+        | FallingLift | LiftRight | ShortLiftUp | ShortLiftDown => ('E', false),
+        _ => return None,
+    };
+    Some(style)
+}
+
+/// Columns and rows of every enemy a group spawns, from its position.
+fn enemy_cells(kind: &LevelEnemyKind, x: usize, row: u8) -> Vec<(usize, u8)> {
+    use LevelEnemyKind::*;
+
+    // groups are spawned 24 pixels (1.5 columns) apart on a fixed row
+    let (count, row) = match kind {
+        TwoGoombasY10 | TwoKoopaTroopasY10 => (2, 0xb),
+        ThreeGoombasY10 | ThreeKoopaTroopasY10 => (3, 0xb),
+        TwoGoombasY6 | TwoKoopaTroopasY6 => (2, 0x7),
+        ThreeGoombasY6 | ThreeKoopaTroopasY6 => (3, 0x7),
+        _ => (1, row),
+    };
+    (0..count).map(|idx| (x + idx * 3 / 2, row)).collect()
+}
+
+/**
+ * Prints a level's metatile grid as text, see `Level::display`.
+ *
+ * A line of page numbers comes first, then one line per row of metatiles
+ * with one character per metatile. Enemies are drawn over the metatiles
+ * with capital letters.
+ */
+pub struct LevelDisplay<'a> {
+    level: &'a Level,
+    area_type: AreaType,
+    ansi: bool,
+}
+
+impl<'a> LevelDisplay<'a> {
+    /// Color each metatile with ANSI escape codes, for terminals.
+    pub fn ansi(mut self, ansi: bool) -> Self {
+        self.ansi = ansi;
+        self
+    }
+}
+
+impl fmt::Display for LevelDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let grid = LevelGrid::new(self.level, self.area_type);
+
+        let mut rows: Vec<Vec<(char, Option<u8>)>> = (0..GRID_ROWS)
+            .map(|row| grid.row(row).map(metatile_style).collect())
+            .collect();
+
+        for (position, entry) in self.level.enemy_data.with_positions() {
+            let LevelEnemyDataEntry::Enemy(enemy) = entry else {
+                continue;
+            };
+            let Some((letter, stands)) = enemy_style(&enemy.kind) else {
+                continue;
+            };
+            let cells =
+                enemy_cells(&enemy.kind, position.x() as usize, position.row);
+            for (x, row) in cells {
+                let row = if stands { row.checked_sub(1) } else { Some(row) };
+                let cell =
+                    row.and_then(|row| rows.get_mut(row as usize)?.get_mut(x));
+                if let Some(cell) = cell {
+                    *cell = (letter, Some(196));
+                }
+            }
+        }
+
+        // page numbers over the first column of each page
+        for page in 0..grid.width() / 16 {
+            write!(f, "{:<16}", format!("{:x}", page))?;
+        }
+        writeln!(f)?;
+
+        for row in rows {
+            for (letter, color) in row {
+                match (self.ansi, color) {
+                    (true, Some(color)) => write!(
+                        f,
+                        "\x1b[48;5;{}m{}{}",
+                        color, letter, ANSI_RESET
+                    )?,
+                    _ => write!(f, "{}", letter)?,
+                }
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Level {
+    /**
+     * Print this level as text, the same way `Path::display` works.
+     *
+     * The area type picks the metatiles used for terrain and bricks.
+     */
+    pub fn display(&self, area_type: AreaType) -> LevelDisplay<'_> {
+        LevelDisplay { level: self, area_type, ansi: false }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let level = Level {
+            level_header: LevelHeader::from_bytes(&[0x00, 0x01]),
+            // a question block and a pipe
+            object_data: LevelObjectData::from_bytes(&[
+                0x23, 0x00, 0x49, 0x71, 0xfd,
+            ]),
+            // a goomba on the floor and a group of two more
+            enemy_data: LevelEnemyData::from_bytes(&[
+                0x8b, 0x06, 0xa0, 0x37, 0xff,
+            ]),
+        };
+        let text = level.display(AreaType::Ground).to_string();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 1 + GRID_ROWS);
+        assert_eq!(lines[0], format!("{:<16}", 0));
+        assert_eq!(lines[4], "  ?             ");
+        assert_eq!(lines[10], "    TT          ");
+        assert_eq!(lines[11], "    PP  G GG    ");
+        assert_eq!(lines[12], "#".repeat(16));
+
+        let ansi = level.display(AreaType::Ground).ansi(true).to_string();
+        assert!(ansi.contains("\x1b[48;5;214m?\x1b[0m"));
+    }
+}
@@ -1,18 +1,37 @@
+use crate::*;
+
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LevelEnemyData {
     pub enemies: Vec<LevelEnemy>,
     pub pipe_pointers: Vec<PipePointer>,
+    /// Number of enemies before each pipe pointer, as the two share the
+    /// enemy data and the game reads them in order.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pipe_pointer_indices: Vec<usize>,
+}
+
+/// An enemy or pipe pointer, see `LevelEnemyData::entries`.
+#[derive(Debug, Clone, Copy)]
+pub enum LevelEnemyDataEntry<'a> {
+    Enemy(&'a LevelEnemy),
+    PipePointer(&'a PipePointer),
 }
 
 impl LevelEnemyData {
-    pub fn from_bytes(bytes: &[u8]) -> Self {
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
         let mut enemies = vec![];
         let mut pipe_pointers = vec![];
+        let mut pipe_pointer_indices = vec![];
 
         // process byte-by-byte
         let mut idx = 0;
         loop {
-            let byte = bytes[idx];
+            let byte = *bytes.get(idx).ok_or(Error::MissingTerminator {
+                what: "enemy data",
+                offset: 0,
+                terminator: 0xFF,
+            })?;
 
             // 0xFF is the end enemy marker
             if byte == 0xFF {
@@ -21,98 +40,577 @@ impl LevelEnemyData {
 
             if (byte & 0x0F) == 0x0E {
                 // pipe pointer (3 bytes)
-                pipe_pointers.push(PipePointer::from_bytes(&bytes[idx..]));
+                let pipe_pointer = PipePointer::try_from_bytes(&bytes[idx..])
+                    .map_err(|error| error.offset_by(idx))?;
+                pipe_pointers.push(pipe_pointer);
+                pipe_pointer_indices.push(enemies.len());
                 idx += 3;
             } else {
                 // enemy pointer (2 bytes)
-                enemies.push(LevelEnemy::from_bytes(&bytes[idx..]));
+                let enemy = LevelEnemy::try_from_bytes(&bytes[idx..])
+                    .map_err(|error| error.offset_by(idx))?;
+                enemies.push(enemy);
                 idx += 2;
             };
         }
 
-        Self { enemies, pipe_pointers }
+        Ok(Self { enemies, pipe_pointers, pipe_pointer_indices })
+    }
+
+    /// Panics on truncated data, see `LevelEnemyData::try_from_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::try_from_bytes(bytes).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for entry in self.entries() {
+            match entry {
+                LevelEnemyDataEntry::Enemy(enemy) => {
+                    bytes.extend(enemy.to_bytes())
+                }
+                LevelEnemyDataEntry::PipePointer(pipe_pointer) => {
+                    bytes.extend(pipe_pointer.to_bytes())
+                }
+            }
+        }
+
+        // 0xFF is the end enemy marker
+        bytes.push(0xFF);
+
+        bytes
+    }
+
+    /**
+     * Get the enemies and pipe pointers in rom order. Pipe pointers without
+     * an index in `pipe_pointer_indices` come after every enemy.
+     */
+    pub fn entries(&self) -> Vec<LevelEnemyDataEntry<'_>> {
+        let mut entries: Vec<_> =
+            self.enemies.iter().map(LevelEnemyDataEntry::Enemy).collect();
+        let indices = self
+            .pipe_pointer_indices
+            .iter()
+            .copied()
+            .chain(std::iter::repeat(usize::MAX));
+
+        // inserted back to front, so earlier indices are still enemy counts
+        let mut pipe_pointers: Vec<_> =
+            self.pipe_pointers.iter().zip(indices).collect();
+        pipe_pointers.sort_by_key(|&(_, index)| index);
+        for (pipe_pointer, index) in pipe_pointers.into_iter().rev() {
+            let entry = LevelEnemyDataEntry::PipePointer(pipe_pointer);
+            entries.insert(index.min(self.enemies.len()), entry);
+        }
+        entries
+    }
+
+    /// Get every entry in rom order along with its position in the area.
+    pub fn with_positions(
+        &self,
+    ) -> impl Iterator<Item = (LevelPosition, LevelEnemyDataEntry<'_>)> {
+        let mut cursor = PageCursor::new();
+        self.entries().into_iter().map(move |entry| {
+            let position = match entry {
+                LevelEnemyDataEntry::Enemy(enemy) => cursor.advance(
+                    enemy.new_page_flag,
+                    enemy.page_skip(),
+                    enemy.x_coordinate,
+                    enemy.y_coordinate,
+                ),
+                LevelEnemyDataEntry::PipePointer(pipe_pointer) => cursor
+                    .advance(
+                        pipe_pointer.new_page_flag,
+                        None,
+                        pipe_pointer.x_coordinate,
+                        pipe_pointer.y_coordinate,
+                    ),
+            };
+            (position, entry)
+        })
+    }
+
+    /// Check every enemy and pipe pointer, see `LevelEnemy::validate`.
+    pub fn validate(&self) -> Result<(), Error> {
+        for enemy in &self.enemies {
+            enemy.validate()?;
+        }
+        for pipe_pointer in &self.pipe_pointers {
+            pipe_pointer.validate()?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LevelEnemyKind {
-    QuestionBlockPowerup,
-    QuestionBlockCoin,
-    HiddenBlockCoin,
-    HiddenBlockExtraLife,
-    BrickPowerup,
-    BrickVine,
-    BrickStar,
-    BrickMultiCoinBlock,
-    BrickExtraLife,
-    SidewaysPipe,
-    UsedBlock,
-    Spring,
-    ReverseLPipe,
-    FlagPole,
-    CastleBridge,
-    Nothing,
-    IslandOrCannon,
-    HorizontalBrick,
-    HorizontalBlock,
-    HorizontalCoins,
-    VerticalBrick,
-    VerticalBlock,
-    PipeNoEntry,
-    PipeEntry,
-    Hole,
-    BalanceHorizontalRope,
-    BridgeV7,
-    BridgeV8,
-    BridgeV10,
-    FilledHole,
-    HorizontalQuestionBlockV3,
-    HorizontalQuestionBlockV7,
-    PageSkip,
-    CastleAxe,
-    AxeRope,
-    ScrollStop,
+    GreenKoopaTroopa,
+    RedKoopaTroopaFallsOffEdges,
+    BuzzyBeetle,
+    RedKoopaTroopa,
+    GreenKoopaTroopaStationary,
+    HammerBro,
+    Goomba,
+    Blooper,
+    BulletBill,
+    YellowKoopaParatroopa,
+    GreyCheepCheep,
     RedCheepCheep,
-    ContinuousBulletBillsOrCheepCheeps,
-    StopContinuation,
-    LoopCommand,
-    DoNotUse,
+    Podoboo,
+    PiranhaPlant,
+    GreenKoopaParatroopaJumping,
+    RedKoopaParatroopaVertical,
+    GreenKoopaParatroopaHorizontal,
+    Lakitu,
+    Spiny,
+    FlyingCheepCheepGenerator,
+    BowserFireGenerator,
+    Fireworks,
+    BulletBillOrCheepCheepGenerator,
+    StopGenerators,
+    FireBarClockwise,
+    FastFireBarClockwise,
+    FireBarCounterClockwise,
+    FastFireBarCounterClockwise,
+    LongFireBarClockwise,
+    BalanceLift,
+    LiftUpAndDown,
+    LiftUp,
+    LiftDown,
+    LiftLeftAndRight,
+    FallingLift,
+    LiftRight,
+    ShortLiftUp,
+    ShortLiftDown,
+    Bowser,
+    WarpZone,
+    ToadOrPrincess,
+    TwoGoombasY10,
+    ThreeGoombasY10,
+    TwoGoombasY6,
+    ThreeGoombasY6,
+    TwoKoopaTroopasY10,
+    ThreeKoopaTroopasY10,
+    TwoKoopaTroopasY6,
+    ThreeKoopaTroopasY6,
+    PageSkip(u8),
+    Undefined(u8),
+}
+
+impl LevelEnemyKind {
+    /// `byte` is the second byte without the new page and hard mode flags.
+    pub fn new(y_coordinate: u8, byte: u8) -> Self {
+        match (y_coordinate, byte) {
+            // Y offset 0xf
+            (0xf, _) => Self::PageSkip(byte),
+
+            (_, 0x00) => Self::GreenKoopaTroopa,
+            (_, 0x01) => Self::RedKoopaTroopaFallsOffEdges,
+            (_, 0x02) => Self::BuzzyBeetle,
+            (_, 0x03) => Self::RedKoopaTroopa,
+            (_, 0x04) => Self::GreenKoopaTroopaStationary,
+            (_, 0x05) => Self::HammerBro,
+            (_, 0x06) => Self::Goomba,
+            (_, 0x07) => Self::Blooper,
+            (_, 0x08) => Self::BulletBill,
+            (_, 0x09) => Self::YellowKoopaParatroopa,
+            (_, 0x0a) => Self::GreyCheepCheep,
+            (_, 0x0b) => Self::RedCheepCheep,
+            (_, 0x0c) => Self::Podoboo,
+            (_, 0x0d) => Self::PiranhaPlant,
+            (_, 0x0e) => Self::GreenKoopaParatroopaJumping,
+            (_, 0x0f) => Self::RedKoopaParatroopaVertical,
+            (_, 0x10) => Self::GreenKoopaParatroopaHorizontal,
+            (_, 0x11) => Self::Lakitu,
+            (_, 0x12) => Self::Spiny,
+            (_, 0x14) => Self::FlyingCheepCheepGenerator,
+            (_, 0x15) => Self::BowserFireGenerator,
+            (_, 0x16) => Self::Fireworks,
+            (_, 0x17) => Self::BulletBillOrCheepCheepGenerator,
+            (_, 0x18) => Self::StopGenerators,
+            (_, 0x1b) => Self::FireBarClockwise,
+            (_, 0x1c) => Self::FastFireBarClockwise,
+            (_, 0x1d) => Self::FireBarCounterClockwise,
+            (_, 0x1e) => Self::FastFireBarCounterClockwise,
+            (_, 0x1f) => Self::LongFireBarClockwise,
+            (_, 0x24) => Self::BalanceLift,
+            (_, 0x25) => Self::LiftUpAndDown,
+            (_, 0x26) => Self::LiftUp,
+            (_, 0x27) => Self::LiftDown,
+            (_, 0x28) => Self::LiftLeftAndRight,
+            (_, 0x29) => Self::FallingLift,
+            (_, 0x2a) => Self::LiftRight,
+            (_, 0x2b) => Self::ShortLiftUp,
+            (_, 0x2c) => Self::ShortLiftDown,
+            (_, 0x2d) => Self::Bowser,
+            (_, 0x34) => Self::WarpZone,
+            (_, 0x35) => Self::ToadOrPrincess,
+            (_, 0x37) => Self::TwoGoombasY10,
+            (_, 0x38) => Self::ThreeGoombasY10,
+            (_, 0x39) => Self::TwoGoombasY6,
+            (_, 0x3a) => Self::ThreeGoombasY6,
+            (_, 0x3b) => Self::TwoKoopaTroopasY10,
+            (_, 0x3c) => Self::ThreeKoopaTroopasY10,
+            (_, 0x3d) => Self::TwoKoopaTroopasY6,
+            (_, 0x3e) => Self::ThreeKoopaTroopasY6,
+
+            _ => Self::Undefined(byte),
+        }
+    }
+
+    /// Get the enemy byte (without the new page and hard mode flags).
+    pub fn value(&self) -> u8 {
+        match self {
+            Self::GreenKoopaTroopa => 0x00,
+            Self::RedKoopaTroopaFallsOffEdges => 0x01,
+            Self::BuzzyBeetle => 0x02,
+            Self::RedKoopaTroopa => 0x03,
+            Self::GreenKoopaTroopaStationary => 0x04,
+            Self::HammerBro => 0x05,
+            Self::Goomba => 0x06,
+            Self::Blooper => 0x07,
+            Self::BulletBill => 0x08,
+            Self::YellowKoopaParatroopa => 0x09,
+            Self::GreyCheepCheep => 0x0a,
+            Self::RedCheepCheep => 0x0b,
+            Self::Podoboo => 0x0c,
+            Self::PiranhaPlant => 0x0d,
+            Self::GreenKoopaParatroopaJumping => 0x0e,
+            Self::RedKoopaParatroopaVertical => 0x0f,
+            Self::GreenKoopaParatroopaHorizontal => 0x10,
+            Self::Lakitu => 0x11,
+            Self::Spiny => 0x12,
+            Self::FlyingCheepCheepGenerator => 0x14,
+            Self::BowserFireGenerator => 0x15,
+            Self::Fireworks => 0x16,
+            Self::BulletBillOrCheepCheepGenerator => 0x17,
+            Self::StopGenerators => 0x18,
+            Self::FireBarClockwise => 0x1b,
+            Self::FastFireBarClockwise => 0x1c,
+            Self::FireBarCounterClockwise => 0x1d,
+            Self::FastFireBarCounterClockwise => 0x1e,
+            Self::LongFireBarClockwise => 0x1f,
+            Self::BalanceLift => 0x24,
+            Self::LiftUpAndDown => 0x25,
+            Self::LiftUp => 0x26,
+            Self::LiftDown => 0x27,
+            Self::LiftLeftAndRight => 0x28,
+            Self::FallingLift => 0x29,
+            Self::LiftRight => 0x2a,
+            Self::ShortLiftUp => 0x2b,
+            Self::ShortLiftDown => 0x2c,
+            Self::Bowser => 0x2d,
+            Self::WarpZone => 0x34,
+            Self::ToadOrPrincess => 0x35,
+            Self::TwoGoombasY10 => 0x37,
+            Self::ThreeGoombasY10 => 0x38,
+            Self::TwoGoombasY6 => 0x39,
+            Self::ThreeGoombasY6 => 0x3a,
+            Self::TwoKoopaTroopasY10 => 0x3b,
+            Self::ThreeKoopaTroopasY10 => 0x3c,
+            Self::TwoKoopaTroopasY6 => 0x3d,
+            Self::ThreeKoopaTroopasY6 => 0x3e,
+
+            Self::PageSkip(byte) => *byte,
+            Self::Undefined(byte) => *byte,
+        }
+    }
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LevelEnemy {
     pub kind: LevelEnemyKind,
     pub x_coordinate: u8,
     pub y_coordinate: u8,
     pub new_page_flag: bool,
+    /// Only spawned in hard mode (later worlds and the second quest).
+    pub hard_mode: bool,
 }
 
 impl LevelEnemy {
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        assert!(bytes.len() >= 2);
-        let x_coordinate = bytes[0] << 4;
+    /**
+     * XXXXYYYY PHOOOOOO
+     */
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Error::check_len("level enemy", bytes, 2)?;
+        let x_coordinate = bytes[0] >> 4;
         let y_coordinate = bytes[0] & 0b00001111;
-        let kind = LevelEnemyKind::CastleAxe;
         let new_page_flag = bytes[1] & 0b10000000 != 0;
+        let hard_mode = bytes[1] & 0b01000000 != 0;
+        let kind = LevelEnemyKind::new(y_coordinate, bytes[1] & 0b00111111);
+
+        Ok(Self { kind, x_coordinate, y_coordinate, new_page_flag, hard_mode })
+    }
+
+    /// Panics on truncated data, see `LevelEnemy::try_from_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::try_from_bytes(bytes).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /**
+     * XXXXYYYY PHOOOOOO
+     */
+    pub fn to_bytes(&self) -> [u8; 2] {
+        let byte0 = (self.x_coordinate << 4) | (self.y_coordinate & 0b00001111);
+        let byte1 = ((self.new_page_flag as u8) << 7)
+            | ((self.hard_mode as u8) << 6)
+            | self.kind.value();
+
+        [byte0, byte1]
+    }
 
-        Self { kind, x_coordinate, y_coordinate, new_page_flag }
+    /// Get the page this enemy skips to, if it is a page skip.
+    pub fn page_skip(&self) -> Option<u8> {
+        match self.kind {
+            LevelEnemyKind::PageSkip(page) => Some(page),
+            _ => None,
+        }
+    }
+
+    /// Check this enemy encodes to bytes that decode back to it.
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = || Error::InvalidValue {
+            what: "level enemy",
+            value: format!("{:?}", self),
+        };
+
+        // y 0xe is a pipe pointer and 0xFF the end enemy marker
+        let (x, y) = (self.x_coordinate, self.y_coordinate);
+        if x > 0xf || y > 0xf || y == 0xe || (x, y) == (0xf, 0xf) {
+            return Err(invalid());
+        }
+
+        match (0x00..=0x3f)
+            .any(|byte| LevelEnemyKind::new(y, byte) == self.kind)
+        {
+            true => Ok(()),
+            false => Err(invalid()),
+        }
     }
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PipePointer {
     pub x_coordinate: u8,
     pub y_coordinate: u8,
     pub new_page_flag: bool,
+    /// Area the player is sent to.
+    pub area: Area,
+    /// The (0-based) world this pointer is used in, ignored in other worlds.
+    pub world: u8,
+    /// Page of the destination area the player starts on.
+    pub page: u8,
 }
 
 impl PipePointer {
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        assert!(bytes.len() >= 3);
-        let x_coordinate = bytes[0] << 4;
+    /**
+     * XXXX1110 PAAAAAAA WWWPPPPP
+     */
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Error::check_len("pipe pointer", bytes, 3)?;
+        let x_coordinate = bytes[0] >> 4;
         let y_coordinate = bytes[0] & 0b00001111;
         let new_page_flag = bytes[1] & 0b10000000 != 0;
+        let area = Area::from_pointer(bytes[1]);
+        let world = bytes[2] >> 5;
+        let page = bytes[2] & 0b00011111;
+
+        Ok(Self {
+            x_coordinate,
+            y_coordinate,
+            new_page_flag,
+            area,
+            world,
+            page,
+        })
+    }
+
+    /// Panics on truncated data, see `PipePointer::try_from_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::try_from_bytes(bytes).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /**
+     * XXXX1110 PAAAAAAA WWWPPPPP
+     */
+    pub fn to_bytes(&self) -> [u8; 3] {
+        let byte0 = (self.x_coordinate << 4) | (self.y_coordinate & 0b00001111);
+        let byte1 = ((self.new_page_flag as u8) << 7) | self.area.pointer();
+        let byte2 = (self.world << 5) | (self.page & 0b00011111);
+
+        [byte0, byte1, byte2]
+    }
+
+    /// Check this pipe pointer encodes to bytes that decode back to it.
+    pub fn validate(&self) -> Result<(), Error> {
+        let valid = self.x_coordinate <= 0xf
+            && self.y_coordinate == 0xe
+            && self.area.index <= 0b00011111
+            && self.world <= 0b111
+            && self.page <= 0b00011111;
+        match valid {
+            true => Ok(()),
+            false => Err(Error::InvalidValue {
+                what: "pipe pointer",
+                value: format!("{:?}", self),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enemy_data_to_bytes() {
+        // columns set by hand, the rest decoded
+        let enemy = |bytes: [u8; 2]| LevelEnemy {
+            x_coordinate: bytes[0] >> 4,
+            ..LevelEnemy::from_bytes(&bytes)
+        };
+        let pipe_pointer = |bytes: [u8; 3]| PipePointer {
+            x_coordinate: bytes[0] >> 4,
+            ..PipePointer::from_bytes(&bytes)
+        };
 
-        Self { x_coordinate, y_coordinate, new_page_flag }
+        // a pipe pointer, enemies, an enemy page skip and another pipe
+        let mut enemy_data = LevelEnemyData {
+            enemies: vec![
+                enemy([0x6b, 0x06]),
+                enemy([0x8b, 0x86]),
+                enemy([0x2f, 0x05]),
+                enemy([0x07, 0x91]),
+            ],
+            pipe_pointers: vec![
+                pipe_pointer([0x1e, 0xc2, 0x00]),
+                pipe_pointer([0x4e, 0x21, 0x65]),
+            ],
+            pipe_pointer_indices: vec![0, 3],
+        };
+        let bytes = [
+            0x1e, 0xc2, 0x00, 0x6b, 0x06, 0x8b, 0x86, 0x2f, 0x05, 0x4e, 0x21,
+            0x65, 0x07, 0x91, 0xFF,
+        ];
+        assert_eq!(enemy_data.to_bytes(), bytes);
+
+        // pipe pointers without an index go last
+        enemy_data.pipe_pointer_indices.clear();
+        assert_eq!(
+            &enemy_data.to_bytes()[8..],
+            [0x1e, 0xc2, 0x00, 0x4e, 0x21, 0x65, 0xFF]
+        );
+    }
+
+    #[test]
+    fn test_enemy_data_round_trip() {
+        let bytes = [
+            0x1e, 0xc2, 0x00, 0x6b, 0x06, 0x8b, 0x86, 0x2f, 0x05, 0x4e, 0x21,
+            0x65, 0x07, 0x91, 0xFF,
+        ];
+        let enemy_data = LevelEnemyData::from_bytes(&bytes);
+        assert_eq!(enemy_data.enemies.len(), 4);
+        assert_eq!(enemy_data.pipe_pointers.len(), 2);
+        assert_eq!(enemy_data.pipe_pointer_indices, [0, 3]);
+        assert_eq!(enemy_data.enemies[0].x_coordinate, 6);
+        assert_eq!(enemy_data.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_enemy_data_truncated() {
+        assert_eq!(
+            LevelEnemyData::try_from_bytes(&[0x6b, 0x06]).unwrap_err(),
+            Error::MissingTerminator {
+                what: "enemy data",
+                offset: 0,
+                terminator: 0xFF
+            }
+        );
+        assert_eq!(
+            LevelEnemyData::try_from_bytes(&[0x6b, 0x06, 0x4e, 0xa1])
+                .unwrap_err(),
+            Error::Truncated {
+                what: "pipe pointer",
+                offset: 2,
+                needed: 3,
+                available: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_enemy_positions() {
+        let bytes = [
+            0x6b, 0x06, 0x8b, 0x86, 0x0f, 0x05, 0x4e, 0xa1, 0x65, 0x0f, 0x83,
+            0x27, 0x06, 0xFF,
+        ];
+        let enemy_data = LevelEnemyData::from_bytes(&bytes);
+        let positions: Vec<_> = enemy_data
+            .with_positions()
+            .map(|(position, _)| (position.page, position.column, position.row))
+            .collect();
+        assert_eq!(
+            positions,
+            [
+                (0, 6, 11),
+                (1, 8, 11),
+                (5, 0, 15),
+                (6, 4, 14),
+                (7, 0, 15),
+                (7, 2, 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pipe_pointer() {
+        // a pipe down into the underground bonus room, used in world 2
+        let pipe_pointer = PipePointer::from_bytes(&[0x0e, 0xc0, 0x25]);
+        assert_eq!(pipe_pointer.area, Area::new(AreaType::Underground, 0));
+        assert!(pipe_pointer.new_page_flag);
+        assert_eq!(pipe_pointer.world, 1);
+        assert_eq!(pipe_pointer.page, 5);
+        assert_eq!(pipe_pointer.to_bytes(), [0x0e, 0xc0, 0x25]);
     }
-      }
+
+    #[test]
+    fn test_enemy_round_trip() {
+        // every possible enemy (excluding pipe pointers and the 0xFF marker)
+        for byte0 in 0x00..=0xff {
+            if byte0 == 0xFF || byte0 & 0x0F == 0x0E {
+                continue;
+            }
+            for byte1 in 0x00..=0xff {
+                let enemy = LevelEnemy::from_bytes(&[byte0, byte1]);
+                assert_eq!(
+                    enemy.to_bytes(),
+                    [byte0, byte1],
+                    "enemy {:?} did not round trip",
+                    enemy
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_enemy_kind() {
+        let enemy = LevelEnemy::from_bytes(&[0x6b, 0x06]);
+        assert!(matches!(enemy.kind, LevelEnemyKind::Goomba));
+        assert!(!enemy.hard_mode);
+
+        let enemy = LevelEnemy::from_bytes(&[0x8b, 0xc5]);
+        assert!(matches!(enemy.kind, LevelEnemyKind::HammerBro));
+        assert!(enemy.new_page_flag);
+        assert!(enemy.hard_mode);
+
+        let enemy = LevelEnemy::from_bytes(&[0x0f, 0x43]);
+        assert!(matches!(enemy.kind, LevelEnemyKind::PageSkip(0x03)));
+        assert!(enemy.hard_mode);
+
+        let enemy = LevelEnemy::from_bytes(&[0x2b, 0x3f]);
+        assert!(matches!(enemy.kind, LevelEnemyKind::Undefined(0x3f)));
+    }
+}
@@ -0,0 +1,734 @@
+use anyhow::Result;
+
+use crate::*;
+
+/// Number of metatile rows the area parser renders per column.
+pub const GRID_ROWS: usize = 13;
+
+/// Metatile used for empty space.
+pub const BLANK_METATILE: u8 = 0x00;
+
+/*
+ * Metatile tables from the game's area parser, indexed by area type (water,
+ * ground, underground, castle) with a fifth entry for cloud levels where
+ * the game has one.
+ */
+const TERRAIN_METATILES: [u8; 5] = [0x69, 0x54, 0x52, 0x62, 0x88];
+const BRICK_METATILES: [u8; 5] = [0x22, 0x51, 0x52, 0x52, 0x88];
+const SOLID_BLOCK_METATILES: [u8; 4] = [0x69, 0x61, 0x61, 0x62];
+const COIN_METATILES: [u8; 4] = [0xc3, 0xc2, 0xc2, 0xc2];
+const HOLE_METATILES: [u8; 4] = [0x87, 0x00, 0x00, 0x00];
+
+/// Question blocks, then bricks for ground levels and for everything else.
+const BRICK_Q_BLOCK_METATILES: [u8; 14] = [
+    0xc1, 0xc0, 0x5f, 0x60, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x5b, 0x5c,
+    0x5d, 0x5e,
+];
+
+/// Rows 0-7 and rows 8-12 filled with terrain, by `LevelGround`.
+const TERRAIN_RENDER_BITS: [[u8; 2]; 16] = [
+    [0b00000000, 0b00000000],
+    [0b00000000, 0b00011000],
+    [0b00000001, 0b00011000],
+    [0b00000111, 0b00011000],
+    [0b00001111, 0b00011000],
+    [0b11111111, 0b00011000],
+    [0b00000001, 0b00011111],
+    [0b00000111, 0b00011111],
+    [0b00001111, 0b00011111],
+    [0b10000001, 0b00011111],
+    [0b00000001, 0b00000000],
+    [0b10001111, 0b00011111],
+    [0b11110001, 0b00011111],
+    [0b11111001, 0b00011000],
+    [0b11110001, 0b00011000],
+    [0b11111111, 0b00011111],
+];
+
+/// Enterable pipe tops, then decoration pipe tops, each followed by shafts.
+const VERTICAL_PIPE_DATA: [u8; 8] =
+    [0x11, 0x10, 0x15, 0x14, 0x13, 0x12, 0x15, 0x14];
+
+/// Sideways pipe parts, indexed by columns left (so stored right to left).
+const SIDE_PIPE_SHAFT_DATA: [u8; 4] = [0x15, 0x14, 0x00, 0x00];
+const SIDE_PIPE_TOP_PART: [u8; 4] = [0x15, 0x1e, 0x1d, 0x1c];
+const SIDE_PIPE_BOTTOM_PART: [u8; 4] = [0x15, 0x21, 0x20, 0x1f];
+
+const STAIRCASE_HEIGHT_DATA: [u8; 9] = [7, 7, 6, 5, 4, 3, 2, 1, 0];
+const STAIRCASE_ROW_DATA: [u8; 9] = [3, 3, 4, 5, 6, 7, 8, 9, 10];
+
+/**
+ * Background scenery for clouds, mountains and fences, 3 pages of 16 columns
+ * each. Each byte holds the starting row in the high nibble and a 1-based
+ * index into `BACK_SCENERY_METATILES` in the low nibble.
+ */
+const BACK_SCENERY_DATA: [u8; 144] = [
+    // clouds
+    0x93, 0x00, 0x00, 0x11, 0x12, 0x12, 0x13, 0x00, 0x00, 0x51, 0x52, 0x53,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x02, 0x03, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x91, 0x92, 0x93, 0x00, 0x00, 0x00, 0x00, 0x51,
+    0x52, 0x53, 0x41, 0x42, 0x43, 0x00, 0x00, 0x00, 0x00, 0x00, 0x91, 0x92,
+    // mountains and bushes
+    0x97, 0x87, 0x88, 0x89, 0x99, 0x00, 0x00, 0x00, 0x11, 0x12, 0x13, 0xa4,
+    0xa5, 0xa5, 0xa5, 0xa6, 0x97, 0x98, 0x99, 0x01, 0x02, 0x03, 0x00, 0xa4,
+    0xa5, 0xa6, 0x00, 0x11, 0x12, 0x12, 0x12, 0x13, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x02, 0x02, 0x03, 0x00, 0xa4, 0xa5, 0xa5, 0xa6, 0x00, 0x00, 0x00,
+    // trees and fences
+    0x11, 0x12, 0x12, 0x13, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x9c,
+    0x00, 0x8b, 0xaa, 0xaa, 0xaa, 0xaa, 0x11, 0x12, 0x13, 0x8b, 0x00, 0x9c,
+    0x9c, 0x00, 0x00, 0x01, 0x02, 0x03, 0x11, 0x12, 0x12, 0x13, 0x00, 0x00,
+    0x00, 0x00, 0xaa, 0xaa, 0x9c, 0xaa, 0x00, 0x8b, 0x00, 0x01, 0x02, 0x03,
+];
+
+/// Up to 3 rows of metatiles for each kind of background scenery.
+const BACK_SCENERY_METATILES: [u8; 36] = [
+    0x80, 0x83, 0x00, // cloud left
+    0x81, 0x84, 0x00, // cloud middle
+    0x82, 0x85, 0x00, // cloud right
+    0x02, 0x00, 0x00, // bush left
+    0x03, 0x00, 0x00, // bush middle
+    0x04, 0x00, 0x00, // bush right
+    0x00, 0x05, 0x06, // mountain left
+    0x07, 0x06, 0x0a, // mountain middle
+    0x00, 0x08, 0x09, // mountain right
+    0x4d, 0x00, 0x00, // fence
+    0x0d, 0x0f, 0x4e, // tall tree
+    0x0e, 0x4e, 0x4e, // short tree
+];
+
+/// Columns of foreground scenery, by `LevelBackground` 1-3.
+const FORE_SCENERY_DATA: [u8; 39] = [
+    // in water
+    0x86, 0x87, 0x87, 0x87, 0x87, 0x87, 0x87, 0x87, 0x87, 0x87, 0x87, 0x69,
+    0x69, //
+    // castle wall
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x45, 0x47, 0x47, 0x47, 0x47, 0x47, 0x00,
+    0x00, //
+    // over water
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x86,
+    0x87,
+];
+
+/// 11 rows of 5 columns, drawn from the castle's starting row down.
+const CASTLE_METATILES: [[u8; 5]; 11] = [
+    [0x00, 0x45, 0x45, 0x45, 0x00],
+    [0x00, 0x48, 0x47, 0x46, 0x00],
+    [0x45, 0x49, 0x49, 0x49, 0x45],
+    [0x47, 0x47, 0x4a, 0x47, 0x47],
+    [0x47, 0x47, 0x4b, 0x47, 0x47],
+    [0x49, 0x49, 0x49, 0x49, 0x49],
+    [0x47, 0x4a, 0x47, 0x4a, 0x47],
+    [0x47, 0x4b, 0x47, 0x4b, 0x47],
+    [0x47, 0x47, 0x47, 0x47, 0x47],
+    [0x4a, 0x47, 0x4a, 0x47, 0x4a],
+    [0x4b, 0x47, 0x4b, 0x47, 0x4b],
+];
+
+/**
+ * An area rendered into metatiles, the way the game's area parser does.
+ *
+ * Each column starts with the background scenery (clouds, hills, bushes,
+ * fences and trees), then the foreground scenery (water, castle walls) and
+ * the terrain from the header and any layout objects. Objects are then drawn
+ * in the order they are stored.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelGrid {
+    columns: Vec<[u8; GRID_ROWS]>,
+}
+
+impl LevelGrid {
+    /// Render the level data of an area of the given type.
+    pub fn new(level: &Level, area_type: AreaType) -> Self {
+        let objects: Vec<_> = level.object_data.with_positions().collect();
+
+        // always render whole pages
+        let width = objects
+            .iter()
+            .map(|(position, object)| {
+                position.x() as usize + object_width(object)
+            })
+            .max()
+            .unwrap_or(0);
+        let width = width.max(1).div_ceil(16) * 16;
+
+        let mut builder = GridBuilder {
+            columns: vec![[BLANK_METATILE; GRID_ROWS]; width],
+            area_type: area_type.value() as usize,
+            cloud: level.level_header.platform == LevelPlatform::Clouds,
+            platform: level.level_header.platform,
+        };
+
+        // layout objects take effect from the column after them, as the
+        // game renders a column before processing its objects
+        let header = &level.level_header;
+        let mut attributes = AreaAttributes {
+            ground: header.ground.value(),
+            back_scenery: header.scenery.value(),
+            fore_scenery: match header.background.value() {
+                fore_scenery @ 0..=3 => fore_scenery,
+                _ => 0,
+            },
+        };
+        let mut changes = objects
+            .iter()
+            .filter(|(_, object)| object.y_coordinate == 0xe)
+            .map(|(position, object)| {
+                (position.x() as usize, object.kind.value())
+            })
+            .peekable();
+        for x in 0..width {
+            while let Some(&(change_x, value)) = changes.peek() {
+                if change_x >= x {
+                    break;
+                }
+                attributes.alter(value);
+                changes.next();
+            }
+            builder.render_back_scenery(x, attributes.back_scenery);
+            builder.render_fore_scenery(x, attributes.fore_scenery);
+            builder.render_terrain(x, attributes.ground);
+        }
+
+        for (position, object) in &objects {
+            builder.render_object(position, object);
+        }
+
+        Self { columns: builder.columns }
+    }
+
+    /// Wrap columns of metatiles rendered some other way, like by the game.
+    pub fn from_columns(columns: Vec<[u8; GRID_ROWS]>) -> Self {
+        Self { columns }
+    }
+
+    /// Number of columns in the grid, always a whole number of pages.
+    pub fn width(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Get the metatile at a column and row.
+    pub fn get(&self, x: usize, row: usize) -> Option<u8> {
+        self.columns.get(x)?.get(row).copied()
+    }
+
+    /// Get every metatile of a column, top to bottom.
+    pub fn column(&self, x: usize) -> Option<&[u8; GRID_ROWS]> {
+        self.columns.get(x)
+    }
+
+    /// Get every metatile of a row, left to right.
+    pub fn row(&self, row: usize) -> impl Iterator<Item = u8> + '_ {
+        self.columns.iter().filter_map(move |column| column.get(row).copied())
+    }
+}
+
+impl Rom {
+    /// Render the level data of an area into metatiles.
+    pub fn area_grid(&self, area: &Area) -> Result<LevelGrid> {
+        let level = self.get_area(area)?;
+        Ok(LevelGrid::new(&level, area.area_type))
+    }
+}
+
+/// Number of columns an object draws into.
+fn object_width(object: &LevelObject) -> usize {
+    use LevelObjectKind::*;
+
+    match object.kind {
+        IslandOrCannon(n)
+        | HorizontalBrick(n)
+        | HorizontalBlock(n)
+        | HorizontalCoin(n)
+        | Hole(n)
+        | BalanceHorizontalRope(n)
+        | BridgeY7(n)
+        | BridgeY8(n)
+        | BridgeY10(n)
+        | FilledHole(n)
+        | HorizontalQuestionBlockY3(n)
+        | HorizontalQuestionBlockY7(n)
+        | Staircase(n) => n as usize,
+        PipeNoEntry(_) | PipeEntry(_) => 2,
+        ReverseLPipe | TallReverseLPipe(_) => 4,
+        Castle(_) => 5,
+        CastleBridge => 13,
+        _ => 1,
+    }
+}
+
+/// Area settings the game keeps while parsing, changed by row 0xE objects.
+struct AreaAttributes {
+    ground: u8,
+    back_scenery: u8,
+    fore_scenery: u8,
+}
+
+impl AreaAttributes {
+    fn alter(&mut self, value: u8) {
+        match value {
+            0x00..=0x3f => {
+                self.ground = value & 0x0f;
+                self.back_scenery = (value & 0x30) >> 4;
+            }
+            // backgrounds 4-7 only change colors and clear the foreground
+            0x40..=0x43 => self.fore_scenery = value & 0x0f,
+            _ => self.fore_scenery = 0,
+        }
+    }
+}
+
+struct GridBuilder {
+    columns: Vec<[u8; GRID_ROWS]>,
+    area_type: usize,
+    cloud: bool,
+    platform: LevelPlatform,
+}
+
+impl GridBuilder {
+    fn render_back_scenery(&mut self, x: usize, back_scenery: u8) {
+        if back_scenery == 0 {
+            return;
+        }
+        // the scenery repeats every 3 pages
+        let offset = (back_scenery as usize - 1) * 48 + x % 48;
+        let data = BACK_SCENERY_DATA[offset];
+        if data == 0 {
+            return;
+        }
+
+        let set = (data & 0x0f) as usize - 1;
+        let metatiles = &BACK_SCENERY_METATILES[set * 3..set * 3 + 3];
+        let row = (data >> 4) as usize;
+        // scenery stops short of the floor
+        for (row, &metatile) in (row..11).zip(metatiles) {
+            self.columns[x][row] = metatile;
+        }
+    }
+
+    fn render_fore_scenery(&mut self, x: usize, fore_scenery: u8) {
+        if fore_scenery == 0 {
+            return;
+        }
+        let offset = (fore_scenery as usize - 1) * GRID_ROWS;
+        let data = &FORE_SCENERY_DATA[offset..offset + GRID_ROWS];
+        for (cell, &metatile) in self.columns[x].iter_mut().zip(data) {
+            if metatile != BLANK_METATILE {
+                *cell = metatile;
+            }
+        }
+    }
+
+    fn render_terrain(&mut self, x: usize, ground: u8) {
+        let [top, bottom] = TERRAIN_RENDER_BITS[ground as usize & 0x0f];
+        // cloud levels only keep the single row of clouds to stand on
+        let bottom = if self.cloud { bottom & 0b00001000 } else { bottom };
+        let bits = top as u16 | (bottom as u16) << 8;
+
+        let mut metatile = match self.cloud {
+            true => TERRAIN_METATILES[4],
+            false => TERRAIN_METATILES[self.area_type],
+        };
+        for row in 0..GRID_ROWS {
+            if row == 11
+                && self.area_type == AreaType::Underground.value() as usize
+            {
+                metatile = 0x54;
+            }
+            if bits & (1 << row) != 0 {
+                self.columns[x][row] = metatile;
+            }
+        }
+    }
+
+    /// Store a metatile, whatever is already there.
+    fn set(&mut self, x: usize, row: usize, metatile: u8) {
+        if let Some(cell) = self.columns.get_mut(x).and_then(|c| c.get_mut(row))
+        {
+            *cell = metatile;
+        }
+    }
+
+    /**
+     * Draw `rows` rows of a metatile down from `row` (the game's
+     * `RenderUnderPart`), keeping ledge tops, palette 3 metatiles (other
+     * than coin question blocks) and rock under mushroom stems.
+     */
+    fn render_under(
+        &mut self,
+        x: usize,
+        row: usize,
+        rows: usize,
+        metatile: u8,
+    ) {
+        let Some(column) = self.columns.get_mut(x) else {
+            return;
+        };
+        for cell in column.iter_mut().skip(row).take(rows) {
+            let keep = match *cell {
+                0x00 | 0xc0 => false,
+                0x17 | 0x1a => true,
+                0xc1..=0xff => true,
+                0x54 => metatile == 0x50,
+                _ => false,
+            };
+            if !keep {
+                *cell = metatile;
+            }
+        }
+    }
+
+    fn render_object(
+        &mut self,
+        position: &LevelPosition,
+        object: &LevelObject,
+    ) {
+        use LevelObjectKind::*;
+
+        let x = position.x() as usize;
+        let row = position.row as usize;
+        let value = object.kind.value();
+
+        match object.kind {
+            QuestionBlockPowerup | QuestionBlockCoin | HiddenBlockCoin
+            | HiddenBlockExtraLife => {
+                let metatile = BRICK_Q_BLOCK_METATILES[value as usize];
+                self.render_under(x, row, 1, metatile);
+            }
+            BrickPowerup | BrickVine | BrickStar | BrickMultiCoinBlock
+            | BrickExtraLife => {
+                let ground =
+                    self.area_type == AreaType::Ground.value() as usize;
+                let offset = if ground { 0 } else { 5 };
+                let metatile = BRICK_Q_BLOCK_METATILES[value as usize + offset];
+                self.render_under(x, row, 1, metatile);
+            }
+            SidewaysPipe => {
+                self.set(x, row, 0x6b);
+                self.set(x, row + 1, 0x6c);
+            }
+            UsedBlock => self.render_under(x, row, 1, 0xc4),
+            Spring => {
+                self.set(x, row, 0x67);
+                self.set(x, row + 1, 0x68);
+            }
+            IslandOrCannon(n) => match self.platform {
+                LevelPlatform::OrangeAndMushrooms => {
+                    self.mushroom_ledge(x, row, n as usize)
+                }
+                LevelPlatform::BulletBills => self.cannon(x, row, n as usize),
+                _ => self.tree_ledge(x, row, n as usize),
+            },
+            HorizontalBrick(n) => {
+                let area_type = if self.cloud { 4 } else { self.area_type };
+                let metatile = BRICK_METATILES[area_type];
+                for i in 0..n as usize {
+                    self.render_under(x + i, row, 1, metatile);
+                }
+            }
+            HorizontalBlock(n) => {
+                let metatile = SOLID_BLOCK_METATILES[self.area_type];
+                for i in 0..n as usize {
+                    self.render_under(x + i, row, 1, metatile);
+                }
+            }
+            HorizontalCoin(n) => {
+                let metatile = COIN_METATILES[self.area_type];
+                for i in 0..n as usize {
+                    self.render_under(x + i, row, 1, metatile);
+                }
+            }
+            VerticalBrick(n) => {
+                let metatile = BRICK_METATILES[self.area_type];
+                self.render_under(x, row, n as usize, metatile);
+            }
+            VerticalBlock(n) => {
+                let metatile = SOLID_BLOCK_METATILES[self.area_type];
+                self.render_under(x, row, n as usize, metatile);
+            }
+            PipeNoEntry(_) | PipeEntry(_) => {
+                let shaft_rows = (value & 0b0111).max(1) as usize;
+                let offset = if value & 0b1000 != 0 { 0 } else { 4 };
+                // columns left to draw index the pipe data, as in the game
+                for (i, columns_left) in [1, 0].into_iter().enumerate() {
+                    let top = VERTICAL_PIPE_DATA[offset + columns_left];
+                    let shaft = VERTICAL_PIPE_DATA[offset + columns_left + 2];
+                    self.set(x + i, row, top);
+                    self.render_under(x + i, row + 1, shaft_rows, shaft);
+                }
+            }
+
+            Hole(n) => {
+                let metatile = HOLE_METATILES[self.area_type];
+                for i in 0..n as usize {
+                    self.render_under(x + i, 8, GRID_ROWS, metatile);
+                }
+            }
+            BalanceHorizontalRope(n) => {
+                for i in 0..n as usize {
+                    let metatile = match i {
+                        0 => 0x42,
+                        _ if i + 1 == n as usize => 0x43,
+                        _ => 0x41,
+                    };
+                    self.set(x + i, 0, metatile);
+                }
+            }
+            BridgeY7(n) => self.bridge(x, 6, n as usize),
+            BridgeY8(n) => self.bridge(x, 7, n as usize),
+            BridgeY10(n) => self.bridge(x, 9, n as usize),
+            FilledHole(n) => {
+                for i in 0..n as usize {
+                    self.set(x + i, 10, 0x86);
+                    self.render_under(x + i, 11, 2, 0x87);
+                }
+            }
+            HorizontalQuestionBlockY3(n) => {
+                for i in 0..n as usize {
+                    self.set(x + i, 3, 0xc0);
+                }
+            }
+            HorizontalQuestionBlockY7(n) => {
+                for i in 0..n as usize {
+                    self.set(x + i, 7, 0xc0);
+                }
+            }
+
+            ReverseLPipe => {
+                for i in 0..4 {
+                    let columns_left = 3 - i;
+                    self.sideways_pipe(x + i, 10, columns_left);
+                    // the vertical part of the pipe sticks out of row 7
+                    if SIDE_PIPE_SHAFT_DATA[columns_left] != 0 {
+                        for blank_row in 0..7 {
+                            self.set(x + i, blank_row, BLANK_METATILE);
+                        }
+                        self.set(x + i, 7, VERTICAL_PIPE_DATA[columns_left]);
+                    }
+                }
+            }
+            FlagPole => {
+                self.set(x, 0, 0x24);
+                self.render_under(x, 1, 9, 0x25);
+                self.set(x, 10, 0x61);
+            }
+            CastleAxe => self.render_under(x, 6, 1, 0xc5),
+            AxeRope => self.render_under(x, 7, 1, 0x0c),
+            CastleBridge => {
+                for i in 0..13 {
+                    self.render_under(x + i, 8, 1, 0x89);
+                }
+            }
+
+            LiftRope => self.render_under(x, 0, GRID_ROWS, 0x40),
+            BalanceLiftVerticalRope(n) => {
+                self.render_under(x, 1, GRID_ROWS, 0x44);
+                self.render_under(x, 1, n as usize, 0x40);
+            }
+            Castle(start_row) => {
+                // the game has no bottom row limit and crashes below row 10
+                for i in 0..5 {
+                    let rows = (start_row as usize..11).enumerate();
+                    for (data_row, grid_row) in rows {
+                        let metatile = CASTLE_METATILES[data_row][4 - i];
+                        self.set(x + i, grid_row, metatile);
+                    }
+                }
+            }
+            Staircase(n) => {
+                for i in 0..(n as usize).min(STAIRCASE_ROW_DATA.len()) {
+                    let step = STAIRCASE_ROW_DATA.len() - 1 - i;
+                    let step_row = STAIRCASE_ROW_DATA[step] as usize;
+                    let rows = STAIRCASE_HEIGHT_DATA[step] as usize + 1;
+                    self.render_under(x + i, step_row, rows, 0x61);
+                }
+            }
+            TallReverseLPipe(n) => {
+                for i in 0..4 {
+                    self.sideways_pipe(x + i, n as usize, 3 - i);
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    fn tree_ledge(&mut self, x: usize, row: usize, width: usize) {
+        for i in 0..width {
+            // a ledge at the very start of a level continues off screen
+            let start = i == 0 && x != 0;
+            let end = i + 1 == width && !start;
+            if start {
+                self.render_under(x + i, row, 1, 0x16);
+            } else if end {
+                self.render_under(x + i, row, 1, 0x18);
+            } else {
+                self.set(x + i, row, 0x17);
+                self.render_under(x + i, row + 1, GRID_ROWS, 0x4c);
+            }
+        }
+    }
+
+    fn mushroom_ledge(&mut self, x: usize, row: usize, width: usize) {
+        let half = (width - 1) / 2;
+        for i in 0..width {
+            let columns_left = width - 1 - i;
+            if i == 0 {
+                self.render_under(x, row, 1, 0x19);
+            } else if columns_left == 0 {
+                self.render_under(x + i, row, 1, 0x1b);
+            } else {
+                self.set(x + i, row, 0x1a);
+                if columns_left == half {
+                    self.set(x + i, row + 1, 0x4f);
+                    self.render_under(x + i, row + 2, GRID_ROWS, 0x50);
+                }
+            }
+        }
+    }
+
+    fn cannon(&mut self, x: usize, row: usize, height: usize) {
+        self.set(x, row, 0x64);
+        if height > 1 {
+            self.set(x, row + 1, 0x65);
+        }
+        if height > 2 {
+            self.render_under(x, row + 2, height - 2, 0x66);
+        }
+    }
+
+    fn bridge(&mut self, x: usize, railing_row: usize, width: usize) {
+        for i in 0..width {
+            self.set(x + i, railing_row, 0x0b);
+            self.render_under(x + i, railing_row + 1, 1, 0x63);
+        }
+    }
+
+    /// Draw a column of a sideways pipe whose opening ends at `bottom_row`.
+    fn sideways_pipe(
+        &mut self,
+        x: usize,
+        bottom_row: usize,
+        columns_left: usize,
+    ) {
+        let side_row = bottom_row.saturating_sub(1);
+        let shaft = SIDE_PIPE_SHAFT_DATA[columns_left];
+        if shaft != 0 {
+            self.render_under(x, 0, side_row, shaft);
+        }
+        self.set(x, side_row, SIDE_PIPE_TOP_PART[columns_left]);
+        self.set(x, side_row + 1, SIDE_PIPE_BOTTOM_PART[columns_left]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(objects: &[u8]) -> Level {
+        Level {
+            level_header: LevelHeader::from_bytes(&[0x00, 0x01]),
+            object_data: LevelObjectData::from_bytes(objects),
+            enemy_data: LevelEnemyData::from_bytes(&[0xff]),
+        }
+    }
+
+    #[test]
+    fn test_terrain() {
+        let grid = LevelGrid::new(&level(&[0xfd]), AreaType::Ground);
+        assert_eq!(grid.width(), 16);
+        assert_eq!(
+            grid.column(0),
+            Some(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x54, 0x54])
+        );
+
+        // the layout change takes effect from the next column
+        let grid =
+            LevelGrid::new(&level(&[0x3e, 0x0f, 0xfd]), AreaType::Castle);
+        assert_eq!(grid.get(3, 0), Some(0));
+        assert_eq!(grid.get(4, 0), Some(0x62));
+        assert!(grid.row(12).all(|metatile| metatile == 0x62));
+
+        let grid = LevelGrid::new(&level(&[0xfd]), AreaType::Underground);
+        assert_eq!(grid.column(5).unwrap()[11..], [0x54, 0x54]);
+    }
+
+    #[test]
+    fn test_objects() {
+        let objects = [
+            0x23, 0x00, // question block
+            0x49, 0x71, // pipe
+            0x8c, 0x01, // hole
+            0xaf, 0x33, // staircase
+            0x0d, 0xc1, // flagpole on the next page
+            0xfd,
+        ];
+        let grid = LevelGrid::new(&level(&objects), AreaType::Ground);
+        assert_eq!(grid.width(), 32);
+
+        assert_eq!(grid.get(2, 3), Some(0xc1));
+        assert_eq!(grid.get(4, 9), Some(0x12));
+        assert_eq!(grid.get(5, 9), Some(0x13));
+        assert_eq!(grid.get(4, 10), Some(0x14));
+        assert_eq!(grid.get(5, 10), Some(0x15));
+        assert_eq!(grid.get(8, 11), Some(0));
+        assert_eq!(grid.get(9, 12), Some(0));
+        assert_eq!(grid.get(10, 11), Some(0x54));
+
+        let steps: Vec<_> = (10..14)
+            .map(|x| {
+                grid.column(x).unwrap().iter().filter(|&&m| m == 0x61).count()
+            })
+            .collect();
+        assert_eq!(steps, [1, 2, 3, 4]);
+        assert_eq!(grid.get(13, 7), Some(0x61));
+        assert_eq!(grid.get(13, 6), Some(0));
+
+        assert_eq!(grid.get(16, 0), Some(0x24));
+        assert_eq!(grid.get(16, 9), Some(0x25));
+        assert_eq!(grid.get(16, 10), Some(0x61));
+    }
+
+    #[test]
+    fn test_scenery() {
+        // mountains, then over water from column 4 and no floor from 5
+        let objects = [0x3e, 0x43, 0x4e, 0x20, 0xfd];
+        let level = Level {
+            level_header: LevelHeader::from_bytes(&[0x00, 0x21]),
+            object_data: LevelObjectData::from_bytes(&objects),
+            enemy_data: LevelEnemyData::from_bytes(&[0xff]),
+        };
+        let grid = LevelGrid::new(&level, AreaType::Ground);
+
+        // the hill at the start of the area
+        assert_eq!(grid.get(0, 9), Some(0));
+        assert_eq!(grid.get(0, 10), Some(0x05));
+        assert_eq!(grid.get(2, 8), Some(0x07));
+        assert_eq!(grid.get(2, 10), Some(0x0a));
+
+        // the floor is drawn over the water until it ends
+        assert_eq!(grid.get(4, 11), Some(0x54));
+        assert_eq!(grid.get(5, 11), Some(0x86));
+        assert_eq!(grid.get(5, 12), Some(0x87));
+    }
+
+    #[test]
+    fn test_castle_and_ledges() {
+        let objects = [0x0f, 0x25, 0x6a, 0x12, 0xfd];
+        let grid = LevelGrid::new(&level(&objects), AreaType::Ground);
+        assert_eq!(
+            grid.column(0).unwrap()[5..11],
+            [0, 0, 0x45, 0x47, 0x47, 0x49]
+        );
+        assert_eq!(
+            grid.column(2).unwrap()[5..11],
+            [0x45, 0x47, 0x49, 0x4a, 0x4b, 0x49]
+        );
+
+        // a 3 wide tree ledge on row 10
+        assert_eq!(grid.get(6, 10), Some(0x16));
+        assert_eq!(grid.get(7, 10), Some(0x17));
+        assert_eq!(grid.get(7, 11), Some(0x4c));
+        assert_eq!(grid.get(8, 10), Some(0x18));
+    }
+}
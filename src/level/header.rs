@@ -1,6 +1,8 @@
 use crate::util::enum_mapped;
+use crate::Error;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LevelHeader {
     //pub header_data: &'a [u8],
     pub time: LevelTime,
@@ -41,8 +43,8 @@ impl LevelHeader {
      *                                   |                   |
      *  =====================================================|
      */
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        assert!(bytes.len() >= 2);
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Error::check_len("level header", bytes, 2)?;
 
         // first byte
         let time = Self::parse_level_time(bytes);
@@ -55,7 +57,7 @@ impl LevelHeader {
         let platform = Self::parse_level_platform(bytes);
         let ground = Self::parse_level_ground(bytes);
 
-        Self {
+        Ok(Self {
             time,
             start_position,
             start_autowalk,
@@ -63,7 +65,25 @@ impl LevelHeader {
             scenery,
             platform,
             ground,
-        }
+        })
+    }
+
+    /// Panics on truncated data, see `LevelHeader::try_from_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::try_from_bytes(bytes).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Encode the header back into its 2 bytes, see `LevelHeader::from_bytes`.
+    pub fn to_bytes(&self) -> [u8; 2] {
+        let byte0 = self.time.value() << 6
+            | (self.start_autowalk as u8) << 5
+            | self.start_position.value() << 3
+            | self.background.value();
+        let byte1 = self.platform.value() << 6
+            | self.scenery.value() << 4
+            | self.ground.value();
+
+        [byte0, byte1]
     }
 
     /// TTxxxxxx xxxxxxxx
@@ -178,3 +198,18 @@ enum_mapped!(
         0b1111 => All,
     }
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_round_trip() {
+        for byte0 in 0x00..=0xff {
+            for byte1 in 0x00..=0xff {
+                let header = LevelHeader::from_bytes(&[byte0, byte1]);
+                assert_eq!(header.to_bytes(), [byte0, byte1]);
+            }
+        }
+    }
+}
@@ -1,33 +1,79 @@
+use crate::util::enum_mapped;
+use crate::*;
+
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LevelObjectData {
     pub objects: Vec<LevelObject>,
 }
 
 impl LevelObjectData {
-    pub fn from_bytes(bytes: &[u8]) -> Self {
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
         let mut objects = vec![];
 
         // process byte-by-byte
         let mut idx = 0;
         loop {
-            let byte = bytes[idx];
+            let byte = *bytes.get(idx).ok_or(Error::MissingTerminator {
+                what: "object data",
+                offset: 0,
+                terminator: 0xFD,
+            })?;
 
             // 0xFD is the end level marker
             if byte == 0xFD {
                 break;
             }
 
-            let object = LevelObject::from_bytes(&bytes[idx..]);
+            let object = LevelObject::try_from_bytes(&bytes[idx..])
+                .map_err(|error| error.offset_by(idx))?;
 
             objects.push(object);
             idx += 2;
         }
 
-        Self { objects }
+        Ok(Self { objects })
+    }
+
+    /// Panics on truncated data, see `LevelObjectData::try_from_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::try_from_bytes(bytes).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Get every object along with its position in the area.
+    pub fn with_positions(
+        &self,
+    ) -> impl Iterator<Item = (LevelPosition, &LevelObject)> {
+        let mut cursor = PageCursor::new();
+        self.objects.iter().map(move |object| {
+            let position = cursor.advance(
+                object.new_page_flag,
+                object.page_skip(),
+                object.x_coordinate,
+                object.y_coordinate,
+            );
+            (position, object)
+        })
+    }
+
+    /// Check every object, see `LevelObject::validate`.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.objects.iter().try_for_each(LevelObject::validate)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> =
+            self.objects.iter().flat_map(|object| object.to_bytes()).collect();
+
+        // 0xFD is the end level marker
+        bytes.push(0xFD);
+
+        bytes
     }
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LevelObject {
     pub kind: LevelObjectKind,
     pub x_coordinate: u8,
@@ -39,14 +85,64 @@ impl LevelObject {
     /**
      * XXXXYYYY POOOOOOO
      */
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        assert!(bytes.len() >= 2);
-        let x_coordinate = bytes[0] << 4;
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Error::check_len("level object", bytes, 2)?;
+        let x_coordinate = bytes[0] >> 4;
         let y_coordinate = bytes[0] & 0b00001111;
         let new_page_flag = bytes[1] & 0b10000000 != 0;
         let kind = Self::parse_object_kind(bytes);
 
-        Self { kind, x_coordinate, y_coordinate, new_page_flag }
+        Ok(Self { kind, x_coordinate, y_coordinate, new_page_flag })
+    }
+
+    /// Panics on truncated data, see `LevelObject::try_from_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::try_from_bytes(bytes).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /**
+     * XXXXYYYY POOOOOOO
+     */
+    pub fn to_bytes(&self) -> [u8; 2] {
+        let byte0 = (self.x_coordinate << 4) | (self.y_coordinate & 0b00001111);
+        let byte1 = ((self.new_page_flag as u8) << 7) | self.kind.value();
+
+        [byte0, byte1]
+    }
+
+    /// Get the page this object skips to, if it is a page skip.
+    pub fn page_skip(&self) -> Option<u8> {
+        match self.kind {
+            LevelObjectKind::PageSkip(byte) => Some(byte & 0b00011111),
+            _ => None,
+        }
+    }
+
+    /**
+     * Check this object encodes to bytes that decode back to it.
+     *
+     * Objects built by hand can have a kind that doesn't exist at their y
+     * coordinate or a size that doesn't fit, which `to_bytes` can't encode.
+     */
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = || Error::InvalidValue {
+            what: "level object",
+            value: format!("{:?}", self),
+        };
+
+        // 0xFD would be read as the end level marker
+        let (x, y) = (self.x_coordinate, self.y_coordinate);
+        if x > 0xf || y > 0xf || (x, y) == (0xf, 0xd) {
+            return Err(invalid());
+        }
+
+        // sizes out of range overflow in `value`, so search for the byte
+        match (0x00..=0x7f)
+            .any(|byte| LevelObjectKind::new(y, byte) == self.kind)
+        {
+            true => Ok(()),
+            false => Err(invalid()),
+        }
     }
 
     fn parse_object_kind(bytes: &[u8]) -> LevelObjectKind {
@@ -56,27 +152,30 @@ impl LevelObject {
     }
 }
 
-#[derive(Debug)]
-pub enum SceneryKind {
-    Nothing,
-    Clouds,
-    Mountains,
-    Fences,
-}
+enum_mapped!(
+    pub SceneryKind (u8) {
+        0b00 => Nothing,
+        0b01 => Clouds,
+        0b10 => Mountains,
+        0b11 => Fences,
+    }
+);
 
-#[derive(Debug)]
-pub enum ChangeBackgroundKind {
-    Nothing,
-    InWater,
-    CastleWall,
-    OverWater,
-    Night,
-    Snow,
-    NightAndSnow,
-    NightAndCastle,
-}
+enum_mapped!(
+    pub ChangeBackgroundKind (u8) {
+        0b000 => Nothing,
+        0b001 => InWater,
+        0b010 => CastleWall,
+        0b011 => OverWater,
+        0b100 => Night,
+        0b101 => Snow,
+        0b110 => NightAndSnow,
+        0b111 => NightAndCastle,
+    }
+);
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LevelObjectKind {
     QuestionBlockPowerup,
     QuestionBlockCoin,
@@ -114,12 +213,13 @@ pub enum LevelObjectKind {
     CastleAxe,
     AxeRope,
     ScrollStop,
+    ScrollStopAlternate,
     ScrollStopWarpZone,
     RedCheepCheep,
     ContinuousBulletBillsOrCheepCheeps,
     StopContinuation,
     LoopCommand,
-    Invalid,
+    Invalid(u8),
 
     LayoutEmpty(SceneryKind),
     LayoutFloor1Mddle0Ceiling0(SceneryKind),
@@ -141,7 +241,7 @@ pub enum LevelObjectKind {
 
     LiftRope,
     BalanceLiftVerticalRope(u8), // length
-    BigCastle,
+    Castle(u8), // y
     Staircase(u8), // width
     TallReverseLPipe(u8), // y
 }
@@ -165,7 +265,7 @@ impl LevelObjectKind {
             (0x0..=0xb, 0x09) => Self::SidewaysPipe,
             (0x0..=0xb, 0x0a) => Self::UsedBlock,
             (0x0..=0xb, 0x0b) => Self::Spring,
-            (0x0..=0xb, 0x0c..=0x0f) => Self::Invalid,
+            (0x0..=0xb, 0x0c..=0x0f) => Self::Invalid(byte),
 
             (0x0..=0xb, 0x10..=0x1f) => Self::IslandOrCannon(low_nibble + 1),
             (0x0..=0xb, 0x20..=0x2f) => Self::HorizontalBrick(low_nibble + 1),
@@ -174,10 +274,10 @@ impl LevelObjectKind {
 
             // anything above 12 is invalid (screen max)
             (0x0..=0xb, 0x50..=0x5b) => Self::VerticalBrick(low_nibble + 1),
-            (0x0..=0xb, 0x5c..=0x5f) => Self::Invalid,
+            (0x0..=0xb, 0x5c..=0x5f) => Self::Invalid(byte),
 
             (0x0..=0xb, 0x60..=0x6b) => Self::VerticalBlock(low_nibble + 1),
-            (0x0..=0xb, 0x6c..=0x6f) => Self::Invalid,
+            (0x0..=0xb, 0x6c..=0x6f) => Self::Invalid(byte),
 
             (0x0..=0xb, 0x70..=0x77) => Self::PipeNoEntry(low_nibble + 2),
             (0x0..=0xb, 0x78..=0x7f) => Self::PipeEntry(low_nibble - 6),
@@ -205,13 +305,13 @@ impl LevelObjectKind {
             (0xd, 0x44) => Self::CastleBridge,
             (0xd, 0x45) => Self::ScrollStopWarpZone,
             (0xd, 0x46) => Self::ScrollStop,
-            (0xd, 0x47) => Self::ScrollStop,
+            (0xd, 0x47) => Self::ScrollStopAlternate,
             (0xd, 0x48) => Self::RedCheepCheep,
             (0xd, 0x49) => Self::ContinuousBulletBillsOrCheepCheeps,
             (0xd, 0x4a) => Self::StopContinuation,
             (0xd, 0x4b) => Self::LoopCommand,
-            (0xd, 0x4c..=0x4f) => Self::Invalid,
-            (0xd, 0x50..=0x7f) => Self::Invalid,
+            (0xd, 0x4c..=0x4f) => Self::Invalid(byte),
+            (0xd, 0x50..=0x7f) => Self::Invalid(byte),
 
             // Y offset 0xe
             (0xe, 0x00) => Self::LayoutEmpty(SceneryKind::Nothing),
@@ -403,27 +503,230 @@ impl LevelObjectKind {
             (0xe, 0x46) => Self::ChangeBackground(ChangeBackgroundKind::NightAndSnow),
             (0xe, 0x47) => Self::ChangeBackground(ChangeBackgroundKind::NightAndCastle),
             (0xe, 0x48..=0x7f) => {
-                Self::Invalid
+                Self::Invalid(byte)
             }
 
             // Y offset 0xf
             (0xf, 0x00) => Self::LiftRope,
-            (0xf, 0x01..=0x0f) => Self::Invalid,
+            (0xf, 0x01..=0x0f) => Self::Invalid(byte),
             (0xf, 0x10..=0x1f) => Self::BalanceLiftVerticalRope(low_nibble + 1),
-            (0xf, 0x20) => Self::BigCastle,
-            (0xf, 0x21..=0x2f) => Self::Invalid,
+            (0xf, 0x20..=0x2f) => Self::Castle(low_nibble),
             (0xf, 0x30..=0x38) => Self::Staircase(low_nibble + 1),
-            (0xf, 0x39..=0x3f) => Self::Invalid,
-            (0xf, 0x40..=0x42) => Self::Invalid,
+            (0xf, 0x39..=0x3f) => Self::Invalid(byte),
+            (0xf, 0x40..=0x42) => Self::Invalid(byte),
             (0xf, 0x43..=0x4a) => Self::TallReverseLPipe(low_nibble),
-            (0xf, 0x4b..=0x4f) => Self::Invalid,
-            (0xf, 0x50..=0x5f) => Self::Invalid,
+            (0xf, 0x4b..=0x4f) => Self::Invalid(byte),
+            (0xf, 0x50..=0x5f) => Self::Invalid(byte),
             (0xf, 0x60) => Self::Nothing,
-            (0xf, 0x61..=0x6f) => Self::Invalid,
-            (0xf, 0x70..=0x7f) => Self::Invalid,
+            (0xf, 0x61..=0x6f) => Self::Invalid(byte),
+            (0xf, 0x70..=0x7f) => Self::Invalid(byte),
 
-            _ => Self::Invalid,
+            _ => Self::Invalid(byte),
             //_ => unreachable!("invalid level object byte: ({}, {})", y_coordinate, byte),
         }
     }
-  }
+
+    /// Get the object byte (without the new page flag) for this kind.
+    pub fn value(&self) -> u8 {
+        match self {
+            // Y offset 0x0 -> 0xb
+            Self::QuestionBlockPowerup => 0x00,
+            Self::QuestionBlockCoin => 0x01,
+            Self::HiddenBlockCoin => 0x02,
+            Self::HiddenBlockExtraLife => 0x03,
+            Self::BrickPowerup => 0x04,
+            Self::BrickVine => 0x05,
+            Self::BrickStar => 0x06,
+            Self::BrickMultiCoinBlock => 0x07,
+            Self::BrickExtraLife => 0x08,
+            Self::SidewaysPipe => 0x09,
+            Self::UsedBlock => 0x0a,
+            Self::Spring => 0x0b,
+            Self::IslandOrCannon(n) => 0x10 + n - 1,
+            Self::HorizontalBrick(n) => 0x20 + n - 1,
+            Self::HorizontalBlock(n) => 0x30 + n - 1,
+            Self::HorizontalCoin(n) => 0x40 + n - 1,
+            Self::VerticalBrick(n) => 0x50 + n - 1,
+            Self::VerticalBlock(n) => 0x60 + n - 1,
+            Self::PipeNoEntry(n) => 0x70 + n - 2,
+            Self::PipeEntry(n) => 0x78 + n - 2,
+
+            // Y offset 0xc
+            Self::Hole(n) => n - 1,
+            Self::BalanceHorizontalRope(n) => 0x10 + n - 1,
+            Self::BridgeY7(n) => 0x20 + n - 1,
+            Self::BridgeY8(n) => 0x30 + n - 1,
+            Self::BridgeY10(n) => 0x40 + n - 1,
+            Self::FilledHole(n) => 0x50 + n - 1,
+            Self::HorizontalQuestionBlockY3(n) => 0x60 + n - 1,
+            Self::HorizontalQuestionBlockY7(n) => 0x70 + n - 1,
+
+            // Y offset 0xd
+            Self::PageSkip(byte) => *byte,
+            Self::ReverseLPipe => 0x40,
+            Self::FlagPole => 0x41,
+            Self::CastleAxe => 0x42,
+            Self::AxeRope => 0x43,
+            Self::CastleBridge => 0x44,
+            Self::ScrollStopWarpZone => 0x45,
+            Self::ScrollStop => 0x46,
+            Self::ScrollStopAlternate => 0x47,
+            Self::RedCheepCheep => 0x48,
+            Self::ContinuousBulletBillsOrCheepCheeps => 0x49,
+            Self::StopContinuation => 0x4a,
+            Self::LoopCommand => 0x4b,
+
+            // Y offset 0xe
+            Self::LayoutEmpty(s) => s.value() << 4,
+            Self::LayoutFloor1Mddle0Ceiling0(s) => s.value() << 4 | 0x1,
+            Self::LayoutFloor1Mddle0Ceiling1(s) => s.value() << 4 | 0x2,
+            Self::LayoutFloor1Mddle0Ceiling3(s) => s.value() << 4 | 0x3,
+            Self::LayoutFloor1Mddle0Ceiling4(s) => s.value() << 4 | 0x4,
+            Self::LayoutFloor1Mddle0Ceiling8(s) => s.value() << 4 | 0x5,
+            Self::LayoutFloor4Mddle0Ceiling1(s) => s.value() << 4 | 0x6,
+            Self::LayoutFloor4Mddle0Ceiling3(s) => s.value() << 4 | 0x7,
+            Self::LayoutFloor4Mddle0Ceiling4(s) => s.value() << 4 | 0x8,
+            Self::LayoutFloor5Mddle0Ceiling1(s) => s.value() << 4 | 0x9,
+            Self::LayoutFloor0Mddle0Ceiling1(s) => s.value() << 4 | 0xa,
+            Self::LayoutFloor5Mddle0Ceiling4(s) => s.value() << 4 | 0xb,
+            Self::LayoutFloor8Mddle0Ceiling1(s) => s.value() << 4 | 0xc,
+            Self::LayoutFloor1Mddle5Ceiling1(s) => s.value() << 4 | 0xd,
+            Self::LayoutFloor1Mddle4Ceiling1(s) => s.value() << 4 | 0xe,
+            Self::LayoutFull(s) => s.value() << 4 | 0xf,
+            Self::ChangeBackground(b) => 0x40 | b.value(),
+
+            // Y offset 0xf
+            Self::LiftRope => 0x00,
+            Self::BalanceLiftVerticalRope(n) => 0x10 + n - 1,
+            Self::Castle(n) => 0x20 + n,
+            Self::Staircase(n) => 0x30 + n - 1,
+            Self::TallReverseLPipe(n) => 0x40 + n,
+            Self::Nothing => 0x60,
+
+            Self::Invalid(byte) => *byte,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_kind_round_trip() {
+        // every object byte at every y coordinate
+        for y_coordinate in 0x0..=0xf {
+            for byte in 0x00..=0x7f {
+                let kind = LevelObjectKind::new(y_coordinate, byte);
+                assert_eq!(
+                    kind.value(),
+                    byte,
+                    "object {:?} at y {} did not round trip",
+                    kind,
+                    y_coordinate
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_object_round_trip() {
+        // every possible object (excluding the 0xFD end level marker)
+        for byte0 in 0x00..=0xff {
+            if byte0 == 0xFD {
+                continue;
+            }
+            for byte1 in 0x00..=0xff {
+                let object = LevelObject::from_bytes(&[byte0, byte1]);
+                assert_eq!(
+                    object.to_bytes(),
+                    [byte0, byte1],
+                    "object {:?} did not round trip",
+                    object
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_object_validate() {
+        let mut object = LevelObject::from_bytes(&[0x47, 0x24]);
+        assert!(object.validate().is_ok());
+
+        // bricks only go up to 16 wide
+        object.kind = LevelObjectKind::HorizontalBrick(17);
+        assert!(object.validate().is_err());
+
+        // holes are only found at y 0xc
+        object.kind = LevelObjectKind::Hole(0);
+        assert!(object.validate().is_err());
+        object.y_coordinate = 0xc;
+        object.kind = LevelObjectKind::Hole(2);
+        assert!(object.validate().is_ok());
+
+        let object = LevelObject::from_bytes(&[0xfd, 0x00]);
+        assert!(object.validate().is_err());
+    }
+
+    #[test]
+    fn test_object_data_round_trip() {
+        let bytes = [0x07, 0x81, 0x47, 0x24, 0x57, 0x00, 0x63, 0x01, 0xFD];
+        let object_data = LevelObjectData::from_bytes(&bytes);
+        assert_eq!(object_data.objects.len(), 4);
+        assert_eq!(object_data.objects[1].x_coordinate, 4);
+        assert_eq!(object_data.objects[1].y_coordinate, 7);
+        assert_eq!(object_data.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_object_data_to_bytes() {
+        let object = |x_coordinate, y_coordinate, new_page_flag, byte| {
+            let kind = LevelObjectKind::new(y_coordinate, byte);
+            LevelObject { kind, x_coordinate, y_coordinate, new_page_flag }
+        };
+        let object_data = LevelObjectData {
+            objects: vec![
+                object(0, 7, true, 0x01),
+                object(4, 7, false, 0x24),
+                object(5, 7, false, 0x00),
+                object(6, 3, false, 0x01),
+            ],
+        };
+        let bytes = [0x07, 0x81, 0x47, 0x24, 0x57, 0x00, 0x63, 0x01, 0xFD];
+        assert_eq!(object_data.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_object_positions() {
+        // a page skip to page 2, then one ignored because of the page flag
+        let bytes = [0x24, 0x01, 0x0d, 0x02, 0x55, 0x21, 0x1d, 0x83, 0xFD];
+        let object_data = LevelObjectData::from_bytes(&bytes);
+        let positions: Vec<_> = object_data
+            .with_positions()
+            .map(|(position, _)| (position.page, position.column, position.x()))
+            .collect();
+        assert_eq!(positions, [(0, 2, 2), (2, 0, 32), (2, 5, 37), (3, 1, 49)]);
+    }
+
+    #[test]
+    fn test_object_data_truncated() {
+        assert_eq!(
+            LevelObjectData::try_from_bytes(&[0x07, 0x81, 0x47, 0x24])
+                .unwrap_err(),
+            Error::MissingTerminator {
+                what: "object data",
+                offset: 0,
+                terminator: 0xFD
+            }
+        );
+        assert_eq!(
+            LevelObjectData::try_from_bytes(&[0x07, 0x81, 0x47]).unwrap_err(),
+            Error::Truncated {
+                what: "level object",
+                offset: 2,
+                needed: 2,
+                available: 1
+            }
+        );
+    }
+}
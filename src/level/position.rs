@@ -0,0 +1,77 @@
+/// Position of an object or enemy within an area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelPosition {
+    /// Page (screen) of the area, 16 columns wide.
+    pub page: u8,
+    pub column: u8,
+    pub row: u8,
+}
+
+impl LevelPosition {
+    /// Column counted from the start of the area.
+    pub fn x(&self) -> u16 {
+        self.page as u16 * 16 + self.column as u16
+    }
+}
+
+/**
+ * Tracks the current page while walking object or enemy data.
+ *
+ * Mirrors the game's area and enemy parsers: the new page flag moves to the
+ * next page, and a page skip jumps straight to a page unless the new page
+ * flag was already set on the same entry.
+ */
+#[derive(Debug, Default)]
+pub struct PageCursor {
+    page: u8,
+}
+
+impl PageCursor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The page of the last entry.
+    pub fn page(&self) -> u8 {
+        self.page
+    }
+
+    /// Step over an entry and get its position.
+    pub fn advance(
+        &mut self,
+        new_page_flag: bool,
+        page_skip: Option<u8>,
+        column: u8,
+        row: u8,
+    ) -> LevelPosition {
+        if new_page_flag {
+            self.page = self.page.wrapping_add(1);
+        } else if let Some(page) = page_skip {
+            self.page = page;
+        }
+
+        LevelPosition { page: self.page, column, row }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_cursor() {
+        let mut cursor = PageCursor::new();
+        assert_eq!(cursor.advance(false, None, 3, 4).x(), 3);
+
+        let position = cursor.advance(true, None, 2, 11);
+        assert_eq!(position, LevelPosition { page: 1, column: 2, row: 11 });
+        assert_eq!(position.x(), 18);
+
+        assert_eq!(cursor.advance(false, Some(6), 0, 15).page, 6);
+        assert_eq!(cursor.advance(false, None, 9, 1).x(), 0x69);
+
+        // a page skip is ignored when the new page flag is also set
+        assert_eq!(cursor.advance(true, Some(2), 0, 15).page, 7);
+        assert_eq!(cursor.page(), 7);
+    }
+}
@@ -0,0 +1,102 @@
+/*!
+ * Levels as JSON, RON or YAML text, so they can be edited and kept in git.
+ *
+ * Every kind and header setting is written by name and coordinates as
+ * numbers. Levels read back are checked with `Level::validate`, so they
+ * can always be encoded with `Level::area_data_to_bytes` and
+ * `Level::enemy_data_to_bytes`.
+ */
+
+use anyhow::Result;
+
+use crate::*;
+
+impl Level {
+    /// Write this level as pretty printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Read a level written by `Level::to_json`.
+    pub fn from_json(text: &str) -> Result<Self> {
+        Self::validated(serde_json::from_str(text)?)
+    }
+
+    /// Write this level as pretty printed RON.
+    pub fn to_ron(&self) -> Result<String> {
+        let config = ron::ser::PrettyConfig::default();
+        Ok(ron::ser::to_string_pretty(self, config)?)
+    }
+
+    /// Read a level written by `Level::to_ron`.
+    pub fn from_ron(text: &str) -> Result<Self> {
+        Self::validated(ron::from_str(text)?)
+    }
+
+    /// Write this level as YAML.
+    pub fn to_yaml(&self) -> Result<String> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+
+    /// Read a level written by `Level::to_yaml`.
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        Self::validated(serde_yaml::from_str(text)?)
+    }
+
+    fn validated(level: Self) -> Result<Self> {
+        level.validate()?;
+        Ok(level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_level() -> Level {
+        Level {
+            level_header: LevelHeader::from_bytes(&[0x50, 0x21]),
+            // a question block, a hole and a page skip
+            object_data: LevelObjectData::from_bytes(&[
+                0x23, 0x00, 0x5c, 0x02, 0x0d, 0x02, 0xfd,
+            ]),
+            // a goomba and a pipe pointer to underground-0
+            enemy_data: LevelEnemyData::from_bytes(&[
+                0x8b, 0x06, 0x1e, 0xc0, 0x02, 0xff,
+            ]),
+        }
+    }
+
+    fn assert_same_bytes(a: &Level, b: &Level) {
+        assert_eq!(a.area_data_to_bytes(), b.area_data_to_bytes());
+        assert_eq!(a.enemy_data_to_bytes(), b.enemy_data_to_bytes());
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let level = test_level();
+
+        let json = level.to_json().unwrap();
+        assert!(json.contains("\"Goomba\""));
+        assert_same_bytes(&level, &Level::from_json(&json).unwrap());
+
+        let ron = level.to_ron().unwrap();
+        assert_same_bytes(&level, &Level::from_ron(&ron).unwrap());
+
+        let yaml = level.to_yaml().unwrap();
+        assert_same_bytes(&level, &Level::from_yaml(&yaml).unwrap());
+    }
+
+    #[test]
+    fn test_text_invalid() {
+        let json = test_level().to_json().unwrap();
+
+        // a hole moved up from y 0xc can't be encoded
+        let moved =
+            json.replacen("\"y_coordinate\": 12", "\"y_coordinate\": 3", 1);
+        assert!(Level::from_json(&moved).is_err());
+
+        let unknown = json.replacen("\"Goomba\"", "\"Gumba\"", 1);
+        assert!(Level::from_json(&unknown).is_err());
+    }
+}
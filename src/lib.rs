@@ -0,0 +1,35 @@
+/*!
+ * Tools for parsing and understanding a Super Mario Bros. 1 (NES) rom.
+ */
+
+mod bps;
+mod chr;
+mod cpu;
+mod demo;
+mod disasm;
+mod error;
+mod ips;
+mod level;
+mod music;
+mod palette;
+mod render;
+mod rom;
+mod title;
+pub mod util;
+
+#[cfg(test)]
+mod test_util;
+
+pub use bps::*;
+pub use chr::*;
+pub use cpu::*;
+pub use demo::*;
+pub use disasm::*;
+pub use error::*;
+pub use ips::*;
+pub use level::*;
+pub use music::*;
+pub use palette::*;
+pub use render::*;
+pub use rom::*;
+pub use title::*;
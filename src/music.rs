@@ -0,0 +1,697 @@
+/*!
+ * The game's music, decoded from the sound engine's data.
+ *
+ * A song is played as one or more parts. Each part has a header pointing
+ * at its note data, with the offset of each channel's notes within that
+ * data. Square 2 leads: a part ends when its square 2 notes do, and the
+ * other channels are cut off there.
+ */
+
+mod encode;
+mod midi;
+
+use std::ops::RangeInclusive;
+
+use anyhow::{anyhow, Result};
+
+use crate::util::find_pattern;
+use crate::*;
+
+/// Size of an area music header, event music has no noise offset.
+pub const MUSIC_HEADER_SIZE: usize = 6;
+
+/// Entries of the note length table each tempo uses.
+pub(crate) const NOTE_LENGTHS: usize = 8;
+
+/// Noise note value that plays no beat, see `MusicNote::midi_key`.
+pub const NOISE_REST: u8 = 0x04;
+
+/**
+ * `LoadHeader`, reading the header offset table (from one byte before the
+ * table, as music numbers start at 1) and then the header itself:
+ *
+ * ```text
+ * lda MusicHeaderData-1,y
+ * tay
+ * lda MusicHeaderData,y
+ * sta NoteLenLookupTblOfs
+ * ```
+ */
+const LOAD_HEADER_CODE: [Option<u8>; 9] = [
+    Some(0xb9),
+    None,
+    None,
+    Some(0xa8),
+    Some(0xb9),
+    None,
+    None,
+    Some(0x85),
+    None,
+];
+
+/**
+ * `ProcessLengthData`, looking up the length of a note:
+ *
+ * ```text
+ * and #%00000111
+ * clc
+ * adc $f0
+ * adc NoteLengthTblAdder
+ * tay
+ * lda MusicLengthLookupTbl,y
+ * ```
+ */
+const PROCESS_LENGTH_CODE: [Option<u8>; 12] = [
+    Some(0x29),
+    Some(0x07),
+    Some(0x18),
+    Some(0x65),
+    None,
+    Some(0x6d),
+    None,
+    None,
+    Some(0xa8),
+    Some(0xb9),
+    None,
+    None,
+];
+
+/**
+ * `Dump_Freq_Regs`, writing a note's timer period to the APU:
+ *
+ * ```text
+ * tay
+ * lda FreqRegLookupTbl+1,y
+ * beq NoTone
+ * sta SND_REGISTER+2,x
+ * lda FreqRegLookupTbl,y
+ * ```
+ */
+const DUMP_FREQ_REGS_CODE: [Option<u8>; 12] = [
+    Some(0xa8),
+    Some(0xb9),
+    None,
+    None,
+    Some(0xf0),
+    None,
+    Some(0x9d),
+    Some(0x02),
+    Some(0x40),
+    Some(0xb9),
+    None,
+    None,
+];
+
+/// The APU channels used by music.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MusicChannel {
+    Square2,
+    Square1,
+    Triangle,
+    Noise,
+}
+
+impl MusicChannel {
+    pub const ALL: [Self; 4] =
+        [Self::Square2, Self::Square1, Self::Triangle, Self::Noise];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Square2 => "square 2",
+            Self::Square1 => "square 1",
+            Self::Triangle => "triangle",
+            Self::Noise => "noise",
+        }
+    }
+
+    /**
+     * Note values the channel can encode: offsets into the frequency table
+     * for tones, kinds of beat for noise. 0 is left out as it ends (or
+     * starts over) a channel's data.
+     */
+    fn note_values(&self) -> impl Iterator<Item = u8> {
+        let last = match self {
+            Self::Square2 | Self::Triangle => 0x7e,
+            Self::Square1 | Self::Noise => 0x3e,
+        };
+        (0x02..=last).step_by(2)
+    }
+}
+
+/// A single note, rest or drum beat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MusicNote {
+    /// Offset into the frequency table, or the kind of beat for noise.
+    pub value: u8,
+    /// Length in frames (60ths of a second).
+    pub frames: u8,
+    /// APU timer period of the note, `None` for rests and noise.
+    pub period: Option<u16>,
+}
+
+impl MusicNote {
+    fn new(value: u8, frames: u8) -> Self {
+        Self { value, frames, period: None }
+    }
+}
+
+/// Where a part's notes are, see `MusicPart`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MusicHeader {
+    /// Offset into the note length table, which sets the tempo.
+    pub length_offset: u8,
+    /// Address of the part's data, starting with the square 2 notes.
+    pub data_addr: u16,
+    pub triangle_offset: u8,
+    pub square1_offset: u8,
+    /// Only area music has a noise channel.
+    pub noise_offset: Option<u8>,
+}
+
+impl MusicHeader {
+    /// Parse a header, with a noise offset for area music.
+    pub fn try_from_bytes(bytes: &[u8], noise: bool) -> Result<Self, Error> {
+        let size = MUSIC_HEADER_SIZE - !noise as usize;
+        Error::check_len("music header", bytes, size)?;
+        Ok(Self {
+            length_offset: bytes[0],
+            data_addr: u16::from_le_bytes([bytes[1], bytes[2]]),
+            triangle_offset: bytes[3],
+            square1_offset: bytes[4],
+            noise_offset: noise.then(|| bytes[5]),
+        })
+    }
+
+    pub fn from_bytes(bytes: &[u8], noise: bool) -> Self {
+        Self::try_from_bytes(bytes, noise)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let [low, high] = self.data_addr.to_le_bytes();
+        let mut bytes = vec![
+            self.length_offset,
+            low,
+            high,
+            self.triangle_offset,
+            self.square1_offset,
+        ];
+        bytes.extend(self.noise_offset);
+        bytes
+    }
+}
+
+/// Reads one channel's notes from a part's data.
+struct NoteReader<'a> {
+    bytes: &'a [u8],
+    lengths: &'a [u8],
+    pos: usize,
+    /// One past the last byte read, as square 1 and noise go back.
+    end: usize,
+}
+
+impl NoteReader<'_> {
+    fn next(&mut self, what: &'static str) -> Result<u8, Error> {
+        let byte = self.bytes.get(self.pos).copied();
+        let byte = byte.ok_or(Error::Truncated {
+            what,
+            offset: self.pos,
+            needed: 1,
+            available: 0,
+        })?;
+        self.pos += 1;
+        self.end = self.end.max(self.pos);
+        Ok(byte)
+    }
+
+    fn length(&self, idx: u8) -> Result<u8, Error> {
+        let length = self.lengths.get(idx as usize).copied();
+        length.ok_or(Error::Truncated {
+            what: "note length table",
+            offset: 0,
+            needed: idx as usize + 1,
+            available: self.lengths.len(),
+        })
+    }
+
+    /**
+     * Read square 2 or triangle notes, until a 0 byte.
+     *
+     * A byte with bit 7 set is the length of the notes after it, the note
+     * following straight after it.
+     */
+    fn lead(&mut self, what: &'static str) -> Result<Vec<MusicNote>, Error> {
+        let mut notes = vec![];
+        let mut length = None;
+        loop {
+            let mut byte = self.next(what)?;
+            if byte & 0x80 != 0 {
+                length = Some(self.length(byte & 0x07)?);
+                byte = self.next(what)?;
+            }
+            if byte == 0 {
+                return Ok(notes);
+            }
+            let frames = match length {
+                Some(frames) => frames,
+                None => self.length(0)?,
+            };
+            notes.push(MusicNote::new(byte, frames));
+        }
+    }
+
+    /**
+     * Read square 1 or noise notes, which have no end of their own.
+     *
+     * Each byte holds both a note and its length. A 0 byte changes the
+     * square 1 sweep for the death music, or starts the noise over. Along
+     * with the notes, the index of the note each sweep change comes before
+     * is returned.
+     */
+    fn alternate(
+        &mut self,
+        what: &'static str,
+        frames: usize,
+        restart: bool,
+    ) -> Result<(Vec<MusicNote>, Vec<usize>), Error> {
+        let start = self.pos;
+        let mut notes: Vec<MusicNote> = vec![];
+        let mut sweeps = vec![];
+        let mut total = 0;
+        let mut restarted = 0;
+        while total < frames {
+            let byte = self.next(what)?;
+            if byte == 0 {
+                if restart {
+                    // nothing but a restart would never end
+                    if restarted == notes.len() {
+                        break;
+                    }
+                    restarted = notes.len();
+                    self.pos = start;
+                } else {
+                    sweeps.push(notes.len());
+                }
+                continue;
+            }
+            let length = self.length(((byte & 0x01) << 2) | (byte >> 6))?;
+            notes.push(MusicNote::new(byte & 0x3e, length));
+            total += length as usize;
+        }
+        Ok((notes, sweeps))
+    }
+}
+
+/// Cut notes off after `frames` frames.
+fn clip_notes(notes: &mut Vec<MusicNote>, frames: usize) {
+    let mut total = 0;
+    notes.retain_mut(|note| {
+        let left = frames.saturating_sub(total);
+        total += note.frames as usize;
+        note.frames = note.frames.min(left.min(u8::MAX as usize) as u8);
+        note.frames > 0
+    });
+}
+
+/// A header and the notes of each channel it points to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MusicPart {
+    pub header: MusicHeader,
+    pub square2: Vec<MusicNote>,
+    pub square1: Vec<MusicNote>,
+    /// Square 1 notes the sweep changes before, by index.
+    pub square1_sweeps: Vec<usize>,
+    pub triangle: Vec<MusicNote>,
+    pub noise: Vec<MusicNote>,
+}
+
+impl MusicPart {
+    /**
+     * Decode the notes of a part from the bytes at its data address.
+     *
+     * `lengths` is the note length table from the header's offset on.
+     * Channels with an offset of 0 aren't played.
+     */
+    pub fn try_from_bytes(
+        header: MusicHeader,
+        bytes: &[u8],
+        lengths: &[u8],
+    ) -> Result<Self, Error> {
+        Self::read(header, bytes, lengths).map(|(part, _)| part)
+    }
+
+    /// Decode a part, along with how many bytes of its data were read.
+    pub(crate) fn read(
+        header: MusicHeader,
+        bytes: &[u8],
+        lengths: &[u8],
+    ) -> Result<(Self, usize), Error> {
+        let noise_offset = header.noise_offset.unwrap_or(0);
+        let offsets =
+            [0, header.triangle_offset, header.square1_offset, noise_offset];
+        let mut readers = offsets.map(|offset| NoteReader {
+            bytes,
+            lengths,
+            pos: offset as usize,
+            end: 0,
+        });
+        let [square2_reader, triangle_reader, square1_reader, noise_reader] =
+            &mut readers;
+
+        let square2 = square2_reader.lead("square 2 music data")?;
+        let frames = square2.iter().map(|note| note.frames as usize).sum();
+
+        let mut triangle = match header.triangle_offset {
+            0 => vec![],
+            _ => triangle_reader.lead("triangle music data")?,
+        };
+        let (mut square1, square1_sweeps) = match header.square1_offset {
+            0 => (vec![], vec![]),
+            _ => square1_reader.alternate(
+                "square 1 music data",
+                frames,
+                false,
+            )?,
+        };
+        let mut noise = match noise_offset {
+            0 => vec![],
+            _ => noise_reader.alternate("noise music data", frames, true)?.0,
+        };
+        for notes in [&mut triangle, &mut square1, &mut noise] {
+            clip_notes(notes, frames);
+        }
+
+        let len = readers.iter().map(|reader| reader.end).max().unwrap_or(0);
+        let part =
+            Self { header, square2, square1, square1_sweeps, triangle, noise };
+        Ok((part, len))
+    }
+
+    pub fn from_bytes(
+        header: MusicHeader,
+        bytes: &[u8],
+        lengths: &[u8],
+    ) -> Self {
+        Self::try_from_bytes(header, bytes, lengths)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Get the notes played on a channel.
+    pub fn channel(&self, channel: MusicChannel) -> &[MusicNote] {
+        match channel {
+            MusicChannel::Square2 => &self.square2,
+            MusicChannel::Square1 => &self.square1,
+            MusicChannel::Triangle => &self.triangle,
+            MusicChannel::Noise => &self.noise,
+        }
+    }
+
+    pub fn channel_mut(
+        &mut self,
+        channel: MusicChannel,
+    ) -> &mut Vec<MusicNote> {
+        match channel {
+            MusicChannel::Square2 => &mut self.square2,
+            MusicChannel::Square1 => &mut self.square1,
+            MusicChannel::Triangle => &mut self.triangle,
+            MusicChannel::Noise => &mut self.noise,
+        }
+    }
+
+    /// Length of the part in frames, set by its square 2 notes.
+    pub fn frames(&self) -> usize {
+        self.square2.iter().map(|note| note.frames as usize).sum()
+    }
+}
+
+/// How to find a song in the music header offset table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SongInfo {
+    pub name: &'static str,
+    /// Entries of the header offset table for each part, played in order.
+    pub headers: RangeInclusive<u8>,
+    /// Area music loops and has a noise channel, event music doesn't.
+    pub area: bool,
+    /// The part area music loops back to.
+    pub loop_part: Option<usize>,
+}
+
+const fn event_song(name: &'static str, header: u8) -> SongInfo {
+    SongInfo { name, headers: header..=header, area: false, loop_part: None }
+}
+
+const fn area_song(name: &'static str, header: u8) -> SongInfo {
+    SongInfo { name, headers: header..=header, area: true, loop_part: Some(0) }
+}
+
+/**
+ * Every song of the game, in the order of the header offset table.
+ *
+ * The ground music is played as 33 parts, looping back to the second one
+ * as the first is only a lead-in. The star and cloud music are the same.
+ */
+pub const SONGS: &[SongInfo] = &[
+    event_song("death", 0),
+    event_song("game-over", 1),
+    event_song("victory", 2),
+    event_song("castle-complete", 3),
+    event_song("level-complete", 5),
+    event_song("hurry-up", 6),
+    area_song("water", 9),
+    area_song("underground", 10),
+    area_song("castle", 11),
+    area_song("star", 12),
+    area_song("pipe-intro", 13),
+    SongInfo {
+        name: "ground",
+        headers: 0x10..=0x30,
+        area: true,
+        loop_part: Some(1),
+    },
+];
+
+/// A decoded song, see `SONGS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Song {
+    pub name: &'static str,
+    pub parts: Vec<MusicPart>,
+    pub loop_part: Option<usize>,
+}
+
+impl Song {
+    /// Length of the song in frames, playing every part once.
+    pub fn frames(&self) -> usize {
+        self.parts.iter().map(MusicPart::frames).sum()
+    }
+}
+
+/// CPU addresses of the sound engine's tables, see `Rom::music_tables`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MusicTables {
+    /// `MusicHeaderData`, header offsets followed by the headers.
+    pub headers: u16,
+    /// `MusicLengthLookupTbl`, note lengths in frames.
+    pub lengths: u16,
+    /// `FreqRegLookupTbl`, big endian APU timer periods.
+    pub frequencies: u16,
+}
+
+/// Get the operand of the instruction at `pos` in `bytes`.
+fn operand_at(bytes: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([bytes[pos + 1], bytes[pos + 2]])
+}
+
+impl Rom {
+    /// Find the sound engine's tables by the code reading them.
+    pub fn music_tables(&self) -> Result<MusicTables> {
+        let prg = self.prg();
+        let headers = find_pattern(prg, &LOAD_HEADER_CODE)
+            .map(|pos| (operand_at(prg, pos), operand_at(prg, pos + 4)))
+            .find(|&(offsets, headers)| offsets.wrapping_add(1) == headers)
+            .map(|(_, headers)| headers);
+        let lengths = find_pattern(prg, &PROCESS_LENGTH_CODE)
+            .map(|pos| operand_at(prg, pos + 9))
+            .next();
+        let frequencies = find_pattern(prg, &DUMP_FREQ_REGS_CODE)
+            .map(|pos| (operand_at(prg, pos + 1), operand_at(prg, pos + 9)))
+            .find(|&(low, high)| high.wrapping_add(1) == low)
+            .map(|(_, high)| high);
+
+        match (headers, lengths, frequencies) {
+            (Some(headers), Some(lengths), Some(frequencies)) => {
+                Ok(MusicTables { headers, lengths, frequencies })
+            }
+            _ => Err(anyhow!("no sound engine found in prg-rom")),
+        }
+    }
+
+    /// Read the header at an entry of the music header offset table.
+    pub fn music_header(&self, index: u8, area: bool) -> Result<MusicHeader> {
+        let tables = self.music_tables()?;
+        self.read_music_header(&tables, index, area)
+    }
+
+    /// Get the address of the header at an entry of the offset table.
+    fn music_header_addr(
+        &self,
+        tables: &MusicTables,
+        index: u8,
+    ) -> Result<u16> {
+        let offset =
+            self.read_cpu_u8(tables.headers.wrapping_add(index as u16))?;
+        Ok(tables.headers.wrapping_add(offset as u16))
+    }
+
+    fn read_music_header(
+        &self,
+        tables: &MusicTables,
+        index: u8,
+        area: bool,
+    ) -> Result<MusicHeader> {
+        let addr = self.music_header_addr(tables, index)?;
+        Ok(MusicHeader::try_from_bytes(self.cpu_slice(addr)?, area)?)
+    }
+
+    /// Get the note length table from a header's offset on.
+    fn music_lengths(
+        &self,
+        tables: &MusicTables,
+        header: &MusicHeader,
+    ) -> Result<&[u8]> {
+        self.cpu_slice(tables.lengths.wrapping_add(header.length_offset as u16))
+    }
+
+    /// Get the APU timer period of a note, `None` for a rest.
+    fn note_period(
+        &self,
+        tables: &MusicTables,
+        value: u8,
+    ) -> Result<Option<u16>> {
+        let addr = tables.frequencies.wrapping_add(value as u16);
+        let high = self.read_cpu_u8(addr)?;
+        let low = self.read_cpu_u8(addr.wrapping_add(1))?;
+        Ok((low != 0).then(|| u16::from_le_bytes([low, high & 0x07])))
+    }
+
+    /// Find the first note value a channel can use with a period.
+    fn music_note_value(
+        &self,
+        tables: &MusicTables,
+        channel: MusicChannel,
+        period: Option<u16>,
+    ) -> Result<Option<u8>> {
+        for value in channel.note_values() {
+            if self.note_period(tables, value)? == period {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Decode a part, along with the length of its data.
+    fn read_music_part(
+        &self,
+        tables: &MusicTables,
+        header: MusicHeader,
+    ) -> Result<(MusicPart, usize)> {
+        let bytes = self.cpu_slice(header.data_addr)?;
+        let lengths = self.music_lengths(tables, &header)?;
+        let (mut part, len) =
+            MusicPart::read(header, bytes, lengths).map_err(|error| {
+                anyhow!("{} at ${:04x}", error, header.data_addr)
+            })?;
+
+        for notes in [&mut part.square2, &mut part.square1, &mut part.triangle]
+        {
+            for note in notes {
+                note.period = self.note_period(tables, note.value)?;
+            }
+        }
+        Ok((part, len))
+    }
+
+    /// Decode a song, see `SONGS`.
+    pub fn song(&self, info: &SongInfo) -> Result<Song> {
+        let tables = self.music_tables()?;
+        let parts = info
+            .headers
+            .clone()
+            .map(|index| {
+                let header =
+                    self.read_music_header(&tables, index, info.area)?;
+                Ok(self.read_music_part(&tables, header)?.0)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Song { name: info.name, parts, loop_part: info.loop_part })
+    }
+
+    /// Decode every song of the game.
+    pub fn songs(&self) -> Result<Vec<Song>> {
+        SONGS.iter().map(|info| self.song(info)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LENGTHS: [u8; 8] = [0x05, 0x0a, 0x14, 0x28, 0x50, 0x1e, 0x3c, 0x02];
+
+    #[test]
+    fn test_music_header() {
+        let bytes = [0x08, 0x00, 0xf5, 0x11, 0x07, 0x1a];
+        let header = MusicHeader::from_bytes(&bytes, true);
+        assert_eq!(header.length_offset, 0x08);
+        assert_eq!(header.data_addr, 0xf500);
+        assert_eq!(header.noise_offset, Some(0x1a));
+        assert_eq!(header.to_bytes(), bytes);
+
+        let header = MusicHeader::from_bytes(&bytes, false);
+        assert_eq!(header.noise_offset, None);
+        assert_eq!(header.to_bytes(), bytes[..5]);
+        assert!(MusicHeader::try_from_bytes(&bytes[..5], true).is_err());
+    }
+
+    #[test]
+    fn test_music_part() {
+        let header = MusicHeader {
+            length_offset: 0,
+            data_addr: 0xf500,
+            triangle_offset: 7,
+            square1_offset: 5,
+            noise_offset: Some(11),
+        };
+        let bytes = [
+            // square 2: 0x0a frames of 0x30 and 0x32, 0x05 frames of 0x04
+            0x81, 0x30, 0x32, 0x80, 0x04, 0x00,
+            // square 1: 0x14 frames of 0x3e, then reads on into the triangle
+            0xbe,
+            // triangle: 0x28 frames of 0x20, cut off at the end of the part
+            0x83, 0x20, 0x00, 0x00,
+            // noise: 0x05 frames of a short beat, over and over
+            0x10, 0x00,
+        ];
+        let part = MusicPart::from_bytes(header, &bytes, &LENGTHS);
+        assert_eq!(part.frames(), 0x19);
+        assert_eq!(
+            part.square2,
+            [
+                MusicNote::new(0x30, 0x0a),
+                MusicNote::new(0x32, 0x0a),
+                MusicNote::new(0x04, 0x05),
+            ]
+        );
+        assert_eq!(
+            part.square1,
+            [MusicNote::new(0x3e, 0x14), MusicNote::new(0x02, 0x05)]
+        );
+        assert_eq!(part.triangle, [MusicNote::new(0x20, 0x19)]);
+        assert_eq!(part.noise.len(), 5);
+        assert!(part.noise.iter().all(|&note| note == MusicNote::new(0x10, 5)));
+
+        // square 2 must end
+        assert!(
+            MusicPart::try_from_bytes(header, &bytes[..4], &LENGTHS).is_err()
+        );
+    }
+}
@@ -0,0 +1,592 @@
+use std::ops::Range;
+
+use anyhow::{anyhow, bail, ensure, Result};
+
+use crate::*;
+
+fn invalid(what: &'static str, value: String) -> Error {
+    Error::InvalidValue { what, value }
+}
+
+/**
+ * Find the entry of the note length table a note is encoded with.
+ *
+ * A note cut off by the end of the part can be encoded as any length long
+ * enough, the shortest is used.
+ */
+fn length_index(
+    channel: MusicChannel,
+    lengths: &[u8],
+    frames: u8,
+    cut_off: bool,
+) -> Result<u8, Error> {
+    let lengths = &lengths[..lengths.len().min(NOTE_LENGTHS)];
+    let exact = lengths.iter().position(|&length| length == frames);
+    let longer = || {
+        let longer =
+            lengths.iter().enumerate().filter(|(_, &length)| length >= frames);
+        longer.min_by_key(|(_, &length)| length).map(|(idx, _)| idx)
+    };
+    match exact.or_else(|| cut_off.then(longer).flatten()) {
+        Some(idx) => Ok(idx as u8),
+        None => Err(invalid(
+            "music note length",
+            format!("{} note of {} frames", channel.name(), frames),
+        )),
+    }
+}
+
+/**
+ * Find the shortest run of beats that repeats into the noise notes.
+ *
+ * The noise channel starts its data over when it ends, so only the run
+ * needs encoding. The last note may be shorter, cut off by the part.
+ */
+fn noise_pattern(notes: &[MusicNote]) -> &[MusicNote] {
+    let repeats = |len: usize| {
+        notes.iter().enumerate().all(|(idx, note)| {
+            let expected = notes[idx % len];
+            let last = idx + 1 == notes.len();
+            note.value == expected.value
+                && (note.frames == expected.frames
+                    || (last && note.frames < expected.frames))
+        })
+    };
+    let len = (1..notes.len()).find(|&len| repeats(len));
+    &notes[..len.unwrap_or(notes.len())]
+}
+
+/**
+ * Encode one channel's notes, the reverse of `NoteReader`.
+ *
+ * Square 2 sets the length of the part, `frames`. The other channels are
+ * cut off there, so square 1 has to last at least as long as it doesn't
+ * end on its own. `sweeps` are the square 1 notes to change the sweep
+ * before, see `MusicPart::square1_sweeps`.
+ */
+fn encode_notes(
+    channel: MusicChannel,
+    notes: &[MusicNote],
+    sweeps: &[usize],
+    lengths: &[u8],
+    frames: usize,
+) -> Result<Vec<u8>, Error> {
+    let notes = match channel {
+        MusicChannel::Noise => noise_pattern(notes),
+        _ => notes,
+    };
+    let lead =
+        matches!(channel, MusicChannel::Square2 | MusicChannel::Triangle);
+    let invalid_value = |note: &MusicNote| {
+        let value = format!("{} note {:#04x}", channel.name(), note.value);
+        invalid("music note", value)
+    };
+
+    if sweeps.iter().any(|&idx| idx >= notes.len()) {
+        let value =
+            format!("{} sweep change after the last note", channel.name());
+        return Err(invalid("music note", value));
+    }
+
+    let mut bytes = vec![];
+    let mut total = 0;
+    let mut length = None;
+    for (idx, note) in notes.iter().enumerate() {
+        // a 0 byte changes the sweep, only square 1 reads it as that
+        bytes
+            .extend(sweeps.iter().filter(|&&sweep| sweep == idx).map(|_| 0x00));
+        if total >= frames && channel != MusicChannel::Square2 {
+            let value =
+                format!("{} notes after the end of the part", channel.name());
+            return Err(invalid("music note", value));
+        }
+        total += note.frames as usize;
+        let cut_off = channel != MusicChannel::Square2 && total >= frames;
+        let length_idx = length_index(channel, lengths, note.frames, cut_off)?;
+
+        if lead {
+            if note.value == 0 || note.value & 0x80 != 0 {
+                return Err(invalid_value(note));
+            }
+            if length != Some(length_idx) {
+                bytes.push(0x80 | length_idx);
+                length = Some(length_idx);
+            }
+            bytes.push(note.value);
+        } else {
+            let byte =
+                note.value | ((length_idx & 0x03) << 6) | (length_idx >> 2);
+            if note.value & !0x3e != 0 || byte == 0 {
+                return Err(invalid_value(note));
+            }
+            bytes.push(byte);
+        }
+    }
+
+    if notes.is_empty() {
+        return Ok(bytes);
+    }
+    match channel {
+        // square 1 has no end, it would read on into the next channel
+        MusicChannel::Square1 if total < frames => {
+            let value = format!("square 1 notes end after {} frames", total);
+            Err(invalid("music note", value))
+        }
+        MusicChannel::Square1 => Ok(bytes),
+        // end the channel, or start the noise over
+        _ => {
+            bytes.push(0x00);
+            Ok(bytes)
+        }
+    }
+}
+
+impl MusicPart {
+    /**
+     * Encode the notes of a part, as stored at its data address.
+     *
+     * `lengths` is the note length table from the header's offset on, each
+     * note must last one of its lengths. The header is returned with the
+     * offset of each channel in the data, channels with no notes aren't
+     * played.
+     */
+    pub fn encode(
+        &self,
+        lengths: &[u8],
+    ) -> Result<(MusicHeader, Vec<u8>), Error> {
+        if self.square2.is_empty() {
+            let value = "part with no square 2 notes".to_string();
+            return Err(invalid("music part", value));
+        }
+        if self.header.noise_offset.is_none() && !self.noise.is_empty() {
+            let value = "noise notes in event music".to_string();
+            return Err(invalid("music part", value));
+        }
+
+        let frames = self.frames();
+        let mut header = self.header;
+        let mut bytes = vec![];
+        for channel in MusicChannel::ALL {
+            let notes = self.channel(channel);
+            let sweeps = match channel {
+                MusicChannel::Square1 => &self.square1_sweeps[..],
+                _ => &[],
+            };
+            let encoded =
+                encode_notes(channel, notes, sweeps, lengths, frames)?;
+            let offset = match encoded.is_empty() {
+                true => 0,
+                false => u8::try_from(bytes.len()).map_err(|_| {
+                    let value = format!("{} bytes", bytes.len());
+                    invalid("music part size", value)
+                })?,
+            };
+            match channel {
+                MusicChannel::Square2 => {}
+                MusicChannel::Square1 => header.square1_offset = offset,
+                MusicChannel::Triangle => header.triangle_offset = offset,
+                MusicChannel::Noise => {
+                    header.noise_offset = header.noise_offset.map(|_| offset)
+                }
+            }
+            bytes.extend(encoded);
+        }
+        Ok((header, bytes))
+    }
+}
+
+impl Rom {
+    /// Get the CPU addresses of the data of each part of a song.
+    fn music_data_ranges(
+        &self,
+        tables: &MusicTables,
+        info: &SongInfo,
+    ) -> Result<Vec<Range<usize>>> {
+        info.headers
+            .clone()
+            .map(|index| {
+                let header =
+                    self.read_music_header(tables, index, info.area)?;
+                let (_, len) = self.read_music_part(tables, header)?;
+                let start = header.data_addr as usize;
+                Ok(start..start + len)
+            })
+            .collect()
+    }
+
+    /**
+     * Look up the frequency table value of every tone by its period.
+     *
+     * Notes whose value already plays their period are left alone, so
+     * only edited notes change.
+     */
+    fn resolve_music_part(
+        &self,
+        tables: &MusicTables,
+        part: &MusicPart,
+        area: bool,
+    ) -> Result<MusicPart> {
+        let mut part = part.clone();
+        part.header.noise_offset = area.then_some(0);
+        let tones = [
+            MusicChannel::Square2,
+            MusicChannel::Square1,
+            MusicChannel::Triangle,
+        ];
+        for channel in tones {
+            for note in part.channel_mut(channel) {
+                if self.note_period(tables, note.value)? == note.period {
+                    continue;
+                }
+                note.value = self
+                    .music_note_value(tables, channel, note.period)?
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "no {} note has period {:?} in the frequency table",
+                            channel.name(),
+                            note.period
+                        )
+                    })?;
+            }
+        }
+        Ok(part)
+    }
+
+    /**
+     * Replace a song with edited notes, see `SONGS`.
+     *
+     * The song keeps its number of parts and each part its tempo (the
+     * header's length offset). Tones are looked up by their period, so
+     * notes only need a value when there's no period. Every part is
+     * encoded before anything is written.
+     *
+     * Parts that are unchanged are left as they are. The data of the rest
+     * goes where the song's old data was, apart from bytes other songs (or
+     * unchanged parts) also read, with identical parts sharing their data.
+     * Headers are rewritten in place, as the offset table is left alone,
+     * so a part whose header another song also plays can't change. Nor
+     * can header bytes another song's header starts within, such as a
+     * noise offset that is also the next header's tempo.
+     */
+    pub fn set_song(&mut self, info: &SongInfo, song: &Song) -> Result<()> {
+        let tables = self.music_tables()?;
+        let indices: Vec<u8> = info.headers.clone().collect();
+        ensure!(
+            song.parts.len() == indices.len(),
+            "{} has {} parts, not {}",
+            info.name,
+            indices.len(),
+            song.parts.len()
+        );
+
+        let others: Vec<&SongInfo> =
+            SONGS.iter().filter(|other| other.name != info.name).collect();
+        let mut shared = vec![];
+        for other in &others {
+            let size = MUSIC_HEADER_SIZE - !other.area as usize;
+            for index in other.headers.clone() {
+                let addr = self.music_header_addr(&tables, index)?;
+                shared.push((addr, size, other.name));
+            }
+        }
+
+        let mut kept = vec![];
+        let mut encoded = vec![];
+        for (idx, part) in song.parts.iter().enumerate() {
+            let part = self.resolve_music_part(&tables, part, info.area)?;
+            let addr = self.music_header_addr(&tables, indices[idx])?;
+            let header =
+                self.read_music_header(&tables, indices[idx], info.area)?;
+            let (current, len) = self.read_music_part(&tables, header)?;
+            let notes = MusicPart { header, ..part.clone() };
+            if part.header.length_offset == header.length_offset
+                && notes == current
+            {
+                let start = header.data_addr as usize;
+                kept.push((idx, addr, start..start + len));
+                continue;
+            }
+            let other = shared.iter().find(|(shared, ..)| *shared == addr);
+            if let Some((.., other)) = other {
+                bail!(
+                    "{} part {} shares its header with {} and can't change",
+                    info.name,
+                    idx,
+                    other
+                );
+            }
+
+            let lengths = self.music_lengths(&tables, &part.header)?;
+            let (header, bytes) = part.encode(lengths).map_err(|error| {
+                anyhow!("{} part {}: {}", info.name, idx, error)
+            })?;
+            encoded.push((idx, addr, header, bytes));
+        }
+
+        // parts sharing a header have to stay the same
+        for (pos, (idx, addr, header, bytes)) in encoded.iter().enumerate() {
+            let kept = kept.iter().find(|kept| kept.1 == *addr);
+            let changed = encoded[..pos].iter().find(|other| {
+                other.1 == *addr && (other.2 != *header || other.3 != *bytes)
+            });
+            let other =
+                kept.map(|kept| kept.0).or(changed.map(|other| other.0));
+            if let Some(other) = other {
+                bail!(
+                    "{} parts {} and {} share a header but differ",
+                    info.name,
+                    other.min(*idx),
+                    other.max(*idx)
+                );
+            }
+        }
+
+        // the song's old data is free, unless something else reads it
+        let own = self.music_data_ranges(&tables, info)?;
+        let mut protected: Vec<Range<usize>> =
+            kept.into_iter().map(|(_, _, range)| range).collect();
+        for other in &others {
+            protected.extend(self.music_data_ranges(&tables, other)?);
+        }
+        let start = own.iter().map(|range| range.start).min().unwrap_or(0);
+        let end = own.iter().map(|range| range.end).max().unwrap_or(0);
+        let contains = |ranges: &[Range<usize>], addr| {
+            ranges.iter().any(|r| r.contains(&addr))
+        };
+        let mut used: Vec<bool> = (start..end)
+            .map(|addr| !contains(&own, addr) || contains(&protected, addr))
+            .collect();
+
+        let mut placed: Vec<(&[u8], u16)> = vec![];
+        let mut data_addrs = vec![];
+        for (_, _, _, bytes) in &encoded {
+            if let Some(&(_, addr)) = placed.iter().find(|(b, _)| b == bytes) {
+                data_addrs.push(addr);
+                continue;
+            }
+            let len = bytes.len();
+            let fits = |pos: usize| {
+                pos + len <= used.len()
+                    && used[pos..pos + len].iter().all(|u| !u)
+            };
+            let Some(pos) = (0..used.len()).find(|&pos| fits(pos)) else {
+                let needed: usize =
+                    encoded.iter().map(|(.., bytes)| bytes.len()).sum();
+                let free = used.iter().filter(|&&u| !u).count();
+                bail!(
+                    "music data out of space: {} needs {} bytes, only {} free",
+                    info.name,
+                    needed,
+                    free
+                );
+            };
+            for byte in &mut used[pos..pos + len] {
+                *byte = true;
+            }
+            let addr = (start + pos) as u16;
+            placed.push((bytes, addr));
+            data_addrs.push(addr);
+        }
+
+        // a header may end inside the next one, the noise offset of a
+        // castle music part being the first byte of the victory music's
+        let mut headers = vec![];
+        for ((idx, addr, mut header, _), &data_addr) in
+            encoded.iter().zip(&data_addrs)
+        {
+            header.data_addr = data_addr;
+            let bytes = header.to_bytes();
+            let range = *addr as usize..*addr as usize + bytes.len();
+            let current = self.cpu_slice(*addr)?;
+            for (other_addr, other_size, other) in &shared {
+                let start = (*other_addr as usize).max(range.start);
+                let end = (*other_addr as usize + other_size).min(range.end);
+                if start >= end {
+                    continue;
+                }
+                let overlap = start - range.start..end - range.start;
+                if current[overlap.clone()] != bytes[overlap] {
+                    bail!(
+                        "{} part {} shares header bytes with {} and can't \
+                         change them",
+                        info.name,
+                        idx,
+                        other
+                    );
+                }
+            }
+            headers.push(bytes);
+        }
+
+        for ((_, addr, _, bytes), (data_addr, header)) in
+            encoded.into_iter().zip(data_addrs.into_iter().zip(headers))
+        {
+            self.write_cpu(data_addr, &bytes)?;
+            self.write_cpu(addr, &header)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::zero_rom;
+
+    const LENGTHS: [u8; 8] = [0x05, 0x0a, 0x14, 0x28, 0x50, 0x1e, 0x3c, 0x02];
+
+    #[test]
+    fn test_encode_music_part() {
+        let header = MusicHeader {
+            length_offset: 0,
+            data_addr: 0xf500,
+            triangle_offset: 0,
+            square1_offset: 0,
+            noise_offset: Some(0),
+        };
+        let note = |value, frames| MusicNote { value, frames, period: None };
+        let part = MusicPart {
+            header,
+            square2: vec![note(0x30, 0x0a), note(0x32, 0x0a), note(0x04, 0x05)],
+            square1: vec![note(0x3e, 0x14), note(0x02, 0x05)],
+            square1_sweeps: vec![1],
+            triangle: vec![note(0x20, 0x19)],
+            noise: vec![note(0x10, 0x05); 5],
+        };
+        let (header, bytes) = part.encode(&LENGTHS).unwrap();
+        assert_eq!(
+            bytes,
+            [
+                0x81, 0x30, 0x32, 0x80, 0x04, 0x00, // square 2
+                0xbe, 0x00, 0x02, // square 1, changing the sweep
+                0x85, 0x20, 0x00, // triangle, cut off as 0x1e frames
+                0x10, 0x00, // noise, one beat over and over
+            ]
+        );
+        assert_eq!(header.square1_offset, 6);
+        assert_eq!(header.triangle_offset, 9);
+        assert_eq!(header.noise_offset, Some(12));
+        let decoded = MusicPart::from_bytes(header, &bytes, &LENGTHS);
+        assert_eq!(decoded, MusicPart { header, ..part.clone() });
+
+        // lengths missing from the table, values that can't be encoded
+        let mut bad = part.clone();
+        bad.square2[0].frames = 0x0b;
+        assert!(bad.encode(&LENGTHS).is_err());
+        let mut bad = part.clone();
+        bad.square1[0].value = 0x41;
+        assert!(bad.encode(&LENGTHS).is_err());
+        let mut bad = part.clone();
+        bad.square1.pop();
+        assert!(bad.encode(&LENGTHS).is_err());
+        let mut bad = part.clone();
+        bad.header.noise_offset = None;
+        assert!(bad.encode(&LENGTHS).is_err());
+        let mut bad = part.clone();
+        bad.square1_sweeps.push(2);
+        assert!(bad.encode(&LENGTHS).is_err());
+    }
+
+    /// A rom with the sound engine's tables and a one part death song.
+    fn music_rom() -> Rom {
+        let mut rom = zero_rom();
+        let mut write =
+            |addr: u16, bytes: &[u8]| rom.write_cpu(addr, bytes).unwrap();
+        write(0xc000, &[0xb9, 0xff, 0xd0, 0xa8, 0xb9, 0x00, 0xd1, 0x85, 0xf0]);
+        write(
+            0xc010,
+            &[
+                0x29, 0x07, 0x18, 0x65, 0xf0, 0x6d, 0xc7, 0x07, 0xa8, 0xb9,
+                0x00, 0xd2,
+            ],
+        );
+        write(
+            0xc020,
+            &[
+                0xa8, 0xb9, 0x01, 0xd3, 0xf0, 0x03, 0x9d, 0x02, 0x40, 0xb9,
+                0x00, 0xd3,
+            ],
+        );
+
+        // the death song, every other song plays a single note elsewhere
+        write(0xd100, &[0x50; 0x31]);
+        write(0xd100, &[0x40]);
+        write(0xd140, &[0x00, 0x00, 0xd4, 0x09, 0x06]);
+        write(0xd150, &[0x00, 0x00, 0xd5, 0x00, 0x00, 0x00]);
+        write(0xd200, &LENGTHS);
+        // 0x02 is an A, 0x04 a rest and 0x06 a C
+        write(0xd302, &[0x00, 0xfd, 0x00, 0x00, 0x01, 0xab]);
+        write(
+            0xd400,
+            &[
+                0x81, 0x02, 0x06, 0x80, 0x04, 0x00, // square 2
+                0x86, 0x00, 0x02, // square 1, changing the sweep
+                0x83, 0x06, 0x00, // triangle
+            ],
+        );
+        write(0xd500, &[0x81, 0x02, 0x00]);
+        rom
+    }
+
+    #[test]
+    fn test_set_song() {
+        let mut rom = music_rom();
+        let death = &SONGS[0];
+        let song = rom.song(death).unwrap();
+        assert_eq!(song.frames(), 0x19);
+
+        // an unchanged song is left as it is
+        let original = rom.rom_data.clone();
+        rom.set_song(death, &song).unwrap();
+        assert_eq!(rom.rom_data, original);
+
+        // tones are looked up by their period
+        let mut edited = song.clone();
+        edited.parts[0].square2[1].period = Some(0xfd);
+        rom.set_song(death, &edited).unwrap();
+        let part = &rom.song(death).unwrap().parts[0];
+        assert_eq!(part.square2[1].value, 0x02);
+        // re-encoded parts keep their sweep changes
+        assert_eq!(part.square1_sweeps, [1]);
+        assert_eq!(rom.cpu_slice(0xd406).unwrap()[..3], [0x86, 0x00, 0x02]);
+
+        let mut edited = song.clone();
+        edited.parts[0].triangle = vec![MusicNote::new(0x06, 0x05); 5];
+        let error = rom.set_song(death, &edited).unwrap_err();
+        assert!(error.to_string().contains("out of space"));
+        edited.parts.push(song.parts[0].clone());
+        assert!(rom.set_song(death, &edited).is_err());
+
+        // pipe-intro shares its header with the ground music
+        let pipe_intro = &SONGS[10];
+        let ground = rom.song(&SONGS[11]).unwrap();
+        let mut edited = rom.song(pipe_intro).unwrap();
+        rom.set_song(pipe_intro, &edited).unwrap();
+        edited.parts[0].square2[0].period = None;
+        let error = rom.set_song(pipe_intro, &edited).unwrap_err();
+        assert!(error.to_string().contains("shares its header with"));
+        assert_eq!(rom.song(&SONGS[11]).unwrap(), ground);
+
+        // the castle music's noise offset is the victory music's tempo
+        rom.write_cpu(0xd102, &[0x65]).unwrap();
+        rom.write_cpu(0xd10b, &[0x60]).unwrap();
+        rom.write_cpu(0xd160, &[0x00, 0x00, 0xd6, 0x00, 0x00]).unwrap();
+        rom.write_cpu(0xd165, &[0x00, 0x00, 0xd5, 0x00, 0x00]).unwrap();
+        rom.write_cpu(0xd600, &[0x81, 0x02, 0x02, 0x02, 0x02, 0x00]).unwrap();
+        let (castle, victory) = (&SONGS[8], rom.song(&SONGS[2]).unwrap());
+        let mut edited = rom.song(castle).unwrap();
+        edited.parts[0].square2 = vec![MusicNote::new(0x06, 0x28)];
+        edited.parts[0].noise = vec![MusicNote::new(0x10, 0x28)];
+        let error = rom.set_song(castle, &edited).unwrap_err();
+        assert!(error.to_string().contains("shares header bytes with"));
+        // without noise notes the noise offset stays 0
+        edited.parts[0].noise.clear();
+        rom.set_song(castle, &edited).unwrap();
+        assert_eq!(rom.song(castle).unwrap().parts[0].square2.len(), 1);
+        assert_eq!(rom.song(&SONGS[2]).unwrap(), victory);
+
+        // exported songs read back the same
+        let midi = song.to_midi();
+        assert_eq!(rom.song_from_midi(death, &midi).unwrap(), song);
+    }
+}
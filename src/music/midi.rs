@@ -0,0 +1,534 @@
+use anyhow::{anyhow, bail, ensure, Result};
+
+use crate::*;
+
+/// Ticks per quarter note, so that a tick is one frame at `MIDI_TEMPO`.
+const TICKS_PER_QUARTER: u16 = 60;
+
+/// Microseconds per quarter note: 60 frames at the NTSC frame rate.
+const MIDI_TEMPO: u32 = 998_356;
+
+/// The NTSC CPU clock, which the APU timers count down from.
+const CPU_HZ: f64 = 1_789_773.0;
+
+const NOTE_VELOCITY: u8 = 100;
+
+/// General MIDI channel 10, for drums.
+const DRUM_CHANNEL: u8 = 9;
+
+impl MusicChannel {
+    fn midi_channel(&self) -> u8 {
+        match self {
+            Self::Square2 => 0,
+            Self::Square1 => 1,
+            Self::Triangle => 2,
+            Self::Noise => DRUM_CHANNEL,
+        }
+    }
+
+    /// General MIDI program: square lead, or synth bass for the triangle.
+    fn midi_program(&self) -> Option<u8> {
+        match self {
+            Self::Square2 | Self::Square1 => Some(80),
+            Self::Triangle => Some(38),
+            Self::Noise => None,
+        }
+    }
+}
+
+impl MusicNote {
+    /**
+     * Get the MIDI key played for this note on a channel.
+     *
+     * The triangle plays an octave below a square with the same period.
+     * Noise beats are drums: a closed hi-hat, a snare or an open hi-hat.
+     */
+    pub fn midi_key(&self, channel: MusicChannel) -> Option<u8> {
+        let steps = match channel {
+            MusicChannel::Noise => {
+                return match self.value & 0x3e {
+                    0x30 => Some(46),
+                    0x20 => Some(38),
+                    value if value & 0x10 != 0 => Some(42),
+                    _ => None,
+                };
+            }
+            MusicChannel::Triangle => 32.0,
+            _ => 16.0,
+        };
+        let period = self.period? as f64;
+        let freq = CPU_HZ / (steps * (period + 1.0));
+        let key = 69.0 + 12.0 * (freq / 440.0).log2();
+        Some(key.round().clamp(0.0, 127.0) as u8)
+    }
+}
+
+/// Write a MIDI variable length quantity.
+fn write_var_len(out: &mut Vec<u8>, value: u32) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+/// Wrap track events in an `MTrk` chunk, ending the track.
+fn track_chunk(mut events: Vec<u8>, delta: u32) -> Vec<u8> {
+    write_var_len(&mut events, delta);
+    events.extend([0xff, 0x2f, 0x00]);
+    let mut chunk = b"MTrk".to_vec();
+    chunk.extend((events.len() as u32).to_be_bytes());
+    chunk.extend(events);
+    chunk
+}
+
+/// Add a text meta event (track name), with no delta time before it.
+fn track_name(events: &mut Vec<u8>, name: &str) {
+    events.extend([0x00, 0xff, 0x03]);
+    write_var_len(events, name.len() as u32);
+    events.extend(name.bytes());
+}
+
+impl Song {
+    /**
+     * Export the song as a standard MIDI file (format 1).
+     *
+     * Every part is played once, with a track per channel after a tempo
+     * track. A tick is one frame.
+     */
+    pub fn to_midi(&self) -> Vec<u8> {
+        let mut tempo = vec![];
+        track_name(&mut tempo, self.name);
+        tempo.extend([0x00, 0xff, 0x51, 0x03]);
+        tempo.extend(&MIDI_TEMPO.to_be_bytes()[1..]);
+        let mut tracks = vec![track_chunk(tempo, 0)];
+
+        for channel in MusicChannel::ALL {
+            let played = self.parts.iter().any(|part| {
+                part.channel(channel)
+                    .iter()
+                    .any(|note| note.midi_key(channel).is_some())
+            });
+            if !played {
+                continue;
+            }
+
+            let midi_channel = channel.midi_channel();
+            let mut events = vec![];
+            track_name(&mut events, channel.name());
+            if let Some(program) = channel.midi_program() {
+                events.extend([0x00, 0xc0 | midi_channel, program]);
+            }
+
+            // ticks since the last event, rests just add to this
+            let mut delta = 0;
+            for part in &self.parts {
+                let mut frames = 0;
+                for note in part.channel(channel) {
+                    frames += note.frames as usize;
+                    let Some(key) = note.midi_key(channel) else {
+                        delta += note.frames as u32;
+                        continue;
+                    };
+                    write_var_len(&mut events, delta);
+                    events.extend([0x90 | midi_channel, key, NOTE_VELOCITY]);
+                    write_var_len(&mut events, note.frames as u32);
+                    events.extend([0x80 | midi_channel, key, 0]);
+                    delta = 0;
+                }
+                delta += part.frames().saturating_sub(frames) as u32;
+            }
+            tracks.push(track_chunk(events, delta));
+        }
+
+        let mut midi = b"MThd".to_vec();
+        midi.extend(6u32.to_be_bytes());
+        midi.extend(1u16.to_be_bytes());
+        midi.extend((tracks.len() as u16).to_be_bytes());
+        midi.extend(TICKS_PER_QUARTER.to_be_bytes());
+        midi.extend(tracks.concat());
+        midi
+    }
+}
+
+/// Microseconds a frame lasts, a tick at `MIDI_TEMPO`.
+const FRAME_MICROS: f64 = MIDI_TEMPO as f64 / TICKS_PER_QUARTER as f64;
+
+/// Tempo of a MIDI file until it sets one, 120 beats per minute.
+const DEFAULT_MIDI_TEMPO: u32 = 500_000;
+
+/// A note read from a MIDI file, timed in frames from the start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MidiNote {
+    channel: u8,
+    key: u8,
+    start: usize,
+    end: usize,
+}
+
+/// Reads the chunks and events of a MIDI file.
+struct MidiReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> MidiReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let rest = &self.bytes[self.pos.min(self.bytes.len())..];
+        Error::check_len("midi file", rest, len)
+            .map_err(|error| error.offset_by(self.pos))?;
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn var_len(&mut self) -> Result<u32, Error> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::InvalidValue {
+            what: "midi variable length quantity",
+            value: format!("more than 4 bytes at offset {:#x}", self.pos),
+        })
+    }
+}
+
+/**
+ * Read every note of a MIDI file, on any track.
+ *
+ * Times are turned into frames through the file's tempo changes. Only
+ * files timed in ticks per quarter note are read, not SMPTE.
+ */
+fn read_midi(bytes: &[u8]) -> Result<Vec<MidiNote>, Error> {
+    let mut reader = MidiReader { bytes, pos: 0 };
+    if reader.take(4)? != b"MThd" {
+        return Err(Error::InvalidHeader {
+            what: "midi file",
+            offset: 0,
+            reason: "no MThd chunk".to_string(),
+        });
+    }
+    let header_len = reader.u32()? as usize;
+    let header = reader.take(header_len)?;
+    Error::check_len("midi header", header, 6)?;
+    let division = u16::from_be_bytes([header[4], header[5]]);
+    if division & 0x8000 != 0 || division == 0 {
+        return Err(Error::InvalidValue {
+            what: "midi time division",
+            value: format!("{:#06x}", division),
+        });
+    }
+
+    // (tick, microseconds per quarter) and (tick, channel, key, on)
+    let mut tempos = vec![];
+    let mut events = vec![];
+    while reader.pos < bytes.len() {
+        let id = reader.take(4)?;
+        let len = reader.u32()? as usize;
+        let chunk = reader.take(len)?;
+        if id != b"MTrk" {
+            continue;
+        }
+
+        let base = reader.pos - len;
+        let mut track = MidiReader { bytes: chunk, pos: 0 };
+        let mut tick = 0u64;
+        let mut running = None;
+        while track.pos < chunk.len() {
+            tick += track.var_len()? as u64;
+            let mut status = track.u8()?;
+            let mut data = None;
+            if status & 0x80 == 0 {
+                data = Some(status);
+                status = running.ok_or_else(|| Error::InvalidValue {
+                    what: "midi event",
+                    value: format!(
+                        "no status at offset {:#x}",
+                        base + track.pos
+                    ),
+                })?;
+            }
+            match status {
+                0xff => {
+                    let kind = track.u8()?;
+                    let len = track.var_len()? as usize;
+                    let meta = track.take(len)?;
+                    if kind == 0x51 && len == 3 {
+                        let tempo =
+                            u32::from_be_bytes([0, meta[0], meta[1], meta[2]]);
+                        tempos.push((tick, tempo));
+                    }
+                }
+                0xf0 | 0xf7 => {
+                    let len = track.var_len()? as usize;
+                    track.take(len)?;
+                }
+                _ => {
+                    running = Some(status);
+                    let first = match data {
+                        Some(byte) => byte,
+                        None => track.u8()?,
+                    };
+                    match status & 0xf0 {
+                        0xc0 | 0xd0 => {}
+                        kind => {
+                            let second = track.u8()?;
+                            let channel = status & 0x0f;
+                            if kind == 0x90 || kind == 0x80 {
+                                let on = kind == 0x90 && second > 0;
+                                events.push((tick, channel, first, on));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // turn ticks into frames, a tempo lasting until the next one
+    tempos.sort_by_key(|&(tick, _)| tick);
+    let frame = |tick: u64| {
+        let mut micros = 0.0;
+        let mut last = (0, DEFAULT_MIDI_TEMPO);
+        for &(start, tempo) in
+            tempos.iter().take_while(|(start, _)| *start < tick)
+        {
+            micros += (start - last.0) as f64 * last.1 as f64 / division as f64;
+            last = (start, tempo);
+        }
+        micros += (tick - last.0) as f64 * last.1 as f64 / division as f64;
+        (micros / FRAME_MICROS).round() as usize
+    };
+
+    events.sort_by_key(|&(tick, _, _, on)| (tick, on));
+    let mut open: Vec<(u8, u8, usize)> = vec![];
+    let mut notes = vec![];
+    for (tick, channel, key, on) in events {
+        let time = frame(tick);
+        let playing =
+            open.iter().position(|&(c, k, _)| (c, k) == (channel, key));
+        if let Some(idx) = playing {
+            let (_, _, start) = open.remove(idx);
+            notes.push(MidiNote { channel, key, start, end: time });
+        }
+        if on {
+            open.push((channel, key, time));
+        }
+    }
+    notes.retain(|note| note.end > note.start);
+    notes.sort_by_key(|note| note.start);
+    Ok(notes)
+}
+
+/**
+ * Split a gap into rests that each last a length of the table.
+ *
+ * A rest ending a channel other than square 2 can be cut off by the end of
+ * the part, so what's left over goes into one last rest.
+ */
+fn fill_rest(
+    notes: &mut Vec<MusicNote>,
+    value: u8,
+    mut frames: usize,
+    lengths: &[u8],
+    cut_off: bool,
+) -> Result<()> {
+    while frames > 0 {
+        let length = lengths
+            .iter()
+            .copied()
+            .filter(|&length| length > 0 && length as usize <= frames)
+            .max();
+        let length = match length {
+            Some(length) => length,
+            None if cut_off => frames as u8,
+            None => bail!("no rest of {} frames fits the note lengths", frames),
+        };
+        notes.push(MusicNote { value, frames: length, period: None });
+        frames -= length as usize;
+    }
+    Ok(())
+}
+
+impl Rom {
+    /**
+     * Read a song from a MIDI file laid out like `Song::to_midi`.
+     *
+     * MIDI channels 1, 2 and 3 are square 2, square 1 and the triangle,
+     * playing one note at a time, and channel 10 the noise drums. Keys are
+     * matched to the frequency table, and time is rounded to frames.
+     *
+     * The song keeps the parts and tempos of the one in the rom, notes
+     * are split where a part ends. Every note must last a length of its
+     * part's tempo; gaps are filled with rests. MIDI has no square 1 sweep
+     * changes, so those of the rom are kept before the same notes.
+     */
+    pub fn song_from_midi(&self, info: &SongInfo, midi: &[u8]) -> Result<Song> {
+        let tables = self.music_tables()?;
+        let mut song = self.song(info)?;
+        let notes = read_midi(midi)?;
+
+        for channel in MusicChannel::ALL {
+            let notes: Vec<_> = notes
+                .iter()
+                .filter(|note| note.channel == channel.midi_channel())
+                .collect();
+            for pair in notes.windows(2) {
+                ensure!(
+                    pair[1].start >= pair[0].end,
+                    "{} plays two notes at once at frame {}",
+                    channel.name(),
+                    pair[1].start
+                );
+            }
+            let rest = match channel {
+                MusicChannel::Noise => Some(NOISE_REST),
+                _ => self.music_note_value(&tables, channel, None)?,
+            };
+            let rest = rest.ok_or_else(|| {
+                anyhow!("no rest for {} in the frequency table", channel.name())
+            })?;
+
+            let mut part_start = 0;
+            for part in &mut song.parts {
+                let part_end = part_start + part.frames();
+                let lengths = self.music_lengths(&tables, &part.header)?;
+                let lengths = &lengths[..lengths.len().min(NOTE_LENGTHS)];
+                let cut_off = channel != MusicChannel::Square2;
+
+                let mut part_notes = vec![];
+                let mut time = part_start;
+                for note in &notes {
+                    let start = note.start.max(part_start);
+                    let end = note.end.min(part_end);
+                    if start >= end {
+                        continue;
+                    }
+                    fill_rest(
+                        &mut part_notes,
+                        rest,
+                        start - time,
+                        lengths,
+                        false,
+                    )?;
+                    let value =
+                        self.midi_note_value(&tables, channel, note.key)?;
+                    let frames = u8::try_from(end - start).map_err(|_| {
+                        anyhow!(
+                            "{} note of {} frames is too long",
+                            channel.name(),
+                            end - start
+                        )
+                    })?;
+                    let period = match channel {
+                        MusicChannel::Noise => None,
+                        _ => self.note_period(&tables, value)?,
+                    };
+                    part_notes.push(MusicNote { value, frames, period });
+                    time = end;
+                }
+                if channel == MusicChannel::Square2 || !part_notes.is_empty() {
+                    fill_rest(
+                        &mut part_notes,
+                        rest,
+                        part_end - time,
+                        lengths,
+                        cut_off,
+                    )?;
+                }
+                *part.channel_mut(channel) = part_notes;
+                let notes = part.square1.len();
+                part.square1_sweeps.retain(|&idx| idx < notes);
+                part_start = part_end;
+            }
+        }
+        Ok(song)
+    }
+
+    /// Find the note value that plays a MIDI key on a channel.
+    fn midi_note_value(
+        &self,
+        tables: &MusicTables,
+        channel: MusicChannel,
+        key: u8,
+    ) -> Result<u8> {
+        for value in channel.note_values() {
+            let period = match channel {
+                MusicChannel::Noise => None,
+                _ => self.note_period(tables, value)?,
+            };
+            let note = MusicNote { value, frames: 0, period };
+            if note.midi_key(channel) == Some(key) {
+                return Ok(value);
+            }
+        }
+        bail!("{} can't play midi key {}", channel.name(), key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_midi() {
+        let mut out = vec![];
+        for value in [0x00, 0x7f, 0x80, 0x3fff, 0x4000] {
+            write_var_len(&mut out, value);
+        }
+        assert_eq!(out, [0x00, 0x7f, 0x81, 0x00, 0xff, 0x7f, 0x81, 0x80, 0x00]);
+
+        // a period of 253 is an A at 440Hz, the triangle an octave down
+        let note = MusicNote { value: 0x02, frames: 10, period: Some(253) };
+        assert_eq!(note.midi_key(MusicChannel::Square2), Some(69));
+        assert_eq!(note.midi_key(MusicChannel::Triangle), Some(57));
+        let rest = MusicNote { period: None, ..note };
+        assert_eq!(rest.midi_key(MusicChannel::Square1), None);
+        let beat = MusicNote { value: 0x10, ..rest };
+        assert_eq!(beat.midi_key(MusicChannel::Noise), Some(42));
+
+        let header = MusicHeader::from_bytes(&[0, 0, 0, 0, 0], false);
+        let part = MusicPart {
+            header,
+            square2: vec![note, rest, note],
+            square1: vec![],
+            square1_sweeps: vec![],
+            triangle: vec![],
+            noise: vec![],
+        };
+        let song = Song { name: "test", parts: vec![part], loop_part: None };
+        let midi = song.to_midi();
+        assert_eq!(&midi[..4], b"MThd");
+        // format 1, a tempo track and square 2, 60 ticks per quarter note
+        assert_eq!(midi[8..14], [0x00, 0x01, 0x00, 0x02, 0x00, 0x3c]);
+
+        let square2 = midi.windows(4).rposition(|chunk| chunk == b"MTrk");
+        let events = &midi[square2.unwrap() + 8..];
+        let name = [0x00, 0xff, 0x03, 0x08];
+        assert!(events.starts_with(&name));
+        let notes = &events[name.len() + 8 + 3..];
+        assert_eq!(
+            notes,
+            [
+                0x00, 0x90, 69, 100, 10, 0x80, 69, 0, // note
+                10, 0x90, 69, 100, 10, 0x80, 69, 0, // rest, note
+                0x00, 0xff, 0x2f, 0x00,
+            ]
+        );
+    }
+}
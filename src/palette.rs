@@ -0,0 +1,131 @@
+/*!
+ * Mapping NES color indices to RGB.
+ */
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use image::Rgba;
+
+use crate::*;
+
+/// Number of colors the PPU can output (without color emphasis).
+pub const NES_COLORS: usize = 64;
+
+/// Bytes used by a .pal file without color emphasis, 3 per color.
+pub const PAL_FILE_SIZE: usize = NES_COLORS * 3;
+
+/// A common approximation of the NTSC 2C02 PPU's output, as a .pal file.
+pub const NES_2C02_PAL: [u8; PAL_FILE_SIZE] = [
+    0x74, 0x74, 0x74, 0x24, 0x18, 0x8c, 0x00, 0x00, 0xa8, 0x44, 0x00, 0x9c,
+    0x8c, 0x00, 0x74, 0xa8, 0x00, 0x10, 0xa4, 0x00, 0x00, 0x7c, 0x08, 0x00,
+    0x40, 0x2c, 0x00, 0x00, 0x44, 0x00, 0x00, 0x50, 0x00, 0x00, 0x3c, 0x14,
+    0x18, 0x3c, 0x5c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xbc, 0xbc, 0xbc, 0x00, 0x70, 0xec, 0x20, 0x38, 0xec, 0x80, 0x00, 0xf0,
+    0xbc, 0x00, 0xbc, 0xe4, 0x00, 0x58, 0xd8, 0x28, 0x00, 0xc8, 0x4c, 0x0c,
+    0x88, 0x70, 0x00, 0x00, 0x94, 0x00, 0x00, 0xa8, 0x00, 0x00, 0x90, 0x38,
+    0x00, 0x80, 0x88, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xfc, 0xfc, 0xfc, 0x3c, 0xbc, 0xfc, 0x5c, 0x94, 0xfc, 0xcc, 0x88, 0xfc,
+    0xf4, 0x78, 0xfc, 0xfc, 0x74, 0xb4, 0xfc, 0x74, 0x60, 0xfc, 0x98, 0x38,
+    0xf0, 0xbc, 0x3c, 0x80, 0xd0, 0x10, 0x4c, 0xdc, 0x48, 0x58, 0xf8, 0x98,
+    0x00, 0xe8, 0xd8, 0x78, 0x78, 0x78, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xfc, 0xfc, 0xfc, 0xa8, 0xe4, 0xfc, 0xc4, 0xd4, 0xfc, 0xd4, 0xc8, 0xfc,
+    0xfc, 0xc4, 0xfc, 0xfc, 0xc4, 0xd8, 0xfc, 0xbc, 0xb0, 0xfc, 0xd8, 0xa8,
+    0xfc, 0xe4, 0xa0, 0xe0, 0xfc, 0xa0, 0xa8, 0xf0, 0xbc, 0xb0, 0xfc, 0xcc,
+    0x9c, 0xfc, 0xf0, 0xc4, 0xc4, 0xc4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/**
+ * The RGB color of every NES color index.
+ *
+ * Defaults to `NES_2C02_PAL`, other palettes can be loaded from the .pal
+ * files emulators use.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NesPalette {
+    pub colors: [[u8; 3]; NES_COLORS],
+}
+
+impl Default for NesPalette {
+    fn default() -> Self {
+        Self::from_bytes(&NES_2C02_PAL)
+    }
+}
+
+impl NesPalette {
+    /**
+     * Read a .pal file: 64 RGB triples.
+     *
+     * Files with the 7 color emphasis variants after the first 64 colors
+     * (1536 bytes) are accepted too, only the first 64 colors are used.
+     */
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Error::check_len("pal file", bytes, PAL_FILE_SIZE)?;
+
+        let mut colors = [[0; 3]; NES_COLORS];
+        for (color, rgb) in colors.iter_mut().zip(bytes.chunks_exact(3)) {
+            color.copy_from_slice(rgb);
+        }
+        Ok(Self { colors })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::try_from_bytes(bytes).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.colors.concat()
+    }
+
+    /// Load a .pal file, see `NesPalette::try_from_bytes`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::try_from_bytes(&fs::read(path)?)?)
+    }
+
+    /// Get the RGB color of a color index, ignoring the unused top 2 bits.
+    pub fn rgb(&self, index: u8) -> [u8; 3] {
+        self.colors[index as usize % NES_COLORS]
+    }
+
+    /// Get the opaque color of a color index.
+    pub fn rgba(&self, index: u8) -> Rgba<u8> {
+        let [r, g, b] = self.rgb(index);
+        Rgba([r, g, b, 0xff])
+    }
+
+    /// Get the colors of a 4 color palette, to draw tiles with.
+    pub fn tile_palette(&self, indices: &[u8; 4]) -> [Rgba<u8>; 4] {
+        indices.map(|index| self.rgba(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nes_palette() {
+        let palette = NesPalette::default();
+        assert_eq!(palette.rgb(0x0f), [0, 0, 0]);
+        assert_eq!(palette.rgb(0x30), [0xfc, 0xfc, 0xfc]);
+        assert_eq!(palette.rgb(0x70), palette.rgb(0x30));
+        assert_eq!(
+            palette.tile_palette(&[0x22, 0x0f, 0x0f, 0x0f])[0],
+            Rgba([0x5c, 0x94, 0xfc, 0xff])
+        );
+
+        let bytes = palette.to_bytes();
+        assert_eq!(bytes.len(), PAL_FILE_SIZE);
+        assert_eq!(NesPalette::from_bytes(&bytes), palette);
+
+        // color emphasis variants are ignored
+        let mut emphasis = bytes.repeat(8);
+        emphasis[PAL_FILE_SIZE] = 0x12;
+        assert_eq!(NesPalette::from_bytes(&emphasis), palette);
+
+        assert!(
+            NesPalette::try_from_bytes(&bytes[..PAL_FILE_SIZE - 1]).is_err()
+        );
+    }
+}
@@ -0,0 +1,309 @@
+/*!
+ * Drawing areas with the game's own graphics and palettes.
+ */
+
+use anyhow::{ensure, Result};
+use image::RgbaImage;
+
+use crate::*;
+
+/*
+ * Metatiles are looked up by their top 2 bits (also their palette) in
+ * `MetatileGraphics_Low` and `MetatileGraphics_High`, then by the low 6 bits
+ * in a table of 4 tiles each.
+ */
+pub(crate) const METATILE_GRAPHICS_LOW: u16 = 0x8b08;
+pub(crate) const METATILE_GRAPHICS_HIGH: u16 = 0x8b0c;
+
+/// Width and height of a metatile in pixels.
+pub const METATILE_PIXELS: usize = 2 * TILE_PIXELS;
+
+/// Sprite tile the game uses for nothing at all.
+const BLANK_SPRITE_TILE: u8 = 0xfc;
+
+/**
+ * Tiles of the first animation frame of an enemy, as listed in the game's
+ * `EnemyGraphicsTable`: 3 rows of a left and right tile.
+ */
+struct EnemySprite {
+    tiles: [u8; 6],
+    palette: usize,
+}
+
+const GOOMBA: EnemySprite =
+    EnemySprite { tiles: [0xfc, 0xfc, 0x70, 0x71, 0x72, 0x73], palette: 3 };
+const GREEN_KOOPA: EnemySprite =
+    EnemySprite { tiles: [0xfc, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9], palette: 1 };
+const RED_KOOPA: EnemySprite = EnemySprite { palette: 2, ..GREEN_KOOPA };
+const GREEN_PARATROOPA: EnemySprite =
+    EnemySprite { tiles: [0x69, 0xa5, 0x6a, 0xa7, 0xa8, 0xa9], palette: 1 };
+const RED_PARATROOPA: EnemySprite =
+    EnemySprite { palette: 2, ..GREEN_PARATROOPA };
+const GREY_CHEEP_CHEEP: EnemySprite =
+    EnemySprite { tiles: [0xfc, 0xfc, 0xb2, 0xb3, 0xb4, 0xb5], palette: 1 };
+const BOWSER_FRONT: EnemySprite =
+    EnemySprite { tiles: [0xbf, 0xbe, 0xc1, 0xc0, 0xc2, 0xfc], palette: 1 };
+const BOWSER_REAR: EnemySprite =
+    EnemySprite { tiles: [0xc4, 0xc3, 0xc6, 0xc5, 0xc8, 0xc7], palette: 1 };
+
+/**
+ * Get the sprites drawn for an enemy, with their pixel offsets from the
+ * enemy and the row the enemy stands on, if it isn't the one it is stored
+ * with.
+ *
+ * Generators, fire bars, lifts and other enemies without a sprite of their
+ * own aren't drawn.
+ */
+fn enemy_sprites(
+    kind: &LevelEnemyKind,
+) -> (Vec<(i64, &'static EnemySprite)>, Option<u8>) {
+    use LevelEnemyKind::*;
+
+    let sprite = |sprite| (vec![(0, sprite)], None);
+    // groups are spawned 24 pixels apart on a fixed row
+    let group = |sprite, count: i64, row| {
+        ((0..count).map(|idx| (idx * 24, sprite)).collect(), Some(row))
+    };
+
+    match kind {
+        GreenKoopaTroopa | GreenKoopaTroopaStationary => sprite(&GREEN_KOOPA),
+        RedKoopaTroopa | RedKoopaTroopaFallsOffEdges => sprite(&RED_KOOPA),
+        BuzzyBeetle => sprite(&EnemySprite {
+            tiles: [0xfc, 0xfc, 0xaa, 0xab, 0xac, 0xad],
+            palette: 3,
+        }),
+        HammerBro => sprite(&EnemySprite {
+            tiles: [0x7d, 0x7c, 0xd1, 0x8c, 0xd3, 0xd2],
+            palette: 1,
+        }),
+        Goomba => sprite(&GOOMBA),
+        Blooper => sprite(&EnemySprite {
+            tiles: [0xfc, 0xfc, 0xdc, 0xdc, 0xdf, 0xdf],
+            palette: 3,
+        }),
+        BulletBill => sprite(&EnemySprite {
+            tiles: [0xfc, 0xfc, 0xe8, 0xe7, 0xea, 0xe9],
+            palette: 3,
+        }),
+        GreyCheepCheep => sprite(&GREY_CHEEP_CHEEP),
+        RedCheepCheep => {
+            sprite(&EnemySprite { palette: 2, ..GREY_CHEEP_CHEEP })
+        }
+        Podoboo => sprite(&EnemySprite {
+            tiles: [0xfc, 0xfc, 0xd0, 0xd0, 0xd7, 0xd7],
+            palette: 2,
+        }),
+        PiranhaPlant => sprite(&EnemySprite {
+            tiles: [0xe5, 0xe5, 0xe6, 0xe6, 0xeb, 0xeb],
+            palette: 1,
+        }),
+        GreenKoopaParatroopaJumping | GreenKoopaParatroopaHorizontal => {
+            sprite(&GREEN_PARATROOPA)
+        }
+        RedKoopaParatroopaVertical => sprite(&RED_PARATROOPA),
+        Lakitu => sprite(&EnemySprite {
+            tiles: [0xb9, 0xb8, 0xbb, 0xba, 0xbc, 0xbc],
+            palette: 1,
+        }),
+        Spiny => sprite(&EnemySprite {
+            tiles: [0xfc, 0xfc, 0x96, 0x97, 0x98, 0x99],
+            palette: 2,
+        }),
+        Bowser => (vec![(0, &BOWSER_FRONT), (16, &BOWSER_REAR)], None),
+        ToadOrPrincess => sprite(&EnemySprite {
+            tiles: [0xcd, 0xcd, 0xce, 0xce, 0xcf, 0xcf],
+            palette: 1,
+        }),
+        TwoGoombasY10 => group(&GOOMBA, 2, 0xb),
+        ThreeGoombasY10 => group(&GOOMBA, 3, 0xb),
+        TwoGoombasY6 => group(&GOOMBA, 2, 0x7),
+        ThreeGoombasY6 => group(&GOOMBA, 3, 0x7),
+        TwoKoopaTroopasY10 => group(&GREEN_KOOPA, 2, 0xb),
+        ThreeKoopaTroopasY10 => group(&GREEN_KOOPA, 3, 0xb),
+        TwoKoopaTroopasY6 => group(&GREEN_KOOPA, 2, 0x7),
+        ThreeKoopaTroopasY6 => group(&GREEN_KOOPA, 3, 0x7),
+        _ => (vec![], None),
+    }
+}
+
+/**
+ * Draws areas of one area type as the player would see them, without the
+ * status bar.
+ *
+ * Metatiles come from the rom's metatile table and background pattern table,
+ * enemies from the sprite pattern table, all colored with the area's
+ * palettes. Each enemy is drawn in its first animation frame. Everything is
+ * read from the rom up front, so drawing a level can't fail.
+ */
+pub struct LevelRenderer {
+    area_type: AreaType,
+    sprites: PatternTable,
+    background: PatternTable,
+    /// Tiles of every metatile, see `Rom::metatile_tiles`.
+    metatiles: Vec<[u8; 4]>,
+    /// Palettes of each background, see `Rom::palettes`.
+    palettes: Vec<Palettes>,
+    pub nes_palette: NesPalette,
+}
+
+impl LevelRenderer {
+    pub fn new(rom: &Rom, area_type: AreaType) -> Result<Self> {
+        let [sprites, background] = rom.pattern_tables()?;
+        let metatiles = (0..=0xff)
+            .map(|metatile| rom.metatile_tiles(metatile))
+            .collect::<Result<_>>()?;
+        let palettes = (0..8)
+            .map(|background| {
+                rom.palettes(area_type, LevelBackground::new(background))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            area_type,
+            sprites,
+            background,
+            metatiles,
+            palettes,
+            nes_palette: NesPalette::default(),
+        })
+    }
+
+    /// Use a different master palette, such as one loaded from a .pal file.
+    pub fn with_nes_palette(mut self, nes_palette: NesPalette) -> Self {
+        self.nes_palette = nes_palette;
+        self
+    }
+
+    /// Draw the level data of an area.
+    pub fn render_level(&self, level: &Level) -> RgbaImage {
+        let background = level.level_header.background.value() as usize;
+        let palettes = &self.palettes[background];
+        let grid = LevelGrid::new(level, self.area_type);
+
+        let mut image = RgbaImage::from_pixel(
+            (grid.width() * METATILE_PIXELS) as u32,
+            (GRID_ROWS * METATILE_PIXELS) as u32,
+            self.nes_palette.rgba(palettes.background_color()),
+        );
+
+        for x in 0..grid.width() {
+            for (row, &metatile) in grid.column(x).unwrap().iter().enumerate() {
+                let colors = palettes.background(metatile as usize >> 6);
+                let colors = self.nes_palette.tile_palette(&colors);
+                // tiles are stored a column at a time
+                let tiles = self.metatiles[metatile as usize];
+                for (idx, &tile) in tiles.iter().enumerate() {
+                    let tile_x = x * METATILE_PIXELS + idx / 2 * TILE_PIXELS;
+                    let tile_y = row * METATILE_PIXELS + idx % 2 * TILE_PIXELS;
+                    self.background.tiles[tile as usize].draw(
+                        &mut image,
+                        tile_x as u32,
+                        tile_y as u32,
+                        &colors,
+                    );
+                }
+            }
+        }
+
+        for (position, entry) in level.enemy_data.with_positions() {
+            let LevelEnemyDataEntry::Enemy(enemy) = entry else {
+                continue;
+            };
+            let (sprites, row) = enemy_sprites(&enemy.kind);
+            // an enemy stands on top of the row it is stored with
+            let row = row.unwrap_or(position.row) as i64;
+            let x = position.x() as i64 * METATILE_PIXELS as i64;
+            let y = row * METATILE_PIXELS as i64 - 3 * TILE_PIXELS as i64;
+            for (offset, sprite) in sprites {
+                self.draw_sprite(&mut image, x + offset, y, sprite, palettes);
+            }
+        }
+
+        image
+    }
+
+    fn draw_sprite(
+        &self,
+        image: &mut RgbaImage,
+        x: i64,
+        y: i64,
+        sprite: &EnemySprite,
+        palettes: &Palettes,
+    ) {
+        let colors = palettes.sprite(sprite.palette);
+        let colors = self.nes_palette.tile_palette(&colors);
+        for (idx, &tile) in sprite.tiles.iter().enumerate() {
+            if tile == BLANK_SPRITE_TILE {
+                continue;
+            }
+            // symmetric sprites repeat a tile, mirrored on the right
+            let right = idx % 2 == 1;
+            let flip = right && sprite.tiles[idx - 1] == tile;
+
+            let tile = &self.sprites.tiles[tile as usize];
+            let tile_x = x + (idx % 2 * TILE_PIXELS) as i64;
+            let tile_y = y + (idx / 2 * TILE_PIXELS) as i64;
+            for py in 0..TILE_PIXELS {
+                for px in 0..TILE_PIXELS {
+                    let src_x = if flip { TILE_PIXELS - 1 - px } else { px };
+                    // color 0 is transparent
+                    let color = tile.pixel(src_x, py);
+                    let (ix, iy) = (tile_x + px as i64, tile_y + py as i64);
+                    let inside = (0..image.width() as i64).contains(&ix)
+                        && (0..image.height() as i64).contains(&iy);
+                    if color != 0 && inside {
+                        let color = colors[color as usize];
+                        image.put_pixel(ix as u32, iy as u32, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Rom {
+    /// Get the 4 background tiles of a metatile: left column then right.
+    pub fn metatile_tiles(&self, metatile: u8) -> Result<[u8; 4]> {
+        let palette = (metatile >> 6) as u16;
+        let low = self.read_cpu_u8(METATILE_GRAPHICS_LOW + palette)?;
+        let high = self.read_cpu_u8(METATILE_GRAPHICS_HIGH + palette)?;
+        let addr = u16::from_le_bytes([low, high])
+            .wrapping_add((metatile & 0b00111111) as u16 * 4);
+
+        let bytes = self.cpu_slice(addr)?;
+        ensure!(
+            bytes.len() >= 4,
+            "metatile {:#04x} crosses end of prg",
+            metatile
+        );
+        let mut tiles = [0; 4];
+        tiles.copy_from_slice(&bytes[..4]);
+        Ok(tiles)
+    }
+
+    /// Draw the level data of an area, see `LevelRenderer`.
+    pub fn render_area(&self, area: &Area) -> Result<RgbaImage> {
+        let level = self.get_area(area)?;
+        let renderer = LevelRenderer::new(self, area.area_type)?;
+        Ok(renderer.render_level(&level))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enemy_sprites() {
+        let (sprites, row) = enemy_sprites(&LevelEnemyKind::Goomba);
+        assert_eq!(sprites.len(), 1);
+        assert_eq!(row, None);
+
+        let (sprites, row) = enemy_sprites(&LevelEnemyKind::ThreeGoombasY6);
+        let offsets: Vec<_> =
+            sprites.iter().map(|(offset, _)| *offset).collect();
+        assert_eq!(offsets, [0, 24, 48]);
+        assert_eq!(row, Some(0x7));
+
+        assert!(enemy_sprites(&LevelEnemyKind::BalanceLift).0.is_empty());
+    }
+}
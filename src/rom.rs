@@ -1,60 +1,322 @@
-use anyhow::{ensure, Result};
+use anyhow::{anyhow, ensure, Result};
 
 use crate::*;
 
+mod areas;
+mod ines;
+mod level_space;
 mod levels;
+mod palettes;
+mod profile;
+mod text;
 
+pub use areas::*;
+pub use ines::*;
+pub use level_space::*;
 pub use levels::*;
-
-const ROM_SIZE_BYTES: usize = 40976;
-const ROM_MD5_BYTES: &str = "811b027eaf99c2def7b933c5208636de";
+pub use palettes::*;
+pub use profile::*;
+pub use text::*;
 
 #[derive(Debug)]
 pub struct Rom {
     pub rom_data: Vec<u8>,
+    pub header: INesHeader,
+    profile: Option<&'static RomProfile>,
+    layout: RomLayout,
+    failed_checks: Vec<Error>,
+    /// Level data bank, fixed by the first write so freed space is kept.
+    level_data_region: Option<std::ops::Range<usize>>,
 }
 
 impl Rom {
+    /**
+     * Load a known dump of the game, see `ROM_PROFILES`.
+     *
+     * Headerless dumps, dumps with a non-standard header and multicarts are
+     * rebuilt as a standard iNES file.
+     */
     pub fn new(rom_data: Vec<u8>) -> Result<Self> {
-        Self::validate_rom_data(&rom_data)?;
-        let rom = Self { rom_data };
+        let rom = Self::new_unverified(rom_data)?;
+        if let Some(error) = rom.failed_checks.first() {
+            return Err(error.clone().into());
+        }
+        Ok(rom)
+    }
+
+    /**
+     * Load any rom laid out like the game, such as a modified dump.
+     *
+     * Checks that fail are reported by `Rom::failed_checks` instead of
+     * refusing the rom. Only data that can't be read at all is an error.
+     */
+    pub fn new_unverified(rom_data: Vec<u8>) -> Result<Self> {
+        if let Some(identity) = RomIdentity::identify(&rom_data) {
+            let rom_data = identity.to_ines(&rom_data);
+            let header = INesHeader::from_bytes(&rom_data)?;
+            let mut rom = Self {
+                rom_data,
+                header,
+                profile: Some(identity.profile),
+                layout: identity.layout,
+                failed_checks: vec![],
+                level_data_region: None,
+            };
+            rom.failed_checks = rom.verify();
+            return Ok(rom);
+        }
+
+        let (rom_data, layout) =
+            if rom_data.len() == SMB1_PRG_SIZE + SMB1_CHR_SIZE {
+                let rom_data = [&SMB1_INES_HEADER[..], &rom_data].concat();
+                (rom_data, RomLayout::Headerless)
+            } else {
+                (rom_data, RomLayout::INes)
+            };
+        let header = INesHeader::from_bytes(&rom_data)?;
+        ensure!(
+            rom_data.len() >= header.file_size(),
+            "rom data shorter than ines header describes: {} < {}",
+            rom_data.len(),
+            header.file_size()
+        );
+
+        let mut rom = Self {
+            rom_data,
+            header,
+            profile: None,
+            layout,
+            failed_checks: vec![],
+            level_data_region: None,
+        };
+        rom.failed_checks = rom.verify();
         Ok(rom)
     }
 
-    pub fn get_level(&self, level_name: &RomLevel) -> Level {
-        let (header_offset, block_offset, enemy_offset) =
-            level_name.get_offsets();
+    /// The known dump this rom was identified as.
+    pub fn profile(&self) -> Option<&'static RomProfile> {
+        self.profile
+    }
 
-        let header_bytes = &self.rom_data[header_offset..];
-        let block_bytes = &self.rom_data[block_offset..];
-        let enemy_bytes = &self.rom_data[enemy_offset..];
+    /// Where the game was found in the data this rom was loaded from.
+    pub fn layout(&self) -> RomLayout {
+        self.layout
+    }
 
-        let level_header = LevelHeader::from_bytes(header_bytes);
-        let object_data = LevelObjectData::from_bytes(block_bytes);
-        let enemy_data = LevelEnemyData::from_bytes(enemy_bytes);
+    /// Checks that failed when the rom was loaded with `new_unverified`.
+    pub fn failed_checks(&self) -> &[Error] {
+        &self.failed_checks
+    }
 
-        Level { level_header, object_data, enemy_data }
+    /// PRG-ROM (program code and data) without the iNES header.
+    pub fn prg(&self) -> &[u8] {
+        let start = self.header.prg_offset();
+        &self.rom_data[start..start + self.header.prg_size()]
     }
 
-    fn validate_rom_data(data: &[u8]) -> Result<()> {
-        // check rom length
-        let len = data.len();
+    /// CHR-ROM (pattern tables) without the iNES header.
+    pub fn chr(&self) -> &[u8] {
+        let start = self.header.chr_offset();
+        &self.rom_data[start..start + self.header.chr_size()]
+    }
+
+    /// Translate a CPU address ($8000-$FFFF) into an offset into `rom_data`.
+    pub fn cpu_to_offset(&self, addr: u16) -> Result<usize> {
+        let prg_offset = self.header.cpu_to_prg(addr)?;
+        Ok(self.header.prg_offset() + prg_offset)
+    }
+
+    /// Translate an offset into `rom_data` into a CPU address.
+    pub fn offset_to_cpu(&self, offset: usize) -> Result<u16> {
+        let prg_start = self.header.prg_offset();
         ensure!(
-            len == ROM_SIZE_BYTES,
-            "rom size invalid: {} != {}",
-            len,
-            ROM_SIZE_BYTES
+            offset >= prg_start,
+            "rom offset {:#x} is inside the header, not prg-rom",
+            offset
         );
+        self.header.prg_to_cpu(offset - prg_start)
+    }
+
+    /// Get the bytes from a CPU address through to the end of PRG-ROM.
+    pub fn cpu_slice(&self, addr: u16) -> Result<&[u8]> {
+        let prg_offset = self.header.cpu_to_prg(addr)?;
+        Ok(&self.prg()[prg_offset..])
+    }
 
-        // check md5
-        let digest = md5::compute(data);
+    /// Read a single byte at a CPU address.
+    pub fn read_cpu_u8(&self, addr: u16) -> Result<u8> {
+        Ok(self.cpu_slice(addr)?[0])
+    }
+
+    /// Read a little-endian word at a CPU address.
+    pub fn read_cpu_u16(&self, addr: u16) -> Result<u16> {
+        let bytes = self.cpu_slice(addr)?;
+        ensure!(bytes.len() >= 2, "word at ${:04x} crosses end of prg", addr);
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Overwrite bytes starting at a CPU address.
+    pub fn write_cpu(&mut self, addr: u16, bytes: &[u8]) -> Result<()> {
+        let offset = self.cpu_to_offset(addr)?;
+        let prg_end = self.header.prg_offset() + self.header.prg_size();
         ensure!(
-            format!("{:x}", digest) == ROM_MD5_BYTES,
-            "md5 mismatch for rom file: expected {:?} got {:?}",
-            ROM_MD5_BYTES,
-            digest
+            offset + bytes.len() <= prg_end,
+            "write of {} bytes at ${:04x} crosses end of prg",
+            bytes.len(),
+            addr
         );
-
+        self.rom_data[offset..offset + bytes.len()].copy_from_slice(bytes);
         Ok(())
     }
-               }
+
+    pub fn get_level(&self, level_name: &RomLevel) -> Result<Level> {
+        let addresses = self.level_addresses(level_name)?;
+        self.read_level(&addresses)
+    }
+
+    /// Decode the world and area pointer tables from the rom.
+    pub fn area_tables(&self) -> Result<AreaPointerTables> {
+        AreaPointerTables::from_rom(self)
+    }
+
+    /// Get every area in the game.
+    pub fn areas(&self) -> Result<impl Iterator<Item = Area>> {
+        Ok(self.area_tables()?.areas().into_iter())
+    }
+
+    /// Get the level data of a single area.
+    pub fn get_area(&self, area: &Area) -> Result<Level> {
+        let addresses = self.area_tables()?.area_addresses(area)?;
+        self.read_level(&addresses)
+    }
+
+    /// Get the area a pipe pointer sends the player to.
+    pub fn pipe_destination(&self, pipe_pointer: &PipePointer) -> Result<Area> {
+        let area = pipe_pointer.area;
+        let tables = self.area_tables()?;
+        ensure!(
+            (area.index as usize) < tables.area_count(area.area_type),
+            "pipe pointer {:?} leads to a missing area",
+            pipe_pointer
+        );
+        Ok(area)
+    }
+
+    /// Get the area a level starts in.
+    pub fn level_area(&self, level_name: &RomLevel) -> Result<Area> {
+        let tables = self.area_tables()?;
+        let (world, level) = level_name.world_and_level();
+
+        // areas the player automatically walks through (the pipe intro
+        // cutscenes) are part of a world but are not a level of their own
+        let mut level_areas = vec![];
+        for &area_pointer in tables.world_area_pointers(world)? {
+            let area = Area::from_pointer(area_pointer);
+            let addresses = tables.area_addresses(&area)?;
+            let header = self.read_level_header(addresses.header)?;
+            if !header.start_autowalk {
+                level_areas.push(area);
+            }
+        }
+
+        level_areas.get(level).copied().ok_or_else(|| {
+            anyhow!("level {:?} not found in world area table", level_name)
+        })
+    }
+
+    /// Get the addresses of the level data a level starts in.
+    pub fn level_addresses(
+        &self,
+        level_name: &RomLevel,
+    ) -> Result<AreaAddresses> {
+        let area = self.level_area(level_name)?;
+        self.area_tables()?.area_addresses(&area)
+    }
+
+    fn read_level(&self, addresses: &AreaAddresses) -> Result<Level> {
+        let level_header = self.read_level_header(addresses.header)?;
+
+        let offset = self.cpu_to_offset(addresses.objects)?;
+        let block_bytes = self.cpu_slice(addresses.objects)?;
+        let object_data = LevelObjectData::try_from_bytes(block_bytes)
+            .map_err(|error| error.offset_by(offset))?;
+
+        let offset = self.cpu_to_offset(addresses.enemies)?;
+        let enemy_bytes = self.cpu_slice(addresses.enemies)?;
+        let enemy_data = LevelEnemyData::try_from_bytes(enemy_bytes)
+            .map_err(|error| error.offset_by(offset))?;
+
+        Ok(Level { level_header, object_data, enemy_data })
+    }
+
+    fn read_level_header(&self, addr: u16) -> Result<LevelHeader> {
+        let offset = self.cpu_to_offset(addr)?;
+        let header_bytes = self.cpu_slice(addr)?;
+        let header = LevelHeader::try_from_bytes(header_bytes)
+            .map_err(|error| error.offset_by(offset))?;
+        Ok(header)
+    }
+
+    /// Find every way this rom differs from a known dump.
+    fn verify(&self) -> Vec<Error> {
+        let mut failed_checks = vec![];
+
+        // check the cartridge is laid out like the game's
+        let header = &self.header;
+        if header.prg_size() != SMB1_PRG_SIZE
+            || header.chr_size() != SMB1_CHR_SIZE
+            || header.mapper != 0
+        {
+            failed_checks.push(Error::InvalidHeader {
+                what: "ines header",
+                offset: 0,
+                reason: format!(
+                    "expected NROM-256, got {} prg banks, {} chr banks and \
+                     mapper {}",
+                    header.prg_rom_banks, header.chr_rom_banks, header.mapper
+                ),
+            });
+        }
+
+        // check prg and chr against the dump this rom was identified as,
+        // or against every known dump when it matches none of them
+        let expected_profiles = match self.profile {
+            Some(profile) => std::slice::from_ref(profile),
+            None => ROM_PROFILES,
+        };
+        let hashes = RomHashes::new(self.prg(), self.chr());
+        let offset = header.prg_offset();
+        let len = header.prg_size() + header.chr_size();
+        for expected in expected_profiles {
+            if hashes.crc32 != expected.crc32 {
+                failed_checks.push(Error::ChecksumMismatch {
+                    what: "crc32",
+                    offset,
+                    len,
+                    expected: format!("{:08x}", expected.crc32),
+                    actual: format!("{:08x}", hashes.crc32),
+                });
+            }
+            if hashes.md5 != expected.md5 {
+                failed_checks.push(Error::ChecksumMismatch {
+                    what: "md5",
+                    offset,
+                    len,
+                    expected: expected.md5.into(),
+                    actual: hashes.md5.clone(),
+                });
+            }
+            if hashes.sha1 != expected.sha1 {
+                failed_checks.push(Error::ChecksumMismatch {
+                    what: "sha1",
+                    offset,
+                    len,
+                    expected: expected.sha1.into(),
+                    actual: hashes.sha1.clone(),
+                });
+            }
+        }
+
+        failed_checks
+    }
+}
@@ -0,0 +1,392 @@
+use anyhow::{ensure, Result};
+
+use crate::util::enum_mapped;
+use crate::*;
+
+/*
+ * CPU addresses of the lookup tables used by the game's `GetAreaDataAddrs`
+ * and `FindAreaPointer` routines to turn a world and area number into the
+ * addresses of an area's level data.
+ */
+pub(crate) const WORLD_ADDR_OFFSETS: u16 = 0x9cb4;
+pub(crate) const AREA_ADDR_OFFSETS: u16 = 0x9cbc;
+pub(crate) const ENEMY_ADDR_H_OFFSETS: u16 = 0x9ce0;
+pub(crate) const ENEMY_DATA_ADDR_LOW: u16 = 0x9ce4;
+pub(crate) const ENEMY_DATA_ADDR_HIGH: u16 = 0x9d06;
+pub(crate) const AREA_DATA_H_OFFSETS: u16 = 0x9d28;
+pub(crate) const AREA_DATA_ADDR_LOW: u16 = 0x9d2c;
+pub(crate) const AREA_DATA_ADDR_HIGH: u16 = 0x9d4e;
+
+const NUM_WORLDS: usize = 8;
+const NUM_AREA_TYPES: usize = 4;
+
+enum_mapped!(
+    pub AreaType (u8) {
+        0b00 => Water,
+        0b01 => Ground,
+        0b10 => Underground,
+        0b11 => Castle,
+    }
+);
+
+/// A single area of the game, identified the same way the game does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Area {
+    pub area_type: AreaType,
+    pub index: u8,
+}
+
+impl Area {
+    pub fn new(area_type: AreaType, index: u8) -> Self {
+        Self { area_type, index }
+    }
+
+    /**
+     * Decode an area pointer as found in the world tables and pipe pointers.
+     *
+     *  x T T I I I I I
+     *    |_| |_______|
+     *     |      |___ index of the area within its area type
+     *     |__________ area type
+     */
+    pub fn from_pointer(area_pointer: u8) -> Self {
+        let area_type = AreaType::new((area_pointer & 0b01100000) >> 5);
+        let index = area_pointer & 0b00011111;
+        Self { area_type, index }
+    }
+
+    /// Parse an area name such as `ground-5` or `underground-2`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (area_type, index) = name.split_once('-')?;
+        let area_type = match area_type {
+            "water" => AreaType::Water,
+            "ground" => AreaType::Ground,
+            "underground" => AreaType::Underground,
+            "castle" => AreaType::Castle,
+            _ => return None,
+        };
+        let index = index.parse().ok()?;
+        Some(Self { area_type, index })
+    }
+
+    /// Get the name of this area, as understood by `Area::from_name`.
+    pub fn name(&self) -> String {
+        let area_type = match self.area_type {
+            AreaType::Water => "water",
+            AreaType::Ground => "ground",
+            AreaType::Underground => "underground",
+            AreaType::Castle => "castle",
+        };
+        format!("{}-{}", area_type, self.index)
+    }
+
+    /// Encode this area as an area pointer.
+    pub fn pointer(&self) -> u8 {
+        (self.area_type.value() << 5) | (self.index & 0b00011111)
+    }
+}
+
+/// Addresses (CPU, $8000-$FFFF) of a single area's level data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AreaAddresses {
+    pub header: u16,
+    pub objects: u16,
+    pub enemies: u16,
+}
+
+/// The world and area pointer tables as stored in the rom.
+#[derive(Debug)]
+pub struct AreaPointerTables {
+    /// Offset into `area_pointers` of the first area of each world.
+    pub world_offsets: Vec<u8>,
+    /// Every world's area pointers, in the order they are played.
+    pub area_pointers: Vec<u8>,
+    /// Offset into `enemy_addrs` of the first area of each area type.
+    pub enemy_offsets: Vec<u8>,
+    pub enemy_addrs: Vec<u16>,
+    /// Offset into `area_addrs` of the first area of each area type.
+    pub area_offsets: Vec<u8>,
+    pub area_addrs: Vec<u16>,
+}
+
+impl AreaPointerTables {
+    pub fn from_rom(rom: &Rom) -> Result<Self> {
+        let num_area_pointers =
+            (ENEMY_ADDR_H_OFFSETS - AREA_ADDR_OFFSETS) as usize;
+        let num_enemy_addrs =
+            (ENEMY_DATA_ADDR_HIGH - ENEMY_DATA_ADDR_LOW) as usize;
+        let num_area_addrs =
+            (AREA_DATA_ADDR_HIGH - AREA_DATA_ADDR_LOW) as usize;
+
+        let world_offsets = read_table(rom, WORLD_ADDR_OFFSETS, NUM_WORLDS)?;
+        let area_pointers =
+            read_table(rom, AREA_ADDR_OFFSETS, num_area_pointers)?;
+        let enemy_offsets =
+            read_table(rom, ENEMY_ADDR_H_OFFSETS, NUM_AREA_TYPES)?;
+        let enemy_addrs = read_split_addrs(
+            rom,
+            ENEMY_DATA_ADDR_LOW,
+            ENEMY_DATA_ADDR_HIGH,
+            num_enemy_addrs,
+        )?;
+        let area_offsets =
+            read_table(rom, AREA_DATA_H_OFFSETS, NUM_AREA_TYPES)?;
+        let area_addrs = read_split_addrs(
+            rom,
+            AREA_DATA_ADDR_LOW,
+            AREA_DATA_ADDR_HIGH,
+            num_area_addrs,
+        )?;
+
+        Ok(Self {
+            world_offsets,
+            area_pointers,
+            enemy_offsets,
+            enemy_addrs,
+            area_offsets,
+            area_addrs,
+        })
+    }
+
+    /// Get the area pointers for a world (0-based), in the order played.
+    pub fn world_area_pointers(&self, world: usize) -> Result<&[u8]> {
+        ensure!(world < self.world_offsets.len(), "invalid world: {}", world);
+
+        let start = self.world_offsets[world] as usize;
+        let end = match self.world_offsets.get(world + 1) {
+            Some(&offset) => offset as usize,
+            None => self.area_pointers.len(),
+        };
+        ensure!(
+            start <= end && end <= self.area_pointers.len(),
+            "world {} area offsets out of range: {}..{}",
+            world,
+            start,
+            end
+        );
+
+        Ok(&self.area_pointers[start..end])
+    }
+
+    /// Get the number of areas of an area type.
+    pub fn area_count(&self, area_type: AreaType) -> usize {
+        let count = |offsets: &[u8], len: usize| {
+            let start = offsets[area_type.value() as usize] as usize;
+            let end = offsets
+                .iter()
+                .map(|&offset| offset as usize)
+                .filter(|&offset| offset > start)
+                .min()
+                .unwrap_or(len);
+            end.min(len).saturating_sub(start)
+        };
+
+        let enemies = count(&self.enemy_offsets, self.enemy_addrs.len());
+        let areas = count(&self.area_offsets, self.area_addrs.len());
+        enemies.min(areas)
+    }
+
+    /// Get every area in the game, grouped by area type.
+    pub fn areas(&self) -> Vec<Area> {
+        let area_types = [
+            AreaType::Water,
+            AreaType::Ground,
+            AreaType::Underground,
+            AreaType::Castle,
+        ];
+
+        let mut areas = vec![];
+        for area_type in area_types {
+            for index in 0..self.area_count(area_type) {
+                areas.push(Area::new(area_type, index as u8));
+            }
+        }
+        areas
+    }
+
+    /**
+     * Resolve an area to the addresses of its level data. Areas past the
+     * last of their type (such as `water-5`) are an error, as their index
+     * would land on the next type's areas.
+     */
+    pub fn area_addresses(&self, area: &Area) -> Result<AreaAddresses> {
+        let (enemy_idx, area_idx) = self.table_indices(area)?;
+        let enemies = self.enemy_addrs[enemy_idx];
+        let header = self.area_addrs[area_idx];
+
+        // object data immediately follows the 2 byte header
+        let objects = header.wrapping_add(2);
+
+        Ok(AreaAddresses { header, objects, enemies })
+    }
+
+    /// Point an area at new level data, in these tables and in the rom.
+    pub fn set_area_addresses(
+        &mut self,
+        rom: &mut Rom,
+        area: &Area,
+        header: u16,
+        enemies: u16,
+    ) -> Result<()> {
+        let (enemy_idx, area_idx) = self.table_indices(area)?;
+
+        let [low, high] = enemies.to_le_bytes();
+        rom.write_cpu(ENEMY_DATA_ADDR_LOW + enemy_idx as u16, &[low])?;
+        rom.write_cpu(ENEMY_DATA_ADDR_HIGH + enemy_idx as u16, &[high])?;
+        let [low, high] = header.to_le_bytes();
+        rom.write_cpu(AREA_DATA_ADDR_LOW + area_idx as u16, &[low])?;
+        rom.write_cpu(AREA_DATA_ADDR_HIGH + area_idx as u16, &[high])?;
+
+        self.enemy_addrs[enemy_idx] = enemies;
+        self.area_addrs[area_idx] = header;
+        Ok(())
+    }
+
+    /**
+     * Indices of an area into `enemy_addrs` and `area_addrs`.
+     *
+     * Each area type's addresses directly follow the last type's, so an
+     * index past the end of its type is refused rather than read as an area
+     * of the next type.
+     */
+    fn table_indices(&self, area: &Area) -> Result<(usize, usize)> {
+        let area_type = area.area_type.value() as usize;
+        let index = area.index as usize;
+        ensure!(
+            index < self.area_count(area.area_type),
+            "area {:?} is not in the area pointer tables",
+            area
+        );
+
+        let enemy_idx = self.enemy_offsets[area_type] as usize + index;
+        let area_idx = self.area_offsets[area_type] as usize + index;
+        Ok((enemy_idx, area_idx))
+    }
+}
+
+/// Read a table of `len` bytes starting at a CPU address.
+fn read_table(rom: &Rom, addr: u16, len: usize) -> Result<Vec<u8>> {
+    let bytes = rom.cpu_slice(addr)?;
+    ensure!(bytes.len() >= len, "table at ${:04x} truncated", addr);
+    Ok(bytes[..len].to_vec())
+}
+
+/// Read a table of addresses stored as separate low and high byte tables.
+fn read_split_addrs(
+    rom: &Rom,
+    low_addr: u16,
+    high_addr: u16,
+    len: usize,
+) -> Result<Vec<u16>> {
+    let low = read_table(rom, low_addr, len)?;
+    let high = read_table(rom, high_addr, len)?;
+    let addrs = low.iter().zip(high).map(|(&l, h)| u16::from_le_bytes([l, h]));
+    Ok(addrs.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::zero_rom;
+
+    /// Tables of 1 water, 3 ground, 2 underground and 2 castle areas.
+    fn area_tables() -> AreaPointerTables {
+        let offsets = vec![0, 1, 4, 6];
+        AreaPointerTables {
+            world_offsets: vec![0; NUM_WORLDS],
+            area_pointers: vec![],
+            enemy_offsets: offsets.clone(),
+            enemy_addrs: (0..8).map(|idx| 0x9000 + idx).collect(),
+            area_offsets: offsets,
+            area_addrs: (0..8).map(|idx| 0xa000 + idx).collect(),
+        }
+    }
+
+    #[test]
+    fn test_area_addresses() {
+        let tables = area_tables();
+        let area = Area::new(AreaType::Ground, 2);
+        let addresses = tables.area_addresses(&area).unwrap();
+        assert_eq!(addresses.header, 0xa003);
+        assert_eq!(addresses.objects, 0xa005);
+        assert_eq!(addresses.enemies, 0x9003);
+
+        // not the first underground area, which follows it in the tables
+        let area = Area::new(AreaType::Ground, 3);
+        assert!(tables.area_addresses(&area).is_err());
+        let area = Area::from_name("water-5").unwrap();
+        assert!(tables.area_addresses(&area).is_err());
+    }
+
+    #[test]
+    fn test_pipe_destination() {
+        let mut rom = zero_rom();
+        for addr in [ENEMY_ADDR_H_OFFSETS, AREA_DATA_H_OFFSETS] {
+            rom.write_cpu(addr, &[0, 1, 4, 6]).unwrap();
+        }
+
+        let mut pipe_pointer = PipePointer {
+            x_coordinate: 0,
+            y_coordinate: 0,
+            new_page_flag: false,
+            area: Area::new(AreaType::Water, 0),
+            world: 0,
+            page: 0,
+        };
+        assert_eq!(
+            rom.pipe_destination(&pipe_pointer).unwrap(),
+            pipe_pointer.area
+        );
+
+        // the second water area would be the first ground area
+        pipe_pointer.area = Area::new(AreaType::Water, 1);
+        assert!(rom.pipe_destination(&pipe_pointer).is_err());
+    }
+
+    #[test]
+    fn test_set_area_addresses() {
+        let mut rom = zero_rom();
+        let mut tables = area_tables();
+
+        let area = Area::new(AreaType::Castle, 1);
+        tables.set_area_addresses(&mut rom, &area, 0xb000, 0xb100).unwrap();
+        assert_eq!(tables.area_addrs[7], 0xb000);
+        assert_eq!(rom.read_cpu_u8(ENEMY_DATA_ADDR_HIGH + 7).unwrap(), 0xb1);
+
+        // past the end of its type, these would be another type's areas
+        for area in [
+            Area::new(AreaType::Water, 1),
+            Area::new(AreaType::Ground, 3),
+            Area::new(AreaType::Castle, 2),
+        ] {
+            let result = tables.set_area_addresses(&mut rom, &area, 0, 0);
+            assert!(result.is_err());
+        }
+        assert_eq!(tables.area_addrs[1], 0xa001);
+    }
+
+    #[test]
+    fn test_area_pointer() {
+        // 1-2 and the underground bonus room as stored in the world tables
+        let area = Area::from_pointer(0xc0);
+        assert_eq!(area, Area::new(AreaType::Underground, 0));
+        assert_eq!(area.pointer(), 0x40);
+
+        let area = Area::from_pointer(0x25);
+        assert_eq!(area, Area::new(AreaType::Ground, 5));
+        assert_eq!(area.pointer(), 0x25);
+
+        assert_eq!(Area::from_pointer(0x62).area_type, AreaType::Castle);
+        assert_eq!(Area::from_pointer(0x01).area_type, AreaType::Water);
+    }
+
+    #[test]
+    fn test_area_name() {
+        let area = Area::new(AreaType::Underground, 2);
+        assert_eq!(area.name(), "underground-2");
+        assert_eq!(Area::from_name("underground-2"), Some(area));
+        assert_eq!(Area::from_name("1-1"), None);
+        assert_eq!(Area::from_name("castle-x"), None);
+    }
+}
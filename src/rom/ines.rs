@@ -0,0 +1,221 @@
+use anyhow::{bail, ensure, Result};
+
+use crate::Error;
+
+/// Size of the iNES header found at the start of every `.nes` file.
+pub const INES_HEADER_SIZE: usize = 16;
+
+/// Size of a single PRG-ROM bank as counted by the header.
+pub const PRG_BANK_SIZE: usize = 0x4000;
+
+/// Size of a single CHR-ROM bank as counted by the header.
+pub const CHR_BANK_SIZE: usize = 0x2000;
+
+/// Size of the optional trainer that sits between the header and PRG-ROM.
+pub const TRAINER_SIZE: usize = 512;
+
+const INES_MAGIC: &[u8] = b"NES\x1a";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    FourScreen,
+}
+
+#[derive(Debug)]
+pub struct INesHeader {
+    pub prg_rom_banks: u16,
+    pub chr_rom_banks: u16,
+    pub mapper: u16,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub trainer: bool,
+    pub nes2: bool,
+}
+
+impl INesHeader {
+    /**
+     * From: https://www.nesdev.org/wiki/INES
+     *
+     *  0-3: "NES" followed by MS-DOS end-of-file (0x1A)
+     *    4: size of PRG-ROM in 16 KiB units
+     *    5: size of CHR-ROM in 8 KiB units
+     *    6: MMMM FTBM - mapper low nibble, four screen, trainer, battery,
+     *                  mirroring
+     *    7: MMMM VVxx - mapper high nibble, NES 2.0 identifier (VV = 0b10)
+     *    8: NES 2.0 only: xxxx MMMM - mapper bits 8-11
+     *    9: NES 2.0 only: CCCC PPPP - CHR/PRG-ROM size MSB
+     * 10-15: unused padding (iNES) or extended NES 2.0 fields
+     */
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Error::check_len("ines header", bytes, INES_HEADER_SIZE)?;
+        if &bytes[0..4] != INES_MAGIC {
+            return Err(Error::InvalidHeader {
+                what: "ines header",
+                offset: 0,
+                reason: format!("magic invalid: {:02x?}", &bytes[0..4]),
+            });
+        }
+
+        let flags6 = bytes[6];
+        let flags7 = bytes[7];
+        let nes2 = flags7 & 0b00001100 == 0b00001000;
+
+        let mut prg_rom_banks = bytes[4] as u16;
+        let mut chr_rom_banks = bytes[5] as u16;
+        let mut mapper = ((flags7 & 0xF0) | (flags6 >> 4)) as u16;
+        if nes2 {
+            mapper |= ((bytes[8] & 0x0F) as u16) << 8;
+            prg_rom_banks |= ((bytes[9] & 0x0F) as u16) << 8;
+            chr_rom_banks |= ((bytes[9] & 0xF0) as u16) << 4;
+        }
+
+        let mirroring = if flags6 & 0b00001000 != 0 {
+            Mirroring::FourScreen
+        } else if flags6 & 0b00000001 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        let battery = flags6 & 0b00000010 != 0;
+        let trainer = flags6 & 0b00000100 != 0;
+
+        Ok(Self {
+            prg_rom_banks,
+            chr_rom_banks,
+            mapper,
+            mirroring,
+            battery,
+            trainer,
+            nes2,
+        })
+    }
+
+    /// Size of PRG-ROM in bytes.
+    pub fn prg_size(&self) -> usize {
+        self.prg_rom_banks as usize * PRG_BANK_SIZE
+    }
+
+    /// Size of CHR-ROM in bytes.
+    pub fn chr_size(&self) -> usize {
+        self.chr_rom_banks as usize * CHR_BANK_SIZE
+    }
+
+    /// File offset of the first byte of PRG-ROM.
+    pub fn prg_offset(&self) -> usize {
+        let trainer_size = if self.trainer { TRAINER_SIZE } else { 0 };
+        INES_HEADER_SIZE + trainer_size
+    }
+
+    /// File offset of the first byte of CHR-ROM.
+    pub fn chr_offset(&self) -> usize {
+        self.prg_offset() + self.prg_size()
+    }
+
+    /// Total file size described by this header.
+    pub fn file_size(&self) -> usize {
+        self.chr_offset() + self.chr_size()
+    }
+
+    /**
+     * Translate a CPU address into an offset into PRG-ROM.
+     *
+     * Only NROM (mapper 0) is supported: PRG-ROM is mapped at $8000-$FFFF
+     * and a single 16 KiB bank is mirrored into $C000-$FFFF.
+     */
+    pub fn cpu_to_prg(&self, addr: u16) -> Result<usize> {
+        if self.mapper != 0 {
+            bail!(
+                "unsupported mapper for address translation: {}",
+                self.mapper
+            );
+        }
+        if addr < 0x8000 {
+            bail!("cpu address ${:04x} is not mapped to prg-rom", addr);
+        }
+
+        let prg_size = self.prg_size();
+        ensure!(prg_size > 0, "rom has no prg-rom");
+
+        Ok((addr as usize - 0x8000) % prg_size)
+    }
+
+    /// Translate an offset into PRG-ROM into its (first) CPU address.
+    pub fn prg_to_cpu(&self, offset: usize) -> Result<u16> {
+        if self.mapper != 0 {
+            bail!(
+                "unsupported mapper for address translation: {}",
+                self.mapper
+            );
+        }
+        ensure!(
+            offset < self.prg_size() && offset < 0x8000,
+            "prg offset {:#x} is not mapped to a cpu address",
+            offset
+        );
+
+        Ok((0x8000 + offset) as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMB1_HEADER: [u8; 16] =
+        [0x4e, 0x45, 0x53, 0x1a, 0x02, 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+    #[test]
+    fn test_smb1_header() {
+        let header = INesHeader::from_bytes(&SMB1_HEADER).unwrap();
+        assert_eq!(header.prg_rom_banks, 2);
+        assert_eq!(header.chr_rom_banks, 1);
+        assert_eq!(header.mapper, 0);
+        assert_eq!(header.mirroring, Mirroring::Vertical);
+        assert!(!header.battery);
+        assert!(!header.trainer);
+        assert!(!header.nes2);
+        assert_eq!(header.prg_offset(), 0x10);
+        assert_eq!(header.chr_offset(), 0x8010);
+        assert_eq!(header.file_size(), 40976);
+    }
+
+    #[test]
+    fn test_flags() {
+        let mut bytes = SMB1_HEADER;
+        bytes[6] = 0b0100_1110;
+        bytes[7] = 0b0100_1000;
+        bytes[8] = 0x01;
+        let header = INesHeader::from_bytes(&bytes).unwrap();
+        assert_eq!(header.mapper, 0x144);
+        assert_eq!(header.mirroring, Mirroring::FourScreen);
+        assert!(header.battery);
+        assert!(header.trainer);
+        assert!(header.nes2);
+        assert_eq!(header.prg_offset(), 0x210);
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        let mut bytes = SMB1_HEADER;
+        bytes[3] = 0x00;
+        assert!(INesHeader::from_bytes(&bytes).is_err());
+        assert!(INesHeader::from_bytes(&bytes[..8]).is_err());
+    }
+
+    #[test]
+    fn test_cpu_translation() {
+        let header = INesHeader::from_bytes(&SMB1_HEADER).unwrap();
+        assert_eq!(header.cpu_to_prg(0x8000).unwrap(), 0x0000);
+        assert_eq!(header.cpu_to_prg(0xfffc).unwrap(), 0x7ffc);
+        assert_eq!(header.prg_to_cpu(0x7ffc).unwrap(), 0xfffc);
+        assert!(header.cpu_to_prg(0x7fff).is_err());
+
+        // NROM-128 mirrors its only bank
+        let mut bytes = SMB1_HEADER;
+        bytes[4] = 0x01;
+        let header = INesHeader::from_bytes(&bytes).unwrap();
+        assert_eq!(header.cpu_to_prg(0xc123).unwrap(), 0x0123);
+    }
+}
@@ -0,0 +1,293 @@
+use std::ops::Range;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::*;
+
+/// The two kinds of level data blob an area points to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelDataKind {
+    /// Level header followed by object data, terminated by 0xFD.
+    AreaData,
+    /// Enemy data, terminated by 0xFF.
+    EnemyData,
+}
+
+/// A run of level data in the rom and every area that points to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelDataBlob {
+    pub kind: LevelDataKind,
+    /// CPU address of the first byte.
+    pub addr: u16,
+    pub len: usize,
+    pub areas: Vec<Area>,
+}
+
+impl LevelDataBlob {
+    /// CPU addresses covered by this blob.
+    pub fn range(&self) -> Range<usize> {
+        self.addr as usize..self.addr as usize + self.len
+    }
+
+    /// Number of areas that point to this blob.
+    pub fn ref_count(&self) -> usize {
+        self.areas.len()
+    }
+}
+
+/**
+ * Which bytes of the level data bank are in use, and by which areas.
+ *
+ * Levels that share an area (such as 1-3 and 5-3) share its blobs too, so
+ * they are counted once. Bytes inside `region` not covered by any blob are
+ * not referenced by the game and are free to be reused.
+ */
+#[derive(Debug, Clone)]
+pub struct LevelDataUsage {
+    /// CPU addresses that hold level data.
+    pub region: Range<usize>,
+    pub blobs: Vec<LevelDataBlob>,
+}
+
+impl LevelDataUsage {
+    /// Get the blob of a kind that an area points to.
+    pub fn area_blob(
+        &self,
+        area: &Area,
+        kind: LevelDataKind,
+    ) -> Option<&LevelDataBlob> {
+        self.blobs
+            .iter()
+            .find(|blob| blob.kind == kind && blob.areas.contains(area))
+    }
+
+    /// Get the unused ranges of the region, lowest address first.
+    pub fn free_space(&self) -> Vec<Range<usize>> {
+        let used = self.used_map(None);
+
+        let mut free = vec![];
+        let mut start = None;
+        for (idx, &is_used) in used.iter().enumerate() {
+            let addr = self.region.start + idx;
+            match (is_used, start) {
+                (false, None) => start = Some(addr),
+                (true, Some(free_start)) => {
+                    free.push(free_start..addr);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(free_start) = start {
+            free.push(free_start..self.region.end);
+        }
+
+        free
+    }
+
+    /**
+     * Mark which bytes of the region are in use.
+     *
+     * Blobs only referenced by `released` are left unmarked, as they are
+     * about to be replaced.
+     */
+    pub(crate) fn used_map(&self, released: Option<&Area>) -> Vec<bool> {
+        let mut used = vec![false; self.region.len()];
+        for blob in &self.blobs {
+            let is_released = released.is_some_and(|area| {
+                blob.areas.iter().all(|blob_area| blob_area == area)
+            });
+            if is_released {
+                continue;
+            }
+
+            let range = blob.range();
+            let start = range.start.max(self.region.start);
+            let end = range.end.min(self.region.end);
+            for addr in start..end {
+                used[addr - self.region.start] = true;
+            }
+        }
+        used
+    }
+
+    /**
+     * Find room for `len` bytes and mark them as used.
+     *
+     * `preferred` is tried first so data that still fits stays where it is,
+     * otherwise the first free range large enough is used.
+     */
+    pub(crate) fn allocate(
+        &self,
+        used: &mut [bool],
+        len: usize,
+        preferred: Option<usize>,
+    ) -> Option<usize> {
+        let fits = |start: usize| {
+            start + len <= used.len()
+                && used[start..start + len].iter().all(|&is_used| !is_used)
+        };
+
+        let preferred = preferred
+            .filter(|addr| self.region.contains(addr))
+            .map(|addr| addr - self.region.start);
+        let start = match preferred {
+            Some(start) if fits(start) => start,
+            _ => (0..used.len()).find(|&start| fits(start))?,
+        };
+
+        for byte in &mut used[start..start + len] {
+            *byte = true;
+        }
+        Some(self.region.start + start)
+    }
+}
+
+impl Rom {
+    /// Find every level data blob in the rom and the areas using it.
+    pub fn level_data_usage(&self) -> Result<LevelDataUsage> {
+        let tables = self.area_tables()?;
+
+        let mut blobs: Vec<LevelDataBlob> = vec![];
+        for area in tables.areas() {
+            let addresses = tables.area_addresses(&area)?;
+            let level = self.read_level(&addresses)?;
+            let area_blobs = [
+                (
+                    LevelDataKind::AreaData,
+                    addresses.header,
+                    level.area_data_to_bytes().len(),
+                ),
+                (
+                    LevelDataKind::EnemyData,
+                    addresses.enemies,
+                    level.enemy_data_to_bytes().len(),
+                ),
+            ];
+
+            for (kind, addr, len) in area_blobs {
+                let existing = blobs
+                    .iter_mut()
+                    .find(|blob| blob.kind == kind && blob.addr == addr);
+                match existing {
+                    Some(blob) => {
+                        blob.len = blob.len.max(len);
+                        blob.areas.push(area);
+                    }
+                    None => blobs.push(LevelDataBlob {
+                        kind,
+                        addr,
+                        len,
+                        areas: vec![area],
+                    }),
+                }
+            }
+        }
+
+        let region = match &self.level_data_region {
+            Some(region) => region.clone(),
+            None => {
+                let start = blobs.iter().map(|blob| blob.range().start).min();
+                let end = blobs.iter().map(|blob| blob.range().end).max();
+                match (start, end) {
+                    (Some(start), Some(end)) => start..end,
+                    _ => bail!("rom has no level data"),
+                }
+            }
+        };
+
+        Ok(LevelDataUsage { region, blobs })
+    }
+
+    /**
+     * Replace the level data of an area.
+     *
+     * The new data is written in place when it fits, otherwise it is moved
+     * to the first free range of the level data bank and the area's
+     * pointers are updated. Blobs shared with other areas are never
+     * overwritten. Every level using this area (such as 1-3 and 5-3) sees
+     * the change.
+     */
+    pub fn write_area(&mut self, area: &Area, level: &Level) -> Result<()> {
+        let usage = self.level_data_usage()?;
+        let mut tables = self.area_tables()?;
+        // fail on unknown areas before anything is written
+        tables.area_addresses(area)?;
+
+        let area_data = level.area_data_to_bytes();
+        let enemy_data = level.enemy_data_to_bytes();
+
+        let mut used = usage.used_map(Some(area));
+        let mut place = |kind: LevelDataKind, len: usize| {
+            let preferred = usage.area_blob(area, kind).map(|blob| blob.addr);
+            let addr =
+                usage.allocate(&mut used, len, preferred.map(usize::from));
+            addr.map(|addr| addr as u16).ok_or_else(|| {
+                anyhow!(
+                    "level data bank out of space: {:?} for {} needs {} bytes",
+                    kind,
+                    area.name(),
+                    len
+                )
+            })
+        };
+        let header = place(LevelDataKind::AreaData, area_data.len())?;
+        let enemies = place(LevelDataKind::EnemyData, enemy_data.len())?;
+
+        // keep the region fixed so bytes freed at its end are not lost
+        self.level_data_region = Some(usage.region);
+        self.write_cpu(header, &area_data)?;
+        self.write_cpu(enemies, &enemy_data)?;
+        tables.set_area_addresses(self, area, header, enemies)
+    }
+
+    /// Replace the level data of the area a level starts in.
+    pub fn set_level(
+        &mut self,
+        level_name: &RomLevel,
+        level: &Level,
+    ) -> Result<()> {
+        let area = self.level_area(level_name)?;
+        self.write_area(&area, level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blob(
+        kind: LevelDataKind,
+        addr: u16,
+        len: usize,
+        areas: &[Area],
+    ) -> LevelDataBlob {
+        LevelDataBlob { kind, addr, len, areas: areas.to_vec() }
+    }
+
+    #[test]
+    fn test_free_space() {
+        let ground = Area::new(AreaType::Ground, 0);
+        let castle = Area::new(AreaType::Castle, 0);
+        let usage = LevelDataUsage {
+            region: 0x9000..0x9010,
+            blobs: vec![
+                blob(LevelDataKind::EnemyData, 0x9000, 4, &[ground]),
+                blob(LevelDataKind::EnemyData, 0x9006, 2, &[ground, castle]),
+                blob(LevelDataKind::AreaData, 0x900a, 4, &[castle]),
+            ],
+        };
+        assert_eq!(
+            usage.free_space(),
+            vec![0x9004..0x9006, 0x9008..0x900a, 0x900e..0x9010]
+        );
+
+        // only blobs no other area points to are released
+        let mut used = usage.used_map(Some(&ground));
+        assert_eq!(used.iter().filter(|&&is_used| is_used).count(), 6);
+        assert_eq!(usage.allocate(&mut used, 6, None), Some(0x9000));
+        assert_eq!(usage.allocate(&mut used, 2, Some(0x9006)), Some(0x9008));
+        assert_eq!(usage.allocate(&mut used, 2, Some(0x900e)), Some(0x900e));
+        assert_eq!(usage.allocate(&mut used, 1, None), None);
+    }
+}
@@ -0,0 +1,244 @@
+use anyhow::{ensure, Result};
+
+use crate::*;
+
+/*
+ * The game queues palette writes as VRAM buffer updates, picked by index
+ * from `VRAM_AddrTable`.
+ */
+pub(crate) const VRAM_ADDR_TABLE_LOW: u16 = 0x805a;
+pub(crate) const VRAM_ADDR_TABLE_HIGH: u16 = 0x806d;
+pub(crate) const VRAM_ADDR_TABLE_LEN: u8 = 19;
+
+/// `VRAM_AddrTable` index of each area type's palettes.
+const AREA_PALETTE_INDICES: [u8; 4] = [0x01, 0x02, 0x03, 0x04];
+
+/// `VRAM_AddrTable` index of the palette loaded for `LevelBackground` 4-7.
+const BACKGROUND_PALETTE_INDICES: [u8; 4] = [0x00, 0x09, 0x0a, 0x04];
+
+/// Background color by area type, then by `LevelBackground` 4-7.
+const BACKGROUND_COLORS: [u8; 8] =
+    [0x22, 0x22, 0x0f, 0x0f, 0x0f, 0x22, 0x0f, 0x0f];
+
+/// Start of palette memory in the PPU's address space.
+pub const PALETTE_PPU_ADDR: u16 = 0x3f00;
+
+/// Bytes of palette memory, 4 background then 4 sprite palettes.
+pub const PALETTE_RAM_SIZE: usize = 32;
+
+/**
+ * A single VRAM buffer update, as stored in the rom.
+ *
+ *  A A A A A A A A   A A A A A A A A   V R L L L L L L   D ...
+ *  |_____________|   |_____________|   | | |_________|   |___|
+ *         |                 |          | |      |          |___ data
+ *         |                 |          | |      |______________ length
+ *         |                 |          | |_____________________ repeat
+ *         |                 |          |_______________________ vertical
+ *         |_________________|__________________________________ ppu address
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VramUpdate {
+    pub ppu_addr: u16,
+    pub vertical: bool,
+    /// The data is a single byte written `len` times.
+    pub repeat: bool,
+    pub len: u8,
+    pub data: Vec<u8>,
+}
+
+impl VramUpdate {
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Error::check_len("vram update", bytes, 3)?;
+
+        let ppu_addr = u16::from_be_bytes([bytes[0], bytes[1]]);
+        let vertical = bytes[2] & 0b10000000 != 0;
+        let repeat = bytes[2] & 0b01000000 != 0;
+        let len = bytes[2] & 0b00111111;
+
+        let data_len = if repeat { 1 } else { len as usize };
+        Error::check_len("vram update", bytes, 3 + data_len)?;
+        let data = bytes[3..3 + data_len].to_vec();
+
+        Ok(Self { ppu_addr, vertical, repeat, len, data })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::try_from_bytes(bytes).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let flags = (self.vertical as u8) << 7 | (self.repeat as u8) << 6;
+        let mut bytes = self.ppu_addr.to_be_bytes().to_vec();
+        bytes.push(flags | (self.len & 0b00111111));
+        bytes.extend(&self.data);
+        bytes
+    }
+
+    /// Get every byte written, in order.
+    pub fn bytes(&self) -> Vec<u8> {
+        match self.repeat {
+            true => self.data.repeat(self.len as usize),
+            false => self.data.clone(),
+        }
+    }
+}
+
+/**
+ * The 32 bytes of palette memory, holding NES color indices.
+ *
+ * Writes follow the PPU's mirroring: the first color of each sprite palette
+ * is the same byte as the first color of the matching background palette.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palettes {
+    pub colors: [u8; PALETTE_RAM_SIZE],
+}
+
+impl Default for Palettes {
+    fn default() -> Self {
+        Self { colors: [0x0f; PALETTE_RAM_SIZE] }
+    }
+}
+
+impl Palettes {
+    /// Apply a VRAM update, ignoring writes outside palette memory.
+    pub fn apply(&mut self, update: &VramUpdate) {
+        let step = if update.vertical { 32 } else { 1 };
+        for (idx, byte) in update.bytes().into_iter().enumerate() {
+            let addr = update.ppu_addr as usize + idx * step;
+            if (0x3f00..0x4000).contains(&addr) {
+                self.write(addr as u16, byte);
+            }
+        }
+    }
+
+    /// Write a color to a PPU address in $3F00-$3FFF.
+    pub fn write(&mut self, ppu_addr: u16, color: u8) {
+        let mut idx = ppu_addr as usize % PALETTE_RAM_SIZE;
+        if idx & 0b10011 == 0b10000 {
+            idx &= 0b01111;
+        }
+        self.colors[idx] = color & 0b00111111;
+    }
+
+    /// The color shown where every background palette has color 0.
+    pub fn background_color(&self) -> u8 {
+        self.colors[0]
+    }
+
+    /// Get one of the 4 background palettes.
+    pub fn background(&self, palette: usize) -> [u8; 4] {
+        self.palette(palette % 4)
+    }
+
+    /// Get one of the 4 sprite palettes, color 0 is transparent.
+    pub fn sprite(&self, palette: usize) -> [u8; 4] {
+        self.palette(4 + palette % 4)
+    }
+
+    fn palette(&self, palette: usize) -> [u8; 4] {
+        let mut colors = [0; 4];
+        colors.copy_from_slice(&self.colors[palette * 4..palette * 4 + 4]);
+        // color 0 always reads back as the background color
+        colors[0] = self.background_color();
+        colors
+    }
+}
+
+impl Rom {
+    /// Read the VRAM update at an index of the game's `VRAM_AddrTable`.
+    pub fn vram_update(&self, index: u8) -> Result<VramUpdate> {
+        ensure!(index < VRAM_ADDR_TABLE_LEN, "no vram update {}", index);
+        let low = self.read_cpu_u8(VRAM_ADDR_TABLE_LOW + index as u16)?;
+        let high = self.read_cpu_u8(VRAM_ADDR_TABLE_HIGH + index as u16)?;
+        let addr = u16::from_le_bytes([low, high]);
+
+        let bytes = self.cpu_slice(addr)?;
+        let offset = self.cpu_to_offset(addr)?;
+        Ok(VramUpdate::try_from_bytes(bytes)
+            .map_err(|e| e.offset_by(offset))?)
+    }
+
+    /**
+     * Get the palettes the game loads for an area type and background.
+     *
+     * Backgrounds 4-7 (night, snow, night and snow, black and white) change
+     * the background color and some palettes, the others only change the
+     * scenery drawn.
+     */
+    pub fn palettes(
+        &self,
+        area_type: AreaType,
+        background: LevelBackground,
+    ) -> Result<Palettes> {
+        let mut palettes = Palettes::default();
+
+        let area_type = area_type.value() as usize;
+        palettes.apply(&self.vram_update(AREA_PALETTE_INDICES[area_type])?);
+
+        let background = background.value() as usize;
+        let color_index = match background {
+            4..=7 => {
+                let index = BACKGROUND_PALETTE_INDICES[background - 4];
+                // index 0 is the empty VRAM buffer, nothing to load
+                if index != 0 {
+                    palettes.apply(&self.vram_update(index)?);
+                }
+                background
+            }
+            _ => area_type,
+        };
+
+        // the player's colors are loaded last, with the background color
+        palettes.write(PALETTE_PPU_ADDR + 0x10, BACKGROUND_COLORS[color_index]);
+        Ok(palettes)
+    }
+
+    /// Get the palettes the game loads for an area.
+    pub fn area_palettes(&self, area: &Area) -> Result<Palettes> {
+        let level = self.get_area(area)?;
+        self.palettes(area.area_type, level.level_header.background)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vram_update() {
+        let update = VramUpdate::from_bytes(&[0x3f, 0x0c, 0x04, 1, 2, 3, 4, 0]);
+        assert_eq!(update.ppu_addr, 0x3f0c);
+        assert_eq!(update.bytes(), [1, 2, 3, 4]);
+        assert_eq!(update.to_bytes(), [0x3f, 0x0c, 0x04, 1, 2, 3, 4]);
+
+        let update = VramUpdate::from_bytes(&[0x20, 0x00, 0x48, 0x24]);
+        assert!(update.repeat);
+        assert_eq!(update.bytes(), [0x24; 8]);
+        assert_eq!(update.to_bytes(), [0x20, 0x00, 0x48, 0x24]);
+
+        assert!(VramUpdate::try_from_bytes(&[0x3f, 0x00, 0x04, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_palettes() {
+        let mut palettes = Palettes::default();
+        let colors: Vec<u8> = (0..32).collect();
+        palettes.apply(&VramUpdate {
+            ppu_addr: PALETTE_PPU_ADDR,
+            vertical: false,
+            repeat: false,
+            len: 32,
+            data: colors,
+        });
+
+        // $3f10 mirrors $3f00, so the last write wins
+        assert_eq!(palettes.background_color(), 0x10);
+        assert_eq!(palettes.background(1), [0x10, 5, 6, 7]);
+        assert_eq!(palettes.sprite(1), [0x10, 0x15, 0x16, 0x17]);
+
+        palettes.write(0x3f24, 0x7f);
+        assert_eq!(palettes.colors[4], 0x3f);
+    }
+}
@@ -0,0 +1,224 @@
+use std::ops::Range;
+
+use crate::*;
+
+/// Size of the game's PRG-ROM.
+pub const SMB1_PRG_SIZE: usize = 2 * PRG_BANK_SIZE;
+
+/// Size of the game's CHR-ROM.
+pub const SMB1_CHR_SIZE: usize = CHR_BANK_SIZE;
+
+/// iNES header of the original NROM-256 cartridge.
+pub const SMB1_INES_HEADER: [u8; INES_HEADER_SIZE] =
+    [0x4e, 0x45, 0x53, 0x1a, 0x02, 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+/**
+ * A known dump of the game.
+ *
+ * Hashes cover PRG-ROM followed by CHR-ROM without any header, so the same
+ * dump is recognised whatever header (or lack of one) it was saved with.
+ */
+#[derive(Debug, PartialEq, Eq)]
+pub struct RomProfile {
+    pub name: &'static str,
+    pub crc32: u32,
+    pub md5: &'static str,
+    pub sha1: &'static str,
+}
+
+/**
+ * Every dump of the game we know how to read.
+ *
+ * The game's cartridges we know of, the multicarts included, all carry the
+ * same PRG-ROM and CHR-ROM, so there is a single entry. The Vs. System and
+ * Famicom Disk System releases are different hardware, and aren't read by
+ * this crate. The hashes cover CHR-ROM too, as published for headerless
+ * dumps, so a graphics hack is loaded as a modified rom.
+ */
+pub const ROM_PROFILES: &[RomProfile] = &[RomProfile {
+    name: "Super Mario Bros. (World)",
+    crc32: 0x3337ec46,
+    md5: "8e3630186e35d477231bf8fd50e54cdd",
+    sha1: "ea343f4e445a9050d4b4fbac2c77d0693b1d0922",
+}];
+
+impl RomProfile {
+    /// Find the known dump matching every one of these hashes.
+    pub fn find(hashes: &RomHashes) -> Option<&'static Self> {
+        ROM_PROFILES.iter().find(|profile| profile.matches(hashes))
+    }
+
+    pub fn matches(&self, hashes: &RomHashes) -> bool {
+        self.crc32 == hashes.crc32
+            && self.md5 == hashes.md5
+            && self.sha1 == hashes.sha1
+    }
+}
+
+/// Hashes of PRG-ROM followed by CHR-ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomHashes {
+    pub crc32: u32,
+    pub md5: String,
+    pub sha1: String,
+}
+
+impl RomHashes {
+    pub fn new(prg: &[u8], chr: &[u8]) -> Self {
+        let payload = [prg, chr].concat();
+        Self {
+            crc32: crc32fast::hash(&payload),
+            md5: format!("{:x}", md5::compute(&payload)),
+            sha1: sha1_smol::Sha1::from(&payload).digest().to_string(),
+        }
+    }
+}
+
+/// Where the game was found inside a dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomLayout {
+    /// A standard iNES file, possibly with a non-standard header.
+    INes,
+    /// PRG-ROM and CHR-ROM without an iNES header.
+    Headerless,
+    /// One game of a multicart (such as Super Mario Bros./Duck Hunt).
+    Multicart,
+}
+
+/// A known dump found in some rom data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomIdentity {
+    pub profile: &'static RomProfile,
+    pub layout: RomLayout,
+    /// Offsets of the game's PRG-ROM in the rom data.
+    pub prg: Range<usize>,
+    /// Offsets of the game's CHR-ROM in the rom data.
+    pub chr: Range<usize>,
+}
+
+impl RomIdentity {
+    /// Identify the dump, if any, that `data` holds.
+    pub fn identify(data: &[u8]) -> Option<Self> {
+        for (layout, prg, chr) in candidates(data) {
+            // the crc alone rules out most candidates cheaply
+            let crc32 = {
+                let mut hasher = crc32fast::Hasher::new();
+                hasher.update(&data[prg.clone()]);
+                hasher.update(&data[chr.clone()]);
+                hasher.finalize()
+            };
+            if ROM_PROFILES.iter().all(|profile| profile.crc32 != crc32) {
+                continue;
+            }
+
+            let hashes = RomHashes::new(&data[prg.clone()], &data[chr.clone()]);
+            if let Some(profile) = RomProfile::find(&hashes) {
+                return Some(Self { profile, layout, prg, chr });
+            }
+        }
+
+        None
+    }
+
+    /// Rebuild the game as a standard iNES file.
+    pub fn to_ines(&self, data: &[u8]) -> Vec<u8> {
+        [&SMB1_INES_HEADER, &data[self.prg.clone()], &data[self.chr.clone()]]
+            .concat()
+    }
+}
+
+/**
+ * Every place the game's PRG-ROM and CHR-ROM could be in `data`.
+ *
+ * Multicarts are searched one 16 KiB PRG bank and one 8 KiB CHR bank at a
+ * time, which covers the discrete mappers they use.
+ */
+fn candidates(data: &[u8]) -> Vec<(RomLayout, Range<usize>, Range<usize>)> {
+    let mut candidates = vec![];
+
+    let header = match INesHeader::from_bytes(data) {
+        Ok(header) => header,
+        Err(_) => {
+            if data.len() == SMB1_PRG_SIZE + SMB1_CHR_SIZE {
+                let prg = 0..SMB1_PRG_SIZE;
+                let chr = SMB1_PRG_SIZE..data.len();
+                candidates.push((RomLayout::Headerless, prg, chr));
+            }
+            return candidates;
+        }
+    };
+
+    let prg_end = header.chr_offset().min(data.len());
+    let chr_end = header.file_size().min(data.len());
+    let layout = if header.prg_size() == SMB1_PRG_SIZE
+        && header.chr_size() == SMB1_CHR_SIZE
+    {
+        RomLayout::INes
+    } else {
+        RomLayout::Multicart
+    };
+
+    let prg_starts = (header.prg_offset()..prg_end).step_by(PRG_BANK_SIZE);
+    for prg_start in prg_starts {
+        let prg = prg_start..prg_start + SMB1_PRG_SIZE;
+        if prg.end > prg_end {
+            break;
+        }
+
+        let chr_starts = (header.chr_offset()..chr_end).step_by(CHR_BANK_SIZE);
+        for chr_start in chr_starts {
+            let chr = chr_start..chr_start + SMB1_CHR_SIZE;
+            if chr.end > chr_end {
+                break;
+            }
+            candidates.push((layout, prg.clone(), chr));
+        }
+    }
+
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashes() {
+        let hashes = RomHashes::new(b"12345", b"6789");
+        assert_eq!(hashes.crc32, 0xcbf43926);
+        assert_eq!(hashes.md5, "25f9e794323b453885f5181f1b624d0b");
+        assert_eq!(hashes.sha1, "f7c3bc1d808e04732adf679965ccc34ca7ae3441");
+        assert_eq!(RomProfile::find(&hashes), None);
+    }
+
+    #[test]
+    fn test_candidates() {
+        let headerless = vec![0; SMB1_PRG_SIZE + SMB1_CHR_SIZE];
+        assert_eq!(
+            candidates(&headerless),
+            [(RomLayout::Headerless, 0..0x8000, 0x8000..0xa000)]
+        );
+
+        let mut ines = SMB1_INES_HEADER.to_vec();
+        ines.extend(&headerless);
+        assert_eq!(
+            candidates(&ines),
+            [(RomLayout::INes, 0x10..0x8010, 0x8010..0xa010)]
+        );
+
+        // 64 KiB PRG and 16 KiB CHR, as on Super Mario Bros./Duck Hunt
+        let mut multicart = SMB1_INES_HEADER.to_vec();
+        multicart[4] = 4;
+        multicart[5] = 2;
+        multicart[6] = 0x21;
+        multicart[7] = 0x40;
+        multicart.resize(0x10 + 0x10000 + 0x4000, 0);
+        let found = candidates(&multicart);
+        assert_eq!(found.len(), 3 * 2);
+        assert!(found
+            .iter()
+            .all(|(layout, ..)| *layout == RomLayout::Multicart));
+        assert_eq!(found[5].1, 0x8010..0x10010);
+        assert_eq!(found[5].2, 0x12010..0x14010);
+    }
+}
@@ -0,0 +1,445 @@
+use anyhow::{anyhow, ensure, Result};
+
+use crate::util::find_pattern;
+use crate::*;
+
+/// Background tile drawn for a space.
+pub const BLANK_TILE: u8 = 0x24;
+
+/// Background tiles that draw characters, other than digits and letters.
+const SYMBOL_TILES: [(char, u8); 6] = [
+    (' ', BLANK_TILE),
+    ('-', 0x28),
+    ('x', 0x29),
+    ('!', 0x2b),
+    ('.', 0xaf),
+    ('©', 0xcf),
+];
+
+/**
+ * `WriteGameText`, copying an entry of `GameText` into the VRAM buffer:
+ *
+ * ```text
+ * ldx GameTextOffsets,y
+ * ldy #$00
+ * lda GameText,x
+ * cmp #$ff
+ * beq EndGameText
+ * sta VRAM_Buffer1,y
+ * ```
+ */
+const WRITE_GAME_TEXT_CODE: [Option<u8>; 15] = [
+    Some(0xbe),
+    None,
+    None,
+    Some(0xa0),
+    Some(0x00),
+    Some(0xbd),
+    None,
+    None,
+    Some(0xc9),
+    Some(0xff),
+    Some(0xf0),
+    None,
+    Some(0x99),
+    Some(0x01),
+    Some(0x03),
+];
+
+/**
+ * Writing `LuigiName` over the name in the status bar:
+ *
+ * ```text
+ * lda LuigiName,y
+ * sta VRAM_Buffer1+3,y
+ * ```
+ */
+const WRITE_LUIGI_NAME_CODE: [Option<u8>; 6] =
+    [Some(0xb9), None, None, Some(0x99), Some(0x04), Some(0x03)];
+
+/// Length of `LuigiName`, the same as the name it replaces.
+const LUIGI_NAME_LEN: usize = 5;
+
+/// Get the character a background tile draws, if it's text.
+pub fn tile_char(tile: u8) -> Option<char> {
+    match tile {
+        0x00..=0x09 => Some((b'0' + tile) as char),
+        0x0a..=0x23 => Some((b'A' + tile - 0x0a) as char),
+        _ => SYMBOL_TILES
+            .iter()
+            .find(|&&(_, symbol)| symbol == tile)
+            .map(|&(c, _)| c),
+    }
+}
+
+/// Get the background tile that draws a character.
+pub fn char_tile(c: char) -> Option<u8> {
+    match c {
+        '0'..='9' => Some(c as u8 - b'0'),
+        'A'..='Z' => Some(c as u8 - b'A' + 0x0a),
+        _ => SYMBOL_TILES
+            .iter()
+            .find(|&&(symbol, _)| symbol == c)
+            .map(|&(_, tile)| tile),
+    }
+}
+
+/**
+ * Turn background tiles into text.
+ *
+ * Tiles that aren't characters (such as the coin in the status bar) are
+ * written as `{xx}`, their index in hex.
+ */
+pub fn decode_text(tiles: &[u8]) -> String {
+    tiles
+        .iter()
+        .map(|&tile| match tile_char(tile) {
+            Some(c) => c.to_string(),
+            None => format!("{{{:02x}}}", tile),
+        })
+        .collect()
+}
+
+/// Turn text back into background tiles, the reverse of `decode_text`.
+pub fn encode_text(text: &str) -> Result<Vec<u8>, Error> {
+    let invalid = |value: &str| Error::InvalidValue {
+        what: "text character",
+        value: format!("{:?}", value),
+    };
+
+    let mut tiles = vec![];
+    let mut chars = text.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        if c != '{' {
+            tiles.push(char_tile(c).ok_or_else(|| invalid(&c.to_string()))?);
+            continue;
+        }
+        let escape = text.get(idx..idx + 4).unwrap_or(&text[idx..]);
+        let tile = escape
+            .strip_suffix('}')
+            .filter(|_| escape.len() == 4)
+            .and_then(|hex| u8::from_str_radix(&hex[1..], 16).ok())
+            .ok_or_else(|| invalid(escape))?;
+        tiles.push(tile);
+        chars.nth(2);
+    }
+    Ok(tiles)
+}
+
+/// Where a string of the catalog is, see `TEXT_CATALOG`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextLocation {
+    /// A line of a message queued by its index in `VRAM_AddrTable`.
+    Message { index: u8, line: usize },
+    /// A line of an entry of `GameText`, by index into `GameTextOffsets`.
+    GameText { entry: u8, line: usize },
+    /// `LuigiName`, written over the player's name for the second player.
+    LuigiName,
+    /// A line of the title screen, see `TitleScreen`.
+    TitleScreen { line: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextInfo {
+    pub name: &'static str,
+    pub location: TextLocation,
+}
+
+const fn game_text(name: &'static str, entry: u8, line: usize) -> TextInfo {
+    TextInfo { name, location: TextLocation::GameText { entry, line } }
+}
+
+const fn message(name: &'static str, index: u8, line: usize) -> TextInfo {
+    TextInfo { name, location: TextLocation::Message { index, line } }
+}
+
+const fn title_screen(name: &'static str, line: usize) -> TextInfo {
+    TextInfo { name, location: TextLocation::TitleScreen { line } }
+}
+
+/**
+ * Every string of the game.
+ *
+ * Lines count the VRAM updates holding text, skipping blanks, digits and
+ * attribute updates. Two player games show the player's name above
+ * "TIME UP" and "GAME OVER", these are separate copies of the name. The
+ * title screen's logo is drawn with tiles of its own, so isn't text.
+ */
+pub const TEXT_CATALOG: &[TextInfo] = &[
+    game_text("player-name", 0, 0),
+    game_text("world-time", 0, 1),
+    game_text("intermission-world", 2, 0),
+    game_text("time-up-name", 4, 0),
+    game_text("time-up", 5, 0),
+    game_text("game-over-name", 6, 0),
+    game_text("game-over", 7, 0),
+    game_text("warp-zone", 8, 0),
+    TextInfo { name: "luigi-name", location: TextLocation::LuigiName },
+    message("mario-thanks", 12, 0),
+    message("luigi-thanks", 13, 0),
+    message("retainer-1", 14, 0),
+    message("retainer-2", 14, 1),
+    message("princess-saved-1", 15, 0),
+    message("princess-saved-2", 16, 0),
+    message("world-select-1", 17, 0),
+    message("world-select-2", 18, 0),
+    title_screen("copyright", 0),
+    title_screen("one-player-game", 1),
+    title_screen("two-player-game", 2),
+    title_screen("top-score", 3),
+];
+
+/// A string of the catalog, as found in the rom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomText {
+    pub name: &'static str,
+    /// CPU address of the first tile, its PPU address for the title screen
+    /// as that is kept in CHR-ROM.
+    pub addr: u16,
+    /// Where the text is drawn, `None` when it's copied over other text.
+    pub ppu_addr: Option<u16>,
+    pub tiles: Vec<u8>,
+}
+
+impl RomText {
+    pub fn text(&self) -> String {
+        decode_text(&self.tiles)
+    }
+}
+
+/// Whether a VRAM update writes text to a nametable.
+fn is_text_update(update: &VramUpdate) -> bool {
+    !update.repeat
+        && (0x2000..0x3000).contains(&update.ppu_addr)
+        && update.data.iter().any(|&tile| {
+            tile_char(tile).is_some_and(|c| c.is_ascii_uppercase())
+        })
+}
+
+/**
+ * Find a line of text in a VRAM buffer, a list of updates ending with
+ * `terminator`. Returns the offset of the line's data and its update.
+ */
+fn find_text_line(
+    bytes: &[u8],
+    terminator: u8,
+    line: usize,
+) -> Result<Option<(usize, VramUpdate)>, Error> {
+    let mut offset = 0;
+    let mut lines = 0;
+    while bytes.get(offset).is_some_and(|&byte| byte != terminator) {
+        let update = VramUpdate::try_from_bytes(&bytes[offset..])
+            .map_err(|error| error.offset_by(offset))?;
+        if is_text_update(&update) {
+            if lines == line {
+                return Ok(Some((offset + 3, update)));
+            }
+            lines += 1;
+        }
+        offset += 3 + update.data.len();
+    }
+    Ok(None)
+}
+
+impl Rom {
+    /// Find `GameTextOffsets` and `GameText` by the code reading them.
+    fn game_text_tables(&self) -> Result<(u16, u16)> {
+        let prg = self.prg();
+        let pos = find_pattern(prg, &WRITE_GAME_TEXT_CODE)
+            .next()
+            .ok_or_else(|| anyhow!("no WriteGameText found in prg-rom"))?;
+        let operand =
+            |pos: usize| u16::from_le_bytes([prg[pos + 1], prg[pos + 2]]);
+        Ok((operand(pos), operand(pos + 5)))
+    }
+
+    /// Find a string of the catalog, see `TEXT_CATALOG`.
+    pub fn text(&self, info: &TextInfo) -> Result<RomText> {
+        let not_found = || anyhow!("no text for {} in the rom", info.name);
+        let (addr, ppu_addr, tiles) = match info.location {
+            TextLocation::Message { index, line } => {
+                let low =
+                    self.read_cpu_u8(VRAM_ADDR_TABLE_LOW + index as u16)?;
+                let high =
+                    self.read_cpu_u8(VRAM_ADDR_TABLE_HIGH + index as u16)?;
+                let buffer = u16::from_le_bytes([low, high]);
+                let (offset, update) =
+                    find_text_line(self.cpu_slice(buffer)?, 0x00, line)?
+                        .ok_or_else(not_found)?;
+                let addr = buffer.wrapping_add(offset as u16);
+                (addr, Some(update.ppu_addr), update.data)
+            }
+            TextLocation::GameText { entry, line } => {
+                let (offsets, game_text) = self.game_text_tables()?;
+                let start = self.read_cpu_u8(offsets + entry as u16)?;
+                let buffer = game_text.wrapping_add(start as u16);
+                let (offset, update) =
+                    find_text_line(self.cpu_slice(buffer)?, 0xff, line)?
+                        .ok_or_else(not_found)?;
+                let addr = buffer.wrapping_add(offset as u16);
+                (addr, Some(update.ppu_addr), update.data)
+            }
+            TextLocation::LuigiName => {
+                let prg = self.prg();
+                let pos = find_pattern(prg, &WRITE_LUIGI_NAME_CODE)
+                    .next()
+                    .ok_or_else(not_found)?;
+                let addr = u16::from_le_bytes([prg[pos + 1], prg[pos + 2]]);
+                let tiles = self.cpu_slice(addr)?;
+                ensure!(
+                    tiles.len() >= LUIGI_NAME_LEN,
+                    "{} truncated",
+                    info.name
+                );
+                (addr, None, tiles[..LUIGI_NAME_LEN].to_vec())
+            }
+            TextLocation::TitleScreen { line } => {
+                let chr =
+                    self.chr().get(TITLE_SCREEN_CHR_ADDR..).unwrap_or_default();
+                let buffer = &chr[..TITLE_SCREEN_SIZE.min(chr.len())];
+                let (offset, update) = find_text_line(buffer, 0x00, line)?
+                    .ok_or_else(not_found)?;
+                let addr = (TITLE_SCREEN_CHR_ADDR + offset) as u16;
+                (addr, Some(update.ppu_addr), update.data)
+            }
+        };
+        Ok(RomText { name: info.name, addr, ppu_addr, tiles })
+    }
+
+    /// Find every string of the catalog.
+    pub fn text_catalog(&self) -> Result<Vec<RomText>> {
+        TEXT_CATALOG.iter().map(|info| self.text(info)).collect()
+    }
+
+    /**
+     * Replace a string of the catalog, see `encode_text`.
+     *
+     * The text is written in place, so it can't be longer than the string
+     * it replaces. Shorter text is padded with spaces, so nothing is left
+     * of the old string on screen.
+     */
+    pub fn set_text(&mut self, info: &TextInfo, text: &str) -> Result<()> {
+        let old = self.text(info)?;
+        let mut tiles = encode_text(text)?;
+        ensure!(
+            tiles.len() <= old.tiles.len(),
+            "text for {} is {} characters, only {} fit",
+            info.name,
+            tiles.len(),
+            old.tiles.len()
+        );
+        tiles.resize(old.tiles.len(), BLANK_TILE);
+        match info.location {
+            TextLocation::TitleScreen { .. } => {
+                let offset = self.header.chr_offset() + old.addr as usize;
+                self.rom_data[offset..offset + tiles.len()]
+                    .copy_from_slice(&tiles);
+                Ok(())
+            }
+            _ => self.write_cpu(old.addr, &tiles),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{write_chr, zero_rom};
+
+    #[test]
+    fn test_text_codec() {
+        let tiles = encode_text("WORLD 1-1!").unwrap();
+        assert_eq!(
+            tiles,
+            [0x20, 0x18, 0x1b, 0x15, 0x0d, 0x24, 0x01, 0x28, 0x01, 0x2b]
+        );
+        assert_eq!(decode_text(&tiles), "WORLD 1-1!");
+
+        // tiles that aren't characters are escaped
+        assert_eq!(decode_text(&[0x2e, 0x29, 0x00]), "{2e}x0");
+        assert_eq!(encode_text("{2e}x0").unwrap(), [0x2e, 0x29, 0x00]);
+        assert!(encode_text("world").is_err());
+        assert!(encode_text("{2e").is_err());
+        assert!(encode_text("{zz}").is_err());
+    }
+
+    #[test]
+    fn test_text_catalog() {
+        let mut rom = zero_rom();
+        let mut write =
+            |addr: u16, bytes: &[u8]| rom.write_cpu(addr, bytes).unwrap();
+        let text = |text| encode_text(text).unwrap();
+
+        write(
+            0xc000,
+            &[
+                0xbe, 0x00, 0xd0, 0xa0, 0x00, 0xbd, 0x10, 0xd0, 0xc9, 0xff,
+                0xf0, 0x00, 0x99, 0x01, 0x03,
+            ],
+        );
+        write(0xc020, &[0xb9, 0x00, 0xd2, 0x99, 0x04, 0x03]);
+
+        // every entry of GameText is the status bar, a name, then blanks
+        write(0xd000, &[0x00; 10]);
+        let mut status_bar = vec![0x20, 0x43, 0x05];
+        status_bar.extend(text("MARIO"));
+        status_bar.extend([0x20, 0x52, 0x4b, BLANK_TILE]);
+        status_bar.extend([0x20, 0x52, 0x0b]);
+        status_bar.extend(text("WORLD  TIME"));
+        status_bar.push(0xff);
+        write(0xd010, &status_bar);
+        write(0xd200, &text("LUIGI"));
+
+        // every message is two lines
+        for index in 0..VRAM_ADDR_TABLE_LEN {
+            write(VRAM_ADDR_TABLE_LOW + index as u16, &[0x00]);
+            write(VRAM_ADDR_TABLE_HIGH + index as u16, &[0xd1]);
+        }
+        let mut message = vec![0x25, 0xc5, 0x06];
+        message.extend(text("BUT OU"));
+        message.extend([0x26, 0x05, 0x07]);
+        message.extend(text("CASTLE!"));
+        message.push(0x00);
+        write(0xd100, &message);
+
+        // the title screen, with a run of blanks that isn't text
+        let mut title_screen = vec![0x20, 0x00, 0x44, BLANK_TILE];
+        for (ppu_addr, line) in [
+            (0x2259, "©1985 NINTENDO"),
+            (0x228b, "1 PLAYER GAME"),
+            (0x22cb, "2 PLAYER GAME"),
+            (0x230c, "TOP-"),
+        ] {
+            title_screen.extend(u16::to_be_bytes(ppu_addr));
+            title_screen.push(line.chars().count() as u8);
+            title_screen.extend(text(line));
+        }
+        title_screen.push(0x00);
+        write_chr(&mut rom, TITLE_SCREEN_CHR_ADDR, &title_screen);
+
+        let catalog = rom.text_catalog().unwrap();
+        assert_eq!(catalog.len(), TEXT_CATALOG.len());
+        let world_time = &catalog[1];
+        assert_eq!(world_time.text(), "WORLD  TIME");
+        assert_eq!(world_time.addr, 0xd01f);
+        assert_eq!(world_time.ppu_addr, Some(0x2052));
+        assert_eq!(catalog[8].text(), "LUIGI");
+        assert_eq!(catalog[8].ppu_addr, None);
+        assert_eq!(catalog[12].text(), "CASTLE!");
+
+        rom.set_text(&TEXT_CATALOG[1], "WORLD TIME").unwrap();
+        assert_eq!(rom.text(&TEXT_CATALOG[1]).unwrap().text(), "WORLD TIME ");
+        assert!(rom.set_text(&TEXT_CATALOG[0], "PRINCESS").is_err());
+        assert!(rom.set_text(&TEXT_CATALOG[0], "mario").is_err());
+
+        let two_players = &catalog[19];
+        assert_eq!(two_players.text(), "2 PLAYER GAME");
+        assert_eq!(two_players.addr, 0x1ee8);
+        assert_eq!(two_players.ppu_addr, Some(0x22cb));
+        rom.set_text(&TEXT_CATALOG[19], "2 PLAYERS").unwrap();
+        let title_screen = rom.title_screen().unwrap();
+        let tiles: Vec<u8> =
+            (11..24).filter_map(|x| title_screen.tile(x, 22)).collect();
+        assert_eq!(decode_text(&tiles), "2 PLAYERS    ");
+    }
+}
@@ -0,0 +1,17 @@
+/*!
+ * A synthetic rom for unit tests, with the code and tables each test needs
+ * written into it, as the game itself can't be shipped.
+ */
+
+use crate::*;
+
+/// A rom of zeros, laid out like the game.
+pub(crate) fn zero_rom() -> Rom {
+    Rom::new_unverified(vec![0; SMB1_PRG_SIZE + SMB1_CHR_SIZE]).unwrap()
+}
+
+/// Write bytes to CHR-ROM, by their address in the PPU's pattern tables.
+pub(crate) fn write_chr(rom: &mut Rom, addr: usize, bytes: &[u8]) {
+    let offset = rom.header.chr_offset() + addr;
+    rom.rom_data[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
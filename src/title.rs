@@ -0,0 +1,328 @@
+/*!
+ * The title screen, drawn over 1-1 from a VRAM buffer.
+ *
+ * The buffer isn't in PRG-ROM: it's kept at the end of CHR-ROM, after the
+ * background tiles, and copied into RAM through the PPU before it's drawn.
+ * Runs of a tile are stored as repeated writes, which keeps it small.
+ */
+
+use anyhow::{ensure, Result};
+use image::RgbaImage;
+
+use crate::*;
+
+/// Where the title screen's VRAM buffer is, in the PPU's address space.
+pub const TITLE_SCREEN_CHR_ADDR: usize = 0x1ec0;
+
+/// Bytes the game copies from `TITLE_SCREEN_CHR_ADDR`.
+pub const TITLE_SCREEN_SIZE: usize = 0x13a;
+
+pub const NAMETABLE_COLUMNS: usize = 32;
+pub const NAMETABLE_ROWS: usize = 30;
+
+/// Start of the first nametable in the PPU's address space.
+pub const NAMETABLE_PPU_ADDR: u16 = 0x2000;
+
+/// Bytes of a nametable: a tile per cell, then the attribute table.
+pub const NAMETABLE_SIZE: usize = 0x400;
+
+/// Offset of the attribute table, a palette for each 2x2 tile square.
+const ATTRIBUTE_OFFSET: usize = NAMETABLE_COLUMNS * NAMETABLE_ROWS;
+
+/// Longest write a VRAM update can hold.
+const MAX_UPDATE_LEN: usize = 0b00111111;
+
+/**
+ * The first nametable as the title screen leaves it.
+ *
+ * Bytes the title screen doesn't write are `None`, there the level behind
+ * it shows through. Writes anywhere else (such as to palettes) are kept as
+ * they are.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TitleScreen {
+    /// Tiles, then attribute bytes, from `NAMETABLE_PPU_ADDR` on.
+    pub nametable: Vec<Option<u8>>,
+    pub other_updates: Vec<VramUpdate>,
+}
+
+impl TitleScreen {
+    /// Decode the VRAM buffer, up to its 0 terminator.
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut nametable = vec![None; NAMETABLE_SIZE];
+        let mut other_updates = vec![];
+
+        let mut offset = 0;
+        loop {
+            match bytes.get(offset) {
+                Some(0x00) => break,
+                Some(_) => {}
+                None => {
+                    return Err(Error::MissingTerminator {
+                        what: "title screen",
+                        offset: 0,
+                        terminator: 0x00,
+                    })
+                }
+            }
+            let update = VramUpdate::try_from_bytes(&bytes[offset..])
+                .map_err(|error| error.offset_by(offset))?;
+            offset += 3 + update.data.len();
+
+            let step = if update.vertical { NAMETABLE_COLUMNS } else { 1 };
+            let written = update.bytes();
+            let start =
+                update.ppu_addr.wrapping_sub(NAMETABLE_PPU_ADDR) as usize;
+            let last = start + written.len().saturating_sub(1) * step;
+            if last >= NAMETABLE_SIZE {
+                other_updates.push(update);
+                continue;
+            }
+            for (idx, byte) in written.into_iter().enumerate() {
+                nametable[start + idx * step] = Some(byte);
+            }
+        }
+
+        Ok(Self { nametable, other_updates })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::try_from_bytes(bytes).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /**
+     * Encode the VRAM buffer, with its 0 terminator.
+     *
+     * Each run of written bytes is split into the fewest bytes of
+     * horizontal updates, repeating a byte where that's shorter.
+     */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut updates = vec![];
+        let mut start = 0;
+        while start < NAMETABLE_SIZE {
+            if self.nametable[start].is_none() {
+                start += 1;
+                continue;
+            }
+            let len = self.nametable[start..]
+                .iter()
+                .take_while(|byte| byte.is_some())
+                .count();
+            let run: Vec<u8> = self.nametable[start..start + len]
+                .iter()
+                .flatten()
+                .copied()
+                .collect();
+            updates.extend(encode_run(start, &run));
+            start += len;
+        }
+        updates.extend(self.other_updates.iter().cloned());
+
+        let mut bytes: Vec<u8> =
+            updates.iter().flat_map(VramUpdate::to_bytes).collect();
+        bytes.push(0x00);
+        bytes
+    }
+
+    /// Get the tile at a cell, if the title screen writes it.
+    pub fn tile(&self, x: usize, y: usize) -> Option<u8> {
+        self.nametable[y * NAMETABLE_COLUMNS + x]
+    }
+
+    pub fn set_tile(&mut self, x: usize, y: usize, tile: Option<u8>) {
+        self.nametable[y * NAMETABLE_COLUMNS + x] = tile;
+    }
+
+    /// Get the background palette of a cell, from the attribute table.
+    pub fn palette(&self, x: usize, y: usize) -> Option<usize> {
+        let attribute = self.nametable[ATTRIBUTE_OFFSET + y / 4 * 8 + x / 4]?;
+        let shift = (y % 4 / 2) * 4 + (x % 4 / 2) * 2;
+        Some((attribute >> shift) as usize & 0b11)
+    }
+}
+
+/**
+ * Split a run of bytes written from `start` into the fewest bytes of
+ * updates: a literal costs its header and data, a repeat 4 bytes.
+ */
+fn encode_run(start: usize, run: &[u8]) -> Vec<VramUpdate> {
+    // cost and length of the best first update, from each position
+    let mut best = vec![(0, 0, false); run.len() + 1];
+    for pos in (0..run.len()).rev() {
+        let left = (run.len() - pos).min(MAX_UPDATE_LEN);
+        let same = run[pos..pos + left]
+            .iter()
+            .take_while(|&&byte| byte == run[pos])
+            .count();
+        let literals =
+            (1..=left).map(|len| (3 + len + best[pos + len].0, len, false));
+        let repeats = (2..=same).map(|len| (4 + best[pos + len].0, len, true));
+        best[pos] =
+            literals.chain(repeats).min_by_key(|&(cost, ..)| cost).unwrap();
+    }
+
+    let mut updates = vec![];
+    let mut pos = 0;
+    while pos < run.len() {
+        let (_, len, repeat) = best[pos];
+        let data = match repeat {
+            true => vec![run[pos]],
+            false => run[pos..pos + len].to_vec(),
+        };
+        updates.push(VramUpdate {
+            ppu_addr: NAMETABLE_PPU_ADDR + (start + pos) as u16,
+            vertical: false,
+            repeat,
+            len: len as u8,
+            data,
+        });
+        pos += len;
+    }
+    updates
+}
+
+impl Rom {
+    fn title_screen_offset(&self) -> usize {
+        self.header.chr_offset() + TITLE_SCREEN_CHR_ADDR
+    }
+
+    /// Decode the title screen, see `TitleScreen`.
+    pub fn title_screen(&self) -> Result<TitleScreen> {
+        let bytes = self.chr().get(TITLE_SCREEN_CHR_ADDR..).unwrap_or_default();
+        let bytes = &bytes[..TITLE_SCREEN_SIZE.min(bytes.len())];
+        Ok(TitleScreen::try_from_bytes(bytes)
+            .map_err(|error| error.offset_by(self.title_screen_offset()))?)
+    }
+
+    /**
+     * Replace the title screen.
+     *
+     * It must encode to no more than the `TITLE_SCREEN_SIZE` bytes the game
+     * copies, the rest of which is cleared.
+     */
+    pub fn set_title_screen(
+        &mut self,
+        title_screen: &TitleScreen,
+    ) -> Result<()> {
+        let mut bytes = title_screen.to_bytes();
+        ensure!(
+            bytes.len() <= TITLE_SCREEN_SIZE,
+            "title screen needs {} bytes, only {} fit",
+            bytes.len(),
+            TITLE_SCREEN_SIZE
+        );
+        bytes.resize(TITLE_SCREEN_SIZE, 0x00);
+        let offset = self.title_screen_offset();
+        let chr = self.chr().get(TITLE_SCREEN_CHR_ADDR..).unwrap_or_default();
+        Error::check_len("title screen", chr, TITLE_SCREEN_SIZE)
+            .map_err(|error| error.offset_by(offset))?;
+        self.rom_data[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    /**
+     * Draw the title screen with the background tiles and palettes of 1-1,
+     * which the game shows it over. Cells it doesn't write are left in the
+     * background color.
+     */
+    pub fn render_title_screen(&self) -> Result<RgbaImage> {
+        let title_screen = self.title_screen()?;
+        let tiles = self.pattern_table(1)?;
+        let palettes =
+            self.area_palettes(&self.level_area(&RomLevel::W1_1)?)?;
+        let nes_palette = NesPalette::default();
+
+        let mut image = RgbaImage::new(
+            (NAMETABLE_COLUMNS * TILE_PIXELS) as u32,
+            (NAMETABLE_ROWS * TILE_PIXELS) as u32,
+        );
+        for y in 0..NAMETABLE_ROWS {
+            for x in 0..NAMETABLE_COLUMNS {
+                let tile = title_screen.tile(x, y).unwrap_or(BLANK_TILE);
+                let palette = title_screen.palette(x, y).unwrap_or(0);
+                let colors =
+                    nes_palette.tile_palette(&palettes.background(palette));
+                tiles.tiles[tile as usize].draw(
+                    &mut image,
+                    (x * TILE_PIXELS) as u32,
+                    (y * TILE_PIXELS) as u32,
+                    &colors,
+                );
+            }
+        }
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{write_chr, zero_rom};
+
+    const BYTES: [u8; 22] = [
+        0x20, 0x00, 0x44, 0x24, // 4 blanks
+        0x20, 0x21, 0x03, 0x01, 0x02, 0x03, // 3 tiles on the next row
+        0x23, 0xc0, 0x01, 0x55, // their palettes
+        0x3f, 0x00, 0x01, 0x0f, // a palette write
+        0x00, 0xff, 0xff, 0xff,
+    ];
+
+    #[test]
+    fn test_title_screen() {
+        let title_screen = TitleScreen::from_bytes(&BYTES);
+        assert_eq!(title_screen.tile(3, 0), Some(0x24));
+        assert_eq!(title_screen.tile(4, 0), None);
+        assert_eq!(title_screen.tile(2, 1), Some(0x02));
+        assert_eq!(title_screen.palette(2, 1), Some(1));
+        assert_eq!(title_screen.palette(8, 1), None);
+        assert_eq!(title_screen.other_updates.len(), 1);
+        assert_eq!(title_screen.to_bytes(), BYTES[..19]);
+
+        // long runs are split, repeats only where they save bytes
+        let mut edited = title_screen.clone();
+        for x in 0..NAMETABLE_COLUMNS {
+            edited.set_tile(x, 2, Some(0x24));
+        }
+        edited.set_tile(0, 3, Some(0x01));
+        edited.set_tile(1, 3, Some(0x01));
+        let bytes = edited.to_bytes();
+        assert_eq!(TitleScreen::from_bytes(&bytes), edited);
+        assert!(bytes
+            .windows(4)
+            .any(|bytes| bytes == [0x20, 0x40, 0x60, 0x24]));
+
+        assert!(TitleScreen::try_from_bytes(&BYTES[..18]).is_err());
+    }
+
+    #[test]
+    fn test_set_title_screen() {
+        let mut rom = zero_rom();
+        write_chr(&mut rom, TITLE_SCREEN_CHR_ADDR, &BYTES);
+
+        let mut title_screen = rom.title_screen().unwrap();
+        title_screen.set_tile(0, 29, Some(0x0a));
+        rom.set_title_screen(&title_screen).unwrap();
+        assert_eq!(rom.title_screen().unwrap(), title_screen);
+
+        // every other tile is a different update
+        for y in (0..NAMETABLE_ROWS).step_by(2) {
+            title_screen.set_tile(0, y, Some(0x0a));
+        }
+        assert!(rom.set_title_screen(&title_screen).is_ok());
+        for x in (0..NAMETABLE_COLUMNS).step_by(2) {
+            for y in 10..20 {
+                title_screen.set_tile(x, y, Some(0x0a));
+            }
+        }
+        assert!(rom.set_title_screen(&title_screen).is_err());
+
+        // a rom without CHR-ROM has no title screen
+        let mut rom_data = SMB1_INES_HEADER.to_vec();
+        rom_data[5] = 0;
+        rom_data.resize(INES_HEADER_SIZE + SMB1_PRG_SIZE, 0);
+        let mut rom = Rom::new_unverified(rom_data).unwrap();
+        assert!(rom.title_screen().is_err());
+        let title_screen = TitleScreen::from_bytes(&BYTES);
+        assert!(rom.set_title_screen(&title_screen).is_err());
+    }
+}
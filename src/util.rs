@@ -20,7 +20,7 @@
  * Is functionally equivalent to:
  *
  * ```
- * #[derive(Debug)]
+ * #[derive(Debug, Clone, Copy)]
  * enum MyCoolEnum {
  *     Foo,
  *     Bar,
@@ -28,12 +28,22 @@
  * }
  *
  * impl MyCoolEnum {
- *     pub fn new(val: u32) -> Self {
+ *     pub fn try_new(val: u32) -> Result<Self, smb1_tools::Error> {
  *         match val {
- *             12 => Self::Foo,
- *             13 => Self::Bar,
- *             57 => Self::Baz,
- *             _ => panic!("invalid {} val: {:?}", "MyCoolEnum", val),
+ *             12 => Ok(Self::Foo),
+ *             13 => Ok(Self::Bar),
+ *             57 => Ok(Self::Baz),
+ *             _ => Err(smb1_tools::Error::InvalidValue {
+ *                 what: "MyCoolEnum",
+ *                 value: format!("{:?}", val),
+ *             }),
+ *         }
+ *     }
+ *
+ *     pub fn new(val: u32) -> Self {
+ *         match Self::try_new(val) {
+ *             Ok(val) => val,
+ *             Err(error) => panic!("{}", error),
  *         }
  *     }
  *
@@ -53,11 +63,18 @@
  * }
  * impl Eq for MyCoolEnum {}
  * ```
+ *
+ * With the `serde` feature the enum also derives `Serialize` and
+ * `Deserialize`, using the variant names.
  */
 #[macro_export]
 macro_rules! enum_mapped {
     ($v:vis $name:ident ($type:ty) { $($val:expr => $variant:ident,)* } ) => {
-        #[derive(Debug)]
+        #[derive(Debug, Clone, Copy)]
+        #[cfg_attr(
+            feature = "serde",
+            derive(serde::Serialize, serde::Deserialize)
+        )]
         $v enum $name {
             $(
                 $variant,
@@ -66,12 +83,23 @@ macro_rules! enum_mapped {
 
         impl $name {
             #[allow(dead_code)]
-            pub fn new(val: $type) -> Self {
+            pub fn try_new(val: $type) -> Result<Self, $crate::Error> {
                 match val {
                     $(
-                        $val => Self::$variant,
+                        $val => Ok(Self::$variant),
                     )*
-                    _ => panic!("invalid {} val: {:?}", stringify!($name), val),
+                    _ => Err($crate::Error::InvalidValue {
+                        what: stringify!($name),
+                        value: format!("{:?}", val),
+                    }),
+                }
+            }
+
+            #[allow(dead_code)]
+            pub fn new(val: $type) -> Self {
+                match Self::try_new(val) {
+                    Ok(val) => val,
+                    Err(error) => panic!("{}", error),
                 }
             }
 
@@ -95,10 +123,24 @@ macro_rules! enum_mapped {
 }
 pub(crate) use enum_mapped;
 
+/**
+ * Find every position of `pattern` in `bytes`, where `None` matches any
+ * byte. Used to find the game's code and tables by their contents.
+ */
+pub fn find_pattern<'a>(
+    bytes: &'a [u8],
+    pattern: &'a [Option<u8>],
+) -> impl Iterator<Item = usize> + 'a {
+    bytes.windows(pattern.len()).enumerate().filter_map(move |(pos, window)| {
+        let found = window.iter().zip(pattern).all(|(byte, expected)| {
+            expected.is_none() || *expected == Some(*byte)
+        });
+        found.then_some(pos)
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     enum_mapped!(
         MyNumberEnum (u32) {
             47 => Foo,
@@ -131,6 +173,18 @@ mod tests {
         let _thing = MyNumberEnum::new(100);
     }
 
+    #[test]
+    fn test_num_4() {
+        assert_eq!(MyNumberEnum::try_new(78), Ok(MyNumberEnum::Bar));
+        assert_eq!(
+            MyNumberEnum::try_new(100),
+            Err(crate::Error::InvalidValue {
+                what: "MyNumberEnum",
+                value: "100".into()
+            })
+        );
+    }
+
     #[test]
     fn test_str_1() {
         assert_eq!(MyStrEnum::Foo.value(), "foo");
@@ -148,4 +202,12 @@ mod tests {
     fn test_str_3() {
         let _thing = MyStrEnum::new("baz");
     }
-                  }
+
+    #[test]
+    fn test_find_pattern() {
+        let bytes = [0xa9, 0x01, 0x85, 0x10, 0xa9, 0x02, 0x85, 0x11];
+        let pattern = [Some(0xa9), None, Some(0x85)];
+        let found: Vec<_> = super::find_pattern(&bytes, &pattern).collect();
+        assert_eq!(found, [0, 4]);
+    }
+}
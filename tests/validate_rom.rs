@@ -41,6 +41,45 @@ const LEVEL_INFORMATION: &[(RomLevel, usize, usize, usize)] = &[
     (RomLevel::W8_4, 55, 18, 7),
 ];
 
+/// [(RomLevel, header_offset, object_offset, enemy_offset)]
+///
+/// File offsets of each level's data in the original rom, used to check the
+/// addresses read from the rom's own area pointer tables.
+const LEVEL_OFFSETS: &[(RomLevel, usize, usize, usize)] = &[
+    (RomLevel::W1_1, 0x269e, 0x26a0, 0x1f11),
+    (RomLevel::W1_2, 0x2c45, 0x2c47, 0x20e8),
+    (RomLevel::W1_3, 0x2703, 0x2705, 0x1f2f),
+    (RomLevel::W1_4, 0x21bf, 0x21c1, 0x1d80),
+    (RomLevel::W2_1, 0x27dd, 0x27df, 0x1f61),
+    (RomLevel::W2_2, 0x2e55, 0x2e57, 0x2181),
+    (RomLevel::W2_3, 0x2758, 0x275a, 0x1f4c),
+    (RomLevel::W2_4, 0x229f, 0x22a1, 0x1dc0),
+    (RomLevel::W3_1, 0x2629, 0x262b, 0x1ee0),
+    (RomLevel::W3_2, 0x2c12, 0x2c14, 0x20c3),
+    (RomLevel::W3_3, 0x247b, 0x247d, 0x1e69),
+    (RomLevel::W3_4, 0x2312, 0x2314, 0x1def),
+    (RomLevel::W4_1, 0x2547, 0x2549, 0x1eab),
+    (RomLevel::W4_2, 0x2ce8, 0x2cea, 0x2115),
+    (RomLevel::W4_3, 0x289f, 0x28a1, 0x1fb9),
+    (RomLevel::W4_4, 0x2220, 0x2222, 0x1da7),
+    (RomLevel::W5_1, 0x284b, 0x284d, 0x1f8c),
+    (RomLevel::W5_2, 0x2aa2, 0x2aa4, 0x2045),
+    (RomLevel::W5_3, 0x2703, 0x2705, 0x1f2f),
+    (RomLevel::W5_4, 0x229f, 0x22a1, 0x1dc0),
+    (RomLevel::W6_1, 0x296b, 0x296d, 0x2001),
+    (RomLevel::W6_2, 0x259a, 0x259c, 0x1eb9),
+    (RomLevel::W6_3, 0x2906, 0x2908, 0x1fde),
+    (RomLevel::W6_4, 0x21bf, 0x21c1, 0x1d80),
+    (RomLevel::W7_1, 0x2b8e, 0x2b90, 0x209e),
+    (RomLevel::W7_2, 0x2e55, 0x2e57, 0x2181),
+    (RomLevel::W7_3, 0x2758, 0x275a, 0x1f4c),
+    (RomLevel::W7_4, 0x237f, 0x2381, 0x1e1a),
+    (RomLevel::W8_1, 0x2a0f, 0x2a11, 0x200b),
+    (RomLevel::W8_2, 0x2b15, 0x2b17, 0x2070),
+    (RomLevel::W8_3, 0x24de, 0x24e0, 0x1e8e),
+    (RomLevel::W8_4, 0x240a, 0x240c, 0x1e2f),
+];
+
 #[test]
 fn test_rom_valid() -> Result<()> {
     let _ = Rom::new(ROM_DATA.into())?;
@@ -48,6 +87,72 @@ fn test_rom_valid() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_rom_profiles() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+    assert_eq!(rom.profile(), Some(&ROM_PROFILES[0]));
+    assert_eq!(rom.layout(), RomLayout::INes);
+    assert!(rom.failed_checks().is_empty());
+
+    // headerless and dirty header dumps are rebuilt with a clean header
+    let rom = Rom::new(ROM_DATA[0x10..].into())?;
+    assert_eq!(rom.layout(), RomLayout::Headerless);
+    assert_eq!(rom.rom_data, ROM_DATA);
+
+    let mut dirty = ROM_DATA.to_vec();
+    dirty[7..16].copy_from_slice(b"DiskDude!");
+    let rom = Rom::new(dirty)?;
+    assert_eq!(rom.layout(), RomLayout::INes);
+    assert_eq!(rom.rom_data, ROM_DATA);
+
+    // the game in the second half of a 64 KiB prg, 16 KiB chr multicart
+    let mut multicart = ROM_DATA[..0x10].to_vec();
+    multicart[4] = 4;
+    multicart[5] = 2;
+    multicart[6] = 0x21;
+    multicart[7] = 0x40;
+    multicart.extend(vec![0xff; 0x8000]);
+    multicart.extend(&ROM_DATA[0x10..0x8010]);
+    multicart.extend(vec![0xff; 0x2000]);
+    multicart.extend(&ROM_DATA[0x8010..]);
+    let rom = Rom::new(multicart)?;
+    assert_eq!(rom.layout(), RomLayout::Multicart);
+    assert_eq!(rom.rom_data, ROM_DATA);
+
+    Ok(())
+}
+
+#[test]
+fn test_rom_unverified() -> Result<()> {
+    let mut modified = ROM_DATA.to_vec();
+    modified[0x2000] ^= 0xff;
+    assert!(Rom::new(modified.clone()).is_err());
+
+    let rom = Rom::new_unverified(modified)?;
+    assert_eq!(rom.profile(), None);
+    let checks: Vec<_> = rom
+        .failed_checks()
+        .iter()
+        .map(|error| match error {
+            Error::ChecksumMismatch { what, offset, len, .. } => {
+                (*what, *offset, *len)
+            }
+            error => panic!("unexpected failed check: {}", error),
+        })
+        .collect();
+    assert_eq!(
+        checks,
+        [
+            ("crc32", 0x10, 0xa000),
+            ("md5", 0x10, 0xa000),
+            ("sha1", 0x10, 0xa000)
+        ]
+    );
+    rom.get_level(&RomLevel::W1_1)?;
+
+    Ok(())
+}
+
 #[test]
 fn test_level_data_valid() -> Result<()> {
     let rom = Rom::new(ROM_DATA.into())?;
@@ -55,7 +160,7 @@ fn test_level_data_valid() -> Result<()> {
     // loop each level
     for (name, num_objects, num_enemies, num_pipe_pointers) in LEVEL_INFORMATION
     {
-        let level = rom.get_level(name);
+        let level = rom.get_level(name)?;
 
         // ensure number of objects in level data is accurate
         let objects_len = level.object_data.objects.len();
@@ -83,4 +188,533 @@ fn test_level_data_valid() -> Result<()> {
     }
 
     Ok(())
-  }
+}
+
+#[test]
+fn test_level_offsets_valid() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+
+    for (name, header_offset, object_offset, enemy_offset) in LEVEL_OFFSETS {
+        let addresses = rom.level_addresses(name)?;
+        let offsets = (
+            rom.cpu_to_offset(addresses.header)?,
+            rom.cpu_to_offset(addresses.objects)?,
+            rom.cpu_to_offset(addresses.enemies)?,
+        );
+        assert_eq!(
+            offsets,
+            (*header_offset, *object_offset, *enemy_offset),
+            "level {:?} wrong offsets",
+            name
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_rom_header_valid() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+
+    // smb1 is NROM-256: 2x 16K PRG, 1x 8K CHR, vertical mirroring
+    assert_eq!(rom.header.prg_rom_banks, 2);
+    assert_eq!(rom.header.chr_rom_banks, 1);
+    assert_eq!(rom.header.mapper, 0);
+    assert_eq!(rom.header.mirroring, Mirroring::Vertical);
+    assert_eq!(rom.prg().len(), 0x8000);
+    assert_eq!(rom.chr().len(), 0x2000);
+
+    // reset vector points back into prg-rom
+    assert_eq!(rom.cpu_to_offset(0xfffc)?, 0x800c);
+    let reset = rom.read_cpu_u16(0xfffc)?;
+    assert!(reset >= 0x8000, "reset vector invalid: ${:04x}", reset);
+
+    Ok(())
+}
+
+#[test]
+fn test_areas_valid() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+
+    let areas: Vec<Area> = rom.areas()?.collect();
+    let count = |area_type: AreaType| {
+        areas.iter().filter(|area| area.area_type == area_type).count()
+    };
+    assert_eq!(areas.len(), 34);
+    assert_eq!(count(AreaType::Water), 3);
+    assert_eq!(count(AreaType::Ground), 22);
+    assert_eq!(count(AreaType::Underground), 3);
+    assert_eq!(count(AreaType::Castle), 6);
+
+    // every area in the game can be parsed
+    for area in &areas {
+        rom.get_area(area)?;
+    }
+
+    // every level starts in one of those areas
+    for (name, _, _, _) in LEVEL_OFFSETS {
+        let area = rom.level_area(name)?;
+        assert!(areas.contains(&area), "level {:?} area {:?}", name, area);
+    }
+
+    assert_eq!(
+        rom.level_area(&RomLevel::W1_1)?,
+        Area::new(AreaType::Ground, 5)
+    );
+    assert_eq!(
+        rom.level_area(&RomLevel::W1_2)?,
+        Area::new(AreaType::Underground, 0)
+    );
+    assert_eq!(rom.level_area(&RomLevel::W2_2)?, Area::new(AreaType::Water, 1));
+    assert_eq!(
+        rom.level_area(&RomLevel::W1_4)?,
+        Area::new(AreaType::Castle, 0)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_level_data_round_trip() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+    let tables = rom.area_tables()?;
+
+    for area in rom.areas()? {
+        let level = rom.get_area(&area)?;
+        let addresses = tables.area_addresses(&area)?;
+
+        let area_data = level.area_data_to_bytes();
+        let original = rom.cpu_slice(addresses.header)?;
+        assert_eq!(
+            area_data,
+            original[..area_data.len()],
+            "area {:?} area data did not round trip",
+            area
+        );
+
+        let enemy_data = level.enemy_data_to_bytes();
+        let original = rom.cpu_slice(addresses.enemies)?;
+        assert_eq!(
+            enemy_data,
+            original[..enemy_data.len()],
+            "area {:?} enemy data did not round trip",
+            area
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_write_area() -> Result<()> {
+    let original = Rom::new(ROM_DATA.into())?;
+    let mut rom = Rom::new(ROM_DATA.into())?;
+    let free_space = |rom: &Rom| -> Result<usize> {
+        let free = rom.level_data_usage()?.free_space();
+        Ok(free.iter().map(|range| range.len()).sum())
+    };
+    let initial_free = free_space(&rom)?;
+
+    // writing every area back unchanged leaves the rom as it was
+    for area in rom.areas()? {
+        let level = rom.get_area(&area)?;
+        rom.write_area(&area, &level)?;
+    }
+    assert_eq!(rom.rom_data, ROM_DATA);
+
+    // emptying 1-2 frees its objects, the header and terminator remain
+    let emptied = rom.level_area(&RomLevel::W1_2)?;
+    let mut empty_level = rom.get_area(&emptied)?;
+    let emptied_len = empty_level.area_data_to_bytes().len();
+    empty_level.object_data = LevelObjectData::from_bytes(&[0xfd]);
+    rom.write_area(&emptied, &empty_level)?;
+    assert_eq!(free_space(&rom)?, initial_free + emptied_len - 3);
+
+    // a grown 1-1 no longer fits in place and is moved into the gap
+    let area = rom.level_area(&RomLevel::W1_1)?;
+    let mut level = rom.get_area(&area)?;
+    let mut object_bytes = level.object_data.to_bytes();
+    let end = object_bytes.len() - 1;
+    object_bytes.splice(end..end, [0x0f, 0x26, 0x0f, 0x26]);
+    level.object_data = LevelObjectData::from_bytes(&object_bytes);
+    rom.write_area(&area, &level)?;
+
+    let written = rom.get_level(&RomLevel::W1_1)?;
+    assert_eq!(written.area_data_to_bytes(), level.area_data_to_bytes());
+    assert_eq!(written.enemy_data_to_bytes(), level.enemy_data_to_bytes());
+    assert_ne!(
+        rom.area_tables()?.area_addresses(&area)?,
+        original.area_tables()?.area_addresses(&area)?
+    );
+
+    // 1-1's old area data is reclaimed and no other area was touched
+    assert_eq!(free_space(&rom)?, initial_free + emptied_len - 7);
+    for other in rom.areas()?.filter(|&other| other != area && other != emptied)
+    {
+        assert_eq!(
+            rom.get_area(&other)?.area_data_to_bytes(),
+            original.get_area(&other)?.area_data_to_bytes()
+        );
+    }
+
+    // a level larger than the whole bank does not fit
+    let mut object_bytes = level.object_data.to_bytes();
+    let end = object_bytes.len() - 1;
+    object_bytes.splice(end..end, [0x0f, 0x26].repeat(0x2000));
+    level.object_data = LevelObjectData::from_bytes(&object_bytes);
+    assert!(rom.write_area(&area, &level).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_pipe_destinations_valid() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+
+    // 1-1's pipe leads to the underground bonus room
+    let level = rom.get_level(&RomLevel::W1_1)?;
+    let pipe_pointer = level.enemy_data.pipe_pointers.first().unwrap();
+    assert_eq!(
+        rom.pipe_destination(pipe_pointer)?,
+        Area::new(AreaType::Underground, 2)
+    );
+    assert_eq!(pipe_pointer.world, 0);
+    assert_eq!(pipe_pointer.page, 0);
+
+    for area in rom.areas()? {
+        let level = rom.get_area(&area)?;
+        for pipe_pointer in &level.enemy_data.pipe_pointers {
+            let destination = rom.pipe_destination(pipe_pointer)?;
+            rom.get_area(&destination)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_area_grid() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+    let area = rom.level_area(&RomLevel::W1_1)?;
+    let grid = rom.area_grid(&area)?;
+
+    // the first pipe
+    assert_eq!(grid.get(28, 9), Some(0x12));
+    assert_eq!(grid.get(29, 10), Some(0x15));
+
+    // the flagpole
+    assert_eq!(grid.get(198, 0), Some(0x24));
+    assert_eq!(grid.get(198, 10), Some(0x61));
+
+    for area in rom.areas()? {
+        let grid = rom.area_grid(&area)?;
+        assert_eq!(grid.width() % 16, 0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_pattern_tables() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+    let [sprites, background] = rom.pattern_tables()?;
+    assert_eq!(sprites.tiles.len(), PATTERN_TABLE_TILES);
+    assert_eq!([sprites.to_bytes(), background.to_bytes()].concat(), rom.chr());
+
+    let image = background.to_image(&GRAYSCALE_TILE_PALETTE);
+    assert_eq!(image.dimensions(), (128, 128));
+
+    Ok(())
+}
+
+#[test]
+fn test_palettes() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+
+    let area = rom.level_area(&RomLevel::W1_1)?;
+    let palettes = rom.area_palettes(&area)?;
+    assert_eq!(palettes.background_color(), 0x22);
+    assert_eq!(palettes.background(0), [0x22, 0x29, 0x1a, 0x0f]);
+    assert_eq!(palettes.sprite(0), [0x22, 0x16, 0x27, 0x18]);
+
+    let palettes =
+        rom.palettes(AreaType::Underground, LevelBackground::DayTime)?;
+    assert_eq!(palettes.background(0), [0x0f, 0x29, 0x1a, 0x09]);
+
+    let palettes =
+        rom.palettes(AreaType::Ground, LevelBackground::NightTime)?;
+    assert_eq!(palettes.background_color(), 0x0f);
+
+    let palettes =
+        rom.palettes(AreaType::Ground, LevelBackground::DayTimeSnow)?;
+    assert_eq!(palettes.background(0), [0x22, 0x30, 0x00, 0x10]);
+
+    Ok(())
+}
+
+#[test]
+fn test_render_area() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+    let area = rom.level_area(&RomLevel::W1_1)?;
+    let grid = rom.area_grid(&area)?;
+    let image = rom.render_area(&area)?;
+    assert_eq!(image.width() as usize, grid.width() * METATILE_PIXELS);
+    assert_eq!(image.height() as usize, GRID_ROWS * METATILE_PIXELS);
+
+    // the sky is the background color
+    let sky = NesPalette::default().rgba(0x22);
+    assert_eq!(image.get_pixel(0, 0), &sky);
+
+    Ok(())
+}
+
+#[test]
+fn test_level_display() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+    let area = rom.level_area(&RomLevel::W1_1)?;
+    let level = rom.get_area(&area)?;
+    let grid = rom.area_grid(&area)?;
+
+    let text = level.display(area.area_type).to_string();
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines.len(), 1 + GRID_ROWS);
+    assert!(lines[1..].iter().all(|line| line.len() == grid.width()));
+    // the first pipe and the first goomba
+    assert_eq!(&lines[10][28..30], "TT");
+    assert!(lines[11].contains('G'));
+
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_level_text_round_trip() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+    for area in rom.areas()? {
+        let level = rom.get_area(&area)?;
+        level.validate()?;
+
+        let json = Level::from_json(&level.to_json()?)?;
+        assert_eq!(json.area_data_to_bytes(), level.area_data_to_bytes());
+        assert_eq!(json.enemy_data_to_bytes(), level.enemy_data_to_bytes());
+
+        let ron = Level::from_ron(&level.to_ron()?)?;
+        assert_eq!(ron.area_data_to_bytes(), level.area_data_to_bytes());
+        assert_eq!(ron.enemy_data_to_bytes(), level.enemy_data_to_bytes());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_ips_patch() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+    let mut modified = Rom::new(ROM_DATA.into())?;
+
+    // move the first enemy of 1-1 over by a column
+    let mut level = modified.get_level(&RomLevel::W1_1)?;
+    level.enemy_data.enemies[0].x_coordinate ^= 1;
+    modified.set_level(&RomLevel::W1_1, &level)?;
+
+    let patch = IpsPatch::from_bytes(&rom.create_ips(&modified)?.to_bytes());
+    assert_eq!(patch.records.len(), 1);
+
+    let patched = rom.apply_ips(&patch)?;
+    assert_eq!(patched.rom_data, modified.rom_data);
+    assert!(!patched.failed_checks().is_empty());
+
+    // a patch can't turn the rom into some other cartridge
+    let patch = IpsPatch {
+        records: vec![IpsRecord::Data { offset: 4, data: vec![4] }],
+        truncate: None,
+    };
+    assert!(rom.apply_ips(&patch).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_bps_patch() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+    let mut modified = Rom::new(ROM_DATA.into())?;
+
+    // swap the level data of 1-1 and 1-2
+    let level_1 = modified.get_level(&RomLevel::W1_1)?;
+    let level_2 = modified.get_level(&RomLevel::W1_2)?;
+    modified.set_level(&RomLevel::W1_1, &level_2)?;
+    modified.set_level(&RomLevel::W1_2, &level_1)?;
+
+    let patch = BpsPatch::from_bytes(&rom.create_bps(&modified).to_bytes());
+    let patched = rom.apply_bps(&patch)?;
+    assert_eq!(patched.rom_data, modified.rom_data);
+
+    // the patch only applies to the rom it was made from
+    assert!(patched.apply_bps(&patch).is_err());
+
+    // patches made for a headerless copy of the dump apply too
+    let headerless = |rom: &Rom| [rom.prg(), rom.chr()].concat();
+    let patch = BpsPatch::diff(&headerless(&rom), &headerless(&modified));
+    assert_eq!(patch.source_profile(), rom.profile());
+    let patched = rom.apply_bps(&patch)?;
+    assert_eq!(patched.rom_data, modified.rom_data);
+    assert!(patched.apply_bps(&patch).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_disassembly() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+    let disasm = rom.disassemble()?;
+
+    assert!(disasm.label_addr("JumpEngine").is_some());
+    assert!(disasm.label_addr("Start").is_some());
+    assert!(disasm.label_addr("NonMaskableInterrupt").is_some());
+    for table in KNOWN_JUMP_TABLES {
+        assert!(disasm.label_addr(table.caller).is_some());
+    }
+
+    // every object and enemy is drawn and set up by some routine
+    let level = rom.get_level(&RomLevel::W1_1)?;
+    for object in &level.object_data.objects {
+        if !matches!(object.kind, LevelObjectKind::PageSkip(_)) {
+            assert!(disasm.area_object_routine(object).is_some());
+        }
+    }
+    let goomba = disasm.enemy_init_routine(&LevelEnemyKind::Goomba).unwrap();
+    assert_eq!(disasm.label(goomba), Some("InitGoomba"));
+    assert!(!disasm.references(goomba).is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_area_loader() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+
+    // the game's own area parser draws the same metatiles as `LevelGrid`
+    for area in rom.areas()? {
+        let grid = rom.area_grid(&area)?;
+        let loaded = rom.run_area_loader(&area, grid.width())?;
+        assert_eq!(loaded, grid, "{:?}", area);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_music() -> Result<()> {
+    let rom = Rom::new(ROM_DATA.into())?;
+    let songs = rom.songs()?;
+    assert_eq!(songs.len(), SONGS.len());
+
+    // square 2 leads every part, the ground music has a lead-in part
+    for song in &songs {
+        assert!(song.parts.iter().all(|part| !part.square2.is_empty()));
+        assert!(song.to_midi().starts_with(b"MThd"));
+    }
+    let ground = songs.iter().find(|song| song.name == "ground").unwrap();
+    assert_eq!(ground.parts.len(), 33);
+    assert_eq!(ground.loop_part, Some(1));
+
+    Ok(())
+}
+
+#[test]
+fn test_set_song() -> Result<()> {
+    let mut rom = Rom::new(ROM_DATA.into())?;
+
+    // unchanged songs leave the rom as it is
+    let original = rom.rom_data.clone();
+    for info in SONGS {
+        let song = rom.song(info)?;
+        rom.set_song(info, &song)?;
+        assert_eq!(rom.rom_data, original);
+    }
+
+    // exported songs import back with the same notes
+    let water = SONGS.iter().find(|info| info.name == "water").unwrap();
+    let song = rom.song(water)?;
+    let imported = rom.song_from_midi(water, &song.to_midi())?;
+    assert_eq!(imported.frames(), song.frames());
+
+    Ok(())
+}
+
+#[test]
+fn test_text_catalog() -> Result<()> {
+    let mut rom = Rom::new(ROM_DATA.into())?;
+    let catalog = rom.text_catalog()?;
+
+    let text = |name| {
+        let text = catalog.iter().find(|text| text.name == name).unwrap();
+        text.text()
+    };
+    assert_eq!(text("player-name"), "MARIO");
+    assert_eq!(text("world-time"), "WORLD  TIME");
+    assert_eq!(text("luigi-name"), "LUIGI");
+    assert_eq!(text("mario-thanks"), "THANK YOU MARIO!");
+    assert_eq!(text("retainer-1"), "BUT OUR PRINCESS IS IN");
+    assert_eq!(text("retainer-2"), "ANOTHER CASTLE!");
+    assert_eq!(text("princess-saved-1"), "YOUR QUEST IS OVER.");
+    assert_eq!(text("copyright"), "©1985 NINTENDO");
+    assert_eq!(text("one-player-game"), "1 PLAYER GAME");
+    assert_eq!(text("two-player-game"), "2 PLAYER GAME");
+
+    let info = &TEXT_CATALOG[0];
+    rom.set_text(info, "LINK")?;
+    assert_eq!(rom.text(info)?.text(), "LINK ");
+    assert!(rom.set_text(info, "PRINCESS").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_title_screen() -> Result<()> {
+    let mut rom = Rom::new(ROM_DATA.into())?;
+    let title_screen = rom.title_screen()?;
+
+    let rows: Vec<String> = (0..NAMETABLE_ROWS)
+        .map(|y| {
+            let tiles: Vec<u8> = (0..NAMETABLE_COLUMNS)
+                .map(|x| title_screen.tile(x, y).unwrap_or(BLANK_TILE))
+                .collect();
+            decode_text(&tiles)
+        })
+        .collect();
+    assert!(rows.iter().any(|row| row.contains("1 PLAYER GAME")));
+    assert!(rows.iter().any(|row| row.contains("2 PLAYER GAME")));
+
+    assert!(title_screen.to_bytes().len() <= TITLE_SCREEN_SIZE);
+    rom.set_title_screen(&title_screen)?;
+    assert_eq!(rom.title_screen()?, title_screen);
+
+    let image = rom.render_title_screen()?;
+    assert_eq!(image.dimensions(), (256, 240));
+
+    Ok(())
+}
+
+#[test]
+fn test_demo() -> Result<()> {
+    let mut rom = Rom::new(ROM_DATA.into())?;
+    let tables = rom.demo_tables()?;
+    let demo = rom.demo()?;
+    assert_eq!(demo.actions.len(), tables.capacity());
+    assert_eq!(
+        demo.actions[0],
+        DemoAction { buttons: BUTTON_RIGHT, frames: 0x9b }
+    );
+
+    let fm2 = demo.to_fm2(&rom);
+    assert_eq!(Demo::from_fm2(&fm2), demo);
+
+    let edited = Demo::from_inputs(&[BUTTON_RIGHT | BUTTON_B; 600]);
+    rom.set_demo(&edited)?;
+    assert_eq!(rom.demo()?, edited);
+
+    let too_long: Vec<u8> = (0..=tables.capacity() as u8).collect();
+    assert!(rom.set_demo(&Demo::from_inputs(&too_long)).is_err());
+
+    Ok(())
+}